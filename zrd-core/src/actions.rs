@@ -1,5 +1,8 @@
 //! Platform-agnostic editor actions
 
+use crate::LineEnding;
+use std::time::Duration;
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum EditorAction {
     // Text manipulation
@@ -8,6 +11,7 @@ pub enum EditorAction {
     Backspace,
     Delete,
     Newline,
+    /// Insert text as one undo step, such as text pasted into the terminal
     Paste(String),
 
     // Cursor movement
@@ -29,11 +33,29 @@ pub enum EditorAction {
     SelectWordRight,
     SelectAll,
 
+    // Multiple cursors
+    AddCursorAbove,
+    AddCursorBelow,
+    SelectNextOccurrence,
+
+    // Block (rectangular) selection
+    BlockSelectLeft,
+    BlockSelectRight,
+    BlockSelectUp,
+    BlockSelectDown,
+
     // Editing operations
     Undo,
     Redo,
+    /// Put the selected text on the clipboard and delete it
     Cut,
+    /// Put the selected text on the clipboard
     Copy,
+    /// Paste the clipboard's text
+    PasteFromClipboard,
+    /// Paste the newest kill ring entry, or right after a paste, replace
+    /// it with the entry before
+    PasteFromHistory,
     DeleteLine,
     DeleteToBeginningOfLine,
     DeleteToEndOfLine,
@@ -43,6 +65,33 @@ pub enum EditorAction {
     MoveLineDown,
     Tab,
     Outdent,
+    ConvertLineEndings(LineEnding),
+
+    // Find, among the matches of `EditorEngine::set_search`
+    /// Select the next match, wrapping around
+    FindNext,
+    /// Select the previous match, wrapping around
+    FindPrevious,
+
+    // Bookmarks
+    /// Bookmark the primary cursor's line, or remove its bookmark
+    ToggleBookmark,
+    /// Move to the next bookmarked line, wrapping around
+    NextBookmark,
+    /// Move to the previous bookmarked line, wrapping around
+    PreviousBookmark,
+
+    // Undo history
+    /// Go back to the state as it was this long before the current one
+    UndoEarlier(Duration),
+    /// Go forward to the newest state at most this long after the current one
+    RedoLater(Duration),
+    /// Switch to the branch of undo history made before the current one
+    PreviousUndoBranch,
+    /// Switch to the branch of undo history made after the current one
+    NextUndoBranch,
+    /// Restore a state from `UndoHistory::entries`
+    GoToUndoState(usize),
 
     // View operations
     IncreaseFontSize,
@@ -53,7 +102,21 @@ pub enum EditorAction {
     Quit,
 
     // Mouse-driven cursor positioning
-    SetCursorPosition { row: usize, column: usize },
-    StartSelection { row: usize, column: usize },
-    ExtendSelection { row: usize, column: usize },
+    SetCursorPosition {
+        row: usize,
+        column: usize,
+    },
+    StartSelection {
+        row: usize,
+        column: usize,
+    },
+    ExtendSelection {
+        row: usize,
+        column: usize,
+    },
+    // Alt+drag; the cell may lie past the end of the line
+    ExtendBlockSelection {
+        row: usize,
+        cell: usize,
+    },
 }
@@ -0,0 +1,104 @@
+//! Anchors: positions that follow the text around them as it is edited
+//!
+//! An anchor moves with insertions and deletions before it. Text inserted
+//! exactly at an anchor goes after it with left gravity and before it with
+//! right gravity. Deleting text that spans an anchor invalidates it, since
+//! what it marked is gone; undoing the deletion does not bring it back.
+
+use crate::{BufferPosition, TextEdit};
+use std::collections::BTreeMap;
+
+/// Which side of an insertion at an anchor it stays on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Gravity {
+    /// Stay before text inserted at the anchor
+    #[default]
+    Left,
+    /// Move after text inserted at the anchor
+    Right,
+}
+
+/// Handle to an anchor created by `EditorEngine::create_anchor`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AnchorId(usize);
+
+#[derive(Debug, Clone, Copy)]
+struct Anchor {
+    /// `None` once the text around the anchor was deleted
+    position: Option<BufferPosition>,
+    gravity: Gravity,
+}
+
+/// The anchors of a document
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Anchors {
+    anchors: BTreeMap<AnchorId, Anchor>,
+    next_id: usize,
+}
+
+impl Anchors {
+    pub fn insert(&mut self, position: BufferPosition, gravity: Gravity) -> AnchorId {
+        let id = AnchorId(self.next_id);
+        self.next_id += 1;
+        self.anchors.insert(
+            id,
+            Anchor {
+                position: Some(position),
+                gravity,
+            },
+        );
+        id
+    }
+
+    pub fn remove(&mut self, id: AnchorId) -> bool {
+        self.anchors.remove(&id).is_some()
+    }
+
+    /// Where an anchor is now, or `None` if it was removed or invalidated
+    pub fn get(&self, id: AnchorId) -> Option<BufferPosition> {
+        self.anchors.get(&id).and_then(|anchor| anchor.position)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.anchors.is_empty()
+    }
+
+    /// Move every anchor past an edit
+    pub fn apply(&mut self, edit: &TextEdit) {
+        for anchor in self.anchors.values_mut() {
+            anchor.position = anchor
+                .position
+                .and_then(|position| transform(position, anchor.gravity, edit));
+        }
+    }
+}
+
+/// Where `position` is after `edit`, or `None` if the edit deleted the text
+/// on both sides of it
+pub fn transform(
+    position: BufferPosition,
+    gravity: Gravity,
+    edit: &TextEdit,
+) -> Option<BufferPosition> {
+    if position < edit.start {
+        return Some(position);
+    }
+    if position == edit.start && (edit.start != edit.old_end || gravity == Gravity::Left) {
+        return Some(position);
+    }
+    if position < edit.old_end {
+        return None;
+    }
+    // At or after the end of the replaced text: keep the distance from it
+    if position.row == edit.old_end.row {
+        Some(BufferPosition::new(
+            edit.new_end.row,
+            edit.new_end.column + position.column - edit.old_end.column,
+        ))
+    } else {
+        Some(BufferPosition::new(
+            position.row - edit.old_end.row + edit.new_end.row,
+            position.column,
+        ))
+    }
+}
@@ -0,0 +1,179 @@
+//! Saving documents safely and without stalling on every keystroke
+//!
+//! Files are written to a temporary file beside them and renamed over the
+//! original, so a crash mid-write leaves either the old contents or the new.
+//! `Autosave` waits for a pause in editing before saving, so that a burst of
+//! edits is written once.
+
+use crate::EditorEngine;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+/// How long after the latest change a save waits for more
+pub const DEFAULT_DELAY: Duration = Duration::from_millis(300);
+
+/// Longest a change goes unsaved while edits keep coming
+pub const MAX_DELAY: Duration = Duration::from_secs(2);
+
+/// How long after a failed save the next attempt waits
+pub const RETRY_DELAY: Duration = Duration::from_secs(5);
+
+/// Replace the file at `path` with what `write` writes, atomically. A
+/// symlink's target is replaced rather than the link, and the file keeps
+/// its permissions. With `sync`, the data is on disk before this returns.
+pub fn write_atomically(
+    path: &Path,
+    sync: bool,
+    write: impl FnOnce(&mut dyn Write) -> io::Result<()>,
+) -> io::Result<()> {
+    let target = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let name = target
+        .file_name()
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "not a file path"))?;
+    let dir = match target.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    // Unique per process, so two editors saving at once don't collide
+    let temp = dir.join(format!(
+        ".{}.{}.tmp",
+        name.to_string_lossy(),
+        std::process::id()
+    ));
+
+    let result = (|| {
+        let file = File::create(&temp)?;
+        if let Ok(metadata) = fs::metadata(&target) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        let mut writer = BufWriter::new(file);
+        write(&mut writer)?;
+        let file = writer.into_inner().map_err(|err| err.into_error())?;
+        if sync {
+            file.sync_all()?;
+        }
+        drop(file);
+        fs::rename(&temp, &target)?;
+        if sync {
+            sync_dir(dir)?;
+        }
+        Ok(())
+    })();
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+
+/// Make a rename in `dir` durable
+#[cfg(unix)]
+fn sync_dir(dir: &Path) -> io::Result<()> {
+    File::open(dir)?.sync_all()
+}
+
+#[cfg(not(unix))]
+fn sync_dir(_dir: &Path) -> io::Result<()> {
+    Ok(())
+}
+
+/// Saves a document a moment after it changes rather than on every edit
+#[derive(Debug, Clone)]
+pub struct Autosave {
+    path: PathBuf,
+    delay: Duration,
+    /// When the first and the latest unsaved changes were made
+    pending: Option<(Instant, Instant)>,
+    /// No sooner than this after a failed save
+    retry_at: Option<Instant>,
+    /// Whether the last save may not have reached the disk yet
+    unsynced: bool,
+}
+
+impl Autosave {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self {
+            path: path.into(),
+            delay: DEFAULT_DELAY,
+            pending: None,
+            retry_at: None,
+            unsynced: false,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Set how long to wait after the latest change, `DEFAULT_DELAY` by
+    /// default
+    pub fn set_delay(&mut self, delay: Duration) {
+        self.delay = delay;
+    }
+
+    /// Note that `engine`'s document changed and needs saving. Nothing is
+    /// scheduled while the engine refuses to save.
+    pub fn schedule(&mut self, engine: &EditorEngine) {
+        if engine.is_save_blocked() {
+            return;
+        }
+        let now = Instant::now();
+        let first = self.pending.map_or(now, |(first, _)| first);
+        self.pending = Some((first, now));
+    }
+
+    /// Whether there are changes not yet saved
+    pub fn is_pending(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    /// When the pending save is due, for frontends to wake up at
+    pub fn due_at(&self) -> Option<Instant> {
+        let (first, latest) = self.pending?;
+        let due = (latest + self.delay).min(first + MAX_DELAY.max(self.delay));
+        Some(self.retry_at.map_or(due, |retry_at| due.max(retry_at)))
+    }
+
+    /// Save if the pending save is due. Returns whether the file was
+    /// written.
+    pub fn save_if_due(&mut self, engine: &EditorEngine) -> io::Result<bool> {
+        match self.due_at() {
+            Some(due) if Instant::now() >= due => self.save(engine, false).map(|()| true),
+            _ => Ok(false),
+        }
+    }
+
+    /// Save now if anything is pending, as when quitting. With `sync`, also
+    /// make sure earlier saves have reached the disk. Returns whether the
+    /// file was written.
+    pub fn flush(&mut self, engine: &EditorEngine, sync: bool) -> io::Result<bool> {
+        if self.pending.is_some() {
+            self.save(engine, sync)?;
+            return Ok(true);
+        }
+        if sync && self.unsynced {
+            File::open(&self.path)?.sync_all()?;
+            self.unsynced = false;
+        }
+        Ok(false)
+    }
+
+    fn save(&mut self, engine: &EditorEngine, sync: bool) -> io::Result<()> {
+        let result = if sync {
+            engine.save_to_file_synced(&self.path)
+        } else {
+            engine.save_to_file(&self.path)
+        };
+        match result {
+            Ok(()) => {
+                self.pending = None;
+                self.retry_at = None;
+                self.unsynced = !sync;
+            }
+            // Still pending, but not tried again on every check
+            Err(_) => self.retry_at = Some(Instant::now() + RETRY_DELAY),
+        }
+        result
+    }
+}
@@ -0,0 +1,130 @@
+//! Binary file detection and byte-level editing
+
+use crate::encoding::Encoding;
+
+/// Bytes shown per row of the hex view
+pub const BYTES_PER_ROW: usize = 16;
+
+/// Only the start of a file is inspected when sniffing for binary content
+const SAMPLE_LEN: usize = 8192;
+
+/// Guess whether file bytes are binary rather than text.
+///
+/// A file is binary if it contains NUL bytes, if more than 10% of it is
+/// control characters, or if more than 30% of it is invalid UTF-8 and it
+/// also contains control characters. Legacy 8-bit text is mostly invalid
+/// UTF-8 but has no control characters, so it is still treated as text.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    if bytes.is_empty() || Encoding::for_bom(bytes).is_some() {
+        return false;
+    }
+    let sample = &bytes[..bytes.len().min(SAMPLE_LEN)];
+    if sample.contains(&0) {
+        return true;
+    }
+
+    let control = sample.iter().filter(|&&b| is_control_byte(b)).count();
+    let invalid = invalid_utf8_len(sample);
+    control * 10 > sample.len() || (control > 0 && invalid * 10 > sample.len() * 3)
+}
+
+fn is_control_byte(b: u8) -> bool {
+    (b < 0x20 && !matches!(b, b'\t' | b'\n' | b'\r' | 0x0C | 0x1B)) || b == 0x7F
+}
+
+/// Number of bytes that are not part of a valid UTF-8 sequence
+fn invalid_utf8_len(mut bytes: &[u8]) -> usize {
+    let mut invalid = 0;
+    while let Err(err) = std::str::from_utf8(bytes) {
+        // A sequence cut off by the end of the sample is not counted
+        let Some(len) = err.error_len() else {
+            break;
+        };
+        invalid += len;
+        bytes = &bytes[err.valid_up_to() + len..];
+    }
+    invalid
+}
+
+/// A binary file opened for byte-level editing. Bytes can be overwritten
+/// but never inserted or removed, so the file size never changes.
+#[derive(Debug, Clone)]
+pub struct HexDocument {
+    bytes: Vec<u8>,
+    cursor: usize,
+    /// Set after the high nibble of the byte under the cursor was typed
+    low_nibble: bool,
+}
+
+impl HexDocument {
+    pub fn new(bytes: Vec<u8>) -> Self {
+        Self {
+            bytes,
+            cursor: 0,
+            low_nibble: false,
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    /// Byte offset of the cursor
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    /// Whether the next hex digit typed goes into the low nibble
+    pub fn is_editing_low_nibble(&self) -> bool {
+        self.low_nibble
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.bytes.len().div_ceil(BYTES_PER_ROW).max(1)
+    }
+
+    /// Bytes displayed on a row
+    pub fn row(&self, row: usize) -> &[u8] {
+        let start = (row * BYTES_PER_ROW).min(self.bytes.len());
+        let end = (start + BYTES_PER_ROW).min(self.bytes.len());
+        &self.bytes[start..end]
+    }
+
+    pub fn set_cursor(&mut self, offset: usize) {
+        self.cursor = offset.min(self.bytes.len().saturating_sub(1));
+        self.low_nibble = false;
+    }
+
+    /// Move the cursor by a number of bytes, clamping to the file
+    pub fn move_by(&mut self, delta: isize) {
+        self.set_cursor(self.cursor.saturating_add_signed(delta));
+    }
+
+    pub fn move_to_row_start(&mut self) {
+        self.set_cursor(self.cursor - self.cursor % BYTES_PER_ROW);
+    }
+
+    pub fn move_to_row_end(&mut self) {
+        self.set_cursor(self.cursor - self.cursor % BYTES_PER_ROW + BYTES_PER_ROW - 1);
+    }
+
+    /// Overwrite one nibble of the byte under the cursor. Typing the low
+    /// nibble advances to the next byte. Returns false for non-hex input.
+    pub fn type_hex_digit(&mut self, c: char) -> bool {
+        let Some(value) = c.to_digit(16) else {
+            return false;
+        };
+        let Some(byte) = self.bytes.get_mut(self.cursor) else {
+            return false;
+        };
+        let value = value as u8;
+        if self.low_nibble {
+            *byte = (*byte & 0xF0) | value;
+            self.move_by(1);
+        } else {
+            *byte = (*byte & 0x0F) | (value << 4);
+            self.low_nibble = true;
+        }
+        true
+    }
+}
@@ -0,0 +1,55 @@
+//! Bookmarks kept between sessions
+//!
+//! Each document's bookmarked rows are saved as text in a sidecar file named
+//! like its undo history sidecar, in a directory such as
+//! `~/.config/zrd/bookmarks/`. The first line is the document's path and
+//! each further line a row number.
+
+use crate::autosave;
+use crate::undo_file::{document_key, sidecar_path};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+const EXTENSION: &str = "bookmarks";
+
+/// Save the bookmarked rows of the document at `path`, with `sync` as for
+/// `autosave::write_atomically`. The sidecar is removed when there are none.
+pub fn save(dir: &Path, path: &Path, rows: &[usize], sync: bool) -> io::Result<()> {
+    let key = document_key(path);
+    let sidecar = sidecar_path(dir, &key, EXTENSION);
+    if rows.is_empty() {
+        return match fs::remove_file(sidecar) {
+            Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        };
+    }
+
+    let mut contents = key;
+    for row in rows {
+        contents.push('\n');
+        contents.push_str(&row.to_string());
+    }
+    contents.push('\n');
+    // Frontends save after every action; leave the file alone if unchanged
+    if fs::read_to_string(&sidecar).is_ok_and(|saved| saved == contents) {
+        return Ok(());
+    }
+
+    fs::create_dir_all(dir)?;
+    autosave::write_atomically(&sidecar, sync, |out| out.write_all(contents.as_bytes()))
+}
+
+/// The saved bookmarked rows of the document at `path`. Lines that are not
+/// row numbers are skipped.
+pub fn load(dir: &Path, path: &Path) -> Vec<usize> {
+    let key = document_key(path);
+    let Ok(contents) = fs::read_to_string(sidecar_path(dir, &key, EXTENSION)) else {
+        return Vec::new();
+    };
+    let mut lines = contents.lines();
+    if lines.next() != Some(key.as_str()) {
+        return Vec::new();
+    }
+    lines.filter_map(|line| line.parse().ok()).collect()
+}
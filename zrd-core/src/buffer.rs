@@ -0,0 +1,299 @@
+//! Rope-backed text storage
+//!
+//! Positions are addressed as (row, byte column) pairs, matching
+//! `BufferPosition`. Only `\n` is treated as a line break; a `\r` before
+//! it is kept in storage so files round-trip byte for byte, but is hidden
+//! from line views and never addressable by a column.
+//!
+//! Every position passed in is clamped to the buffer and snapped back to a
+//! character boundary, so a stale or out-of-range column can never split a
+//! UTF-8 sequence.
+
+use crate::BufferPosition;
+use ropey::Rope;
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+
+#[derive(Clone, Default)]
+pub struct Buffer {
+    rope: Rope,
+}
+
+impl Buffer {
+    pub fn new() -> Self {
+        Self { rope: Rope::new() }
+    }
+
+    /// Build a buffer by streaming UTF-8 text from a reader
+    pub fn from_reader<R: io::Read>(reader: R) -> io::Result<Self> {
+        Ok(Self {
+            rope: Rope::from_reader(reader)?,
+        })
+    }
+
+    /// Stream the buffer contents to a writer without building a `String`
+    pub fn write_to<W: io::Write>(&self, writer: W) -> io::Result<()> {
+        self.rope.write_to(writer)
+    }
+
+    /// Total length of the text in bytes
+    pub fn len_bytes(&self) -> usize {
+        self.rope.len_bytes()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rope.len_bytes() == 0
+    }
+
+    /// Number of lines; an empty buffer has one empty line
+    pub fn line_count(&self) -> usize {
+        self.rope.len_lines()
+    }
+
+    /// Text of a line without its line ending.
+    /// Borrowed when the line is stored contiguously.
+    pub fn line(&self, row: usize) -> Option<Cow<'_, str>> {
+        if row >= self.line_count() {
+            return None;
+        }
+        let start = self.rope.line_to_byte(row);
+        let end = start + self.line_len(row);
+        Some(self.rope.byte_slice(start..end).into())
+    }
+
+    /// Length of a line in bytes, excluding the line ending
+    pub fn line_len(&self, row: usize) -> usize {
+        if row >= self.line_count() {
+            return 0;
+        }
+        let start = self.rope.line_to_byte(row);
+        let end = self.rope.line_to_byte(row + 1);
+        end - start - self.line_ending_len(row)
+    }
+
+    /// Bytes taken by the line ending of a row: 2 for `\r\n`, 1 for `\n`,
+    /// and 0 for the last line
+    fn line_ending_len(&self, row: usize) -> usize {
+        if row + 1 >= self.line_count() {
+            return 0;
+        }
+        let start = self.rope.line_to_byte(row);
+        let end = self.rope.line_to_byte(row + 1);
+        if end - start >= 2 && self.rope.byte(end - 2) == b'\r' {
+            2
+        } else {
+            1
+        }
+    }
+
+    /// Whether the text ends with a line break
+    pub fn has_trailing_newline(&self) -> bool {
+        let len = self.len_bytes();
+        len > 0 && self.rope.byte(len - 1) == b'\n'
+    }
+
+    /// Iterate over all lines, without line endings
+    pub fn lines(&self) -> impl Iterator<Item = Cow<'_, str>> + '_ {
+        (0..self.line_count()).filter_map(move |row| self.line(row))
+    }
+
+    /// Column where the grapheme cluster ending at `column` starts,
+    /// or 0 at the start of the line
+    pub fn prev_grapheme_column(&self, row: usize, column: usize) -> usize {
+        let line = self.line(row).unwrap_or_default();
+        let column = column.min(line.len());
+        GraphemeCursor::new(column, line.len(), true)
+            .prev_boundary(&line, 0)
+            .ok()
+            .flatten()
+            .unwrap_or(0)
+    }
+
+    /// Column where the grapheme cluster starting at `column` ends,
+    /// or the line length at the end of the line
+    pub fn next_grapheme_column(&self, row: usize, column: usize) -> usize {
+        let line = self.line(row).unwrap_or_default();
+        let column = column.min(line.len());
+        GraphemeCursor::new(column, line.len(), true)
+            .next_boundary(&line, 0)
+            .ok()
+            .flatten()
+            .unwrap_or(line.len())
+    }
+
+    /// Clamp a position to the buffer bounds, moving a column that falls
+    /// inside a multibyte character back to the start of that character
+    pub fn clamp_position(&self, pos: BufferPosition) -> BufferPosition {
+        let row = pos.row.min(self.line_count().saturating_sub(1));
+        let line_start = self.rope.line_to_byte(row);
+        let byte = line_start + pos.column.min(self.line_len(row));
+        let byte = self.rope.char_to_byte(self.rope.byte_to_char(byte));
+        BufferPosition::new(row, byte - line_start)
+    }
+
+    /// Convert a position to an absolute byte offset
+    pub fn position_to_byte(&self, pos: BufferPosition) -> usize {
+        let pos = self.clamp_position(pos);
+        self.rope.line_to_byte(pos.row) + pos.column
+    }
+
+    /// Convert an absolute byte offset to a position
+    pub fn byte_to_position(&self, byte: usize) -> BufferPosition {
+        let byte = byte.min(self.len_bytes());
+        let row = self.rope.byte_to_line(byte);
+        self.clamp_position(BufferPosition::new(row, byte - self.rope.line_to_byte(row)))
+    }
+
+    /// Convert a position to an absolute char (Unicode scalar value) index
+    pub fn position_to_char(&self, pos: BufferPosition) -> usize {
+        self.rope.byte_to_char(self.position_to_byte(pos))
+    }
+
+    /// Convert an absolute char index to a position
+    pub fn char_to_position(&self, char_idx: usize) -> BufferPosition {
+        let char_idx = char_idx.min(self.rope.len_chars());
+        self.byte_to_position(self.rope.char_to_byte(char_idx))
+    }
+
+    /// Convert a position to an absolute offset in UTF-16 code units
+    pub fn position_to_utf16(&self, pos: BufferPosition) -> usize {
+        self.rope.char_to_utf16_cu(self.position_to_char(pos))
+    }
+
+    /// Convert an absolute offset in UTF-16 code units to a position.
+    /// An offset between the halves of a surrogate pair maps to the
+    /// start of that character.
+    pub fn utf16_to_position(&self, utf16_idx: usize) -> BufferPosition {
+        let utf16_idx = utf16_idx.min(self.rope.len_utf16_cu());
+        self.char_to_position(self.rope.utf16_cu_to_char(utf16_idx))
+    }
+
+    /// Number of chars between the start of the line and a position
+    pub fn char_column(&self, pos: BufferPosition) -> usize {
+        let pos = self.clamp_position(pos);
+        self.position_to_char(pos) - self.position_to_char(BufferPosition::new(pos.row, 0))
+    }
+
+    /// Position a number of chars into a line, clamped to the line end
+    pub fn position_from_char_column(&self, row: usize, chars: usize) -> BufferPosition {
+        let line = self.line(row).unwrap_or_default();
+        let column = line
+            .char_indices()
+            .nth(chars)
+            .map_or(line.len(), |(i, _)| i);
+        self.clamp_position(BufferPosition::new(row, column))
+    }
+
+    /// Number of UTF-16 code units between the start of the line and a
+    /// position, as used by LSP's default position encoding
+    pub fn utf16_column(&self, pos: BufferPosition) -> usize {
+        let pos = self.clamp_position(pos);
+        self.position_to_utf16(pos) - self.position_to_utf16(BufferPosition::new(pos.row, 0))
+    }
+
+    /// Position a number of UTF-16 code units into a line, clamped to the line end
+    pub fn position_from_utf16_column(&self, row: usize, units: usize) -> BufferPosition {
+        let line = self.line(row).unwrap_or_default();
+        let mut remaining = units;
+        let mut column = line.len();
+        for (i, c) in line.char_indices() {
+            if remaining < c.len_utf16() {
+                column = i;
+                break;
+            }
+            remaining -= c.len_utf16();
+        }
+        self.clamp_position(BufferPosition::new(row, column))
+    }
+
+    /// Number of grapheme clusters between the start of the line and a position
+    pub fn grapheme_column(&self, pos: BufferPosition) -> usize {
+        let pos = self.clamp_position(pos);
+        let line = self.line(pos.row).unwrap_or_default();
+        line.grapheme_indices(true)
+            .take_while(|(i, grapheme)| i + grapheme.len() <= pos.column)
+            .count()
+    }
+
+    /// Position a number of grapheme clusters into a line, clamped to the line end
+    pub fn position_from_grapheme_column(&self, row: usize, graphemes: usize) -> BufferPosition {
+        let line = self.line(row).unwrap_or_default();
+        let column = line
+            .grapheme_indices(true)
+            .nth(graphemes)
+            .map_or(line.len(), |(i, _)| i);
+        self.clamp_position(BufferPosition::new(row, column))
+    }
+
+    /// Insert text at a position, returning the position just after it
+    pub fn insert(&mut self, pos: BufferPosition, text: &str) -> BufferPosition {
+        let byte = self.position_to_byte(pos);
+        let char_idx = self.rope.byte_to_char(byte);
+        self.rope.insert(char_idx, text);
+        self.byte_to_position(byte + text.len())
+    }
+
+    /// Delete the text between two positions
+    pub fn delete(&mut self, start: BufferPosition, end: BufferPosition) {
+        let start = self.position_to_char(start);
+        let end = self.position_to_char(end);
+        if start < end {
+            self.rope.remove(start..end);
+        }
+    }
+
+    /// Replace the text between two positions, returning the end of the new text
+    pub fn replace(
+        &mut self,
+        start: BufferPosition,
+        end: BufferPosition,
+        text: &str,
+    ) -> BufferPosition {
+        self.delete(start, end);
+        self.insert(start, text)
+    }
+
+    /// Get the text between two positions
+    pub fn text_range(&self, start: BufferPosition, end: BufferPosition) -> String {
+        let start = self.position_to_char(start);
+        let end = self.position_to_char(end);
+        if start >= end {
+            return String::new();
+        }
+        self.rope.slice(start..end).to_string()
+    }
+}
+
+impl From<&str> for Buffer {
+    fn from(text: &str) -> Self {
+        Self {
+            rope: Rope::from_str(text),
+        }
+    }
+}
+
+impl From<String> for Buffer {
+    fn from(text: String) -> Self {
+        Self::from(text.as_str())
+    }
+}
+
+impl fmt::Display for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for chunk in self.rope.chunks() {
+            f.write_str(chunk)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Buffer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Buffer")
+            .field("len_bytes", &self.len_bytes())
+            .field("line_count", &self.line_count())
+            .finish()
+    }
+}
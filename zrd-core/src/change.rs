@@ -0,0 +1,55 @@
+//! Change events, so frontends and tools can follow edits without
+//! comparing whole documents
+
+use crate::undo::CursorState;
+use crate::BufferPosition;
+
+/// Text between `start` and `old_end` replaced by `text`, which now ends at
+/// `new_end`. Positions are as of the document just before this edit; for
+/// `new_end`, just after it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextEdit {
+    pub start: BufferPosition,
+    pub old_end: BufferPosition,
+    pub new_end: BufferPosition,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub(crate) fn insertion(start: BufferPosition, end: BufferPosition, text: &str) -> Self {
+        Self {
+            start,
+            old_end: start,
+            new_end: end,
+            text: text.to_string(),
+        }
+    }
+
+    pub(crate) fn deletion(start: BufferPosition, end: BufferPosition) -> Self {
+        Self {
+            start,
+            old_end: end,
+            new_end: start,
+            text: String::new(),
+        }
+    }
+
+    /// Rows the edit touched, as of the document before it
+    pub fn old_rows(&self) -> std::ops::RangeInclusive<usize> {
+        self.start.row..=self.old_end.row
+    }
+
+    /// Rows holding the new text, as of the document after the edit
+    pub fn new_rows(&self) -> std::ops::RangeInclusive<usize> {
+        self.start.row..=self.new_end.row
+    }
+}
+
+/// What one action or editing call changed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChangeEvent {
+    /// Edits in the order they were made; empty if only cursors moved
+    pub edits: Vec<TextEdit>,
+    pub cursors_before: CursorState,
+    pub cursors_after: CursorState,
+}
@@ -0,0 +1,83 @@
+//! Clipboards that cut and copied text goes to and pasted text comes from
+
+use std::io::{self, Write};
+
+/// A place to hold copied text
+pub trait Clipboard {
+    /// The text to paste, if there is any
+    fn read(&mut self) -> Option<String>;
+
+    fn write(&mut self, text: &str) -> io::Result<()>;
+}
+
+/// A clipboard of its own, for tests and for frontends without one
+#[derive(Debug, Clone, Default)]
+pub struct MemoryClipboard {
+    text: Option<String>,
+}
+
+impl MemoryClipboard {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clipboard for MemoryClipboard {
+    fn read(&mut self) -> Option<String> {
+        self.text.clone()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.text = Some(text.to_string());
+        Ok(())
+    }
+}
+
+/// The terminal's clipboard, set with the OSC 52 escape sequence so that
+/// copying works over SSH too. Terminals rarely allow reading it back, so
+/// pasting gives the text last copied here; text pasted with the
+/// terminal's own shortcut arrives as input instead.
+#[derive(Debug)]
+pub struct Osc52Clipboard<W: Write> {
+    out: W,
+    text: Option<String>,
+}
+
+impl<W: Write> Osc52Clipboard<W> {
+    /// Write escape sequences to `out`, normally the terminal's stdout
+    pub fn new(out: W) -> Self {
+        Self { out, text: None }
+    }
+}
+
+impl<W: Write> Clipboard for Osc52Clipboard<W> {
+    fn read(&mut self) -> Option<String> {
+        self.text.clone()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        write!(self.out, "\x1b]52;c;{}\x07", base64(text.as_bytes()))?;
+        self.out.flush()?;
+        self.text = Some(text.to_string());
+        Ok(())
+    }
+}
+
+/// Standard base64 with padding, as OSC 52 expects
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (i, &byte)| {
+            group | (byte as u32) << (16 - 8 * i)
+        });
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
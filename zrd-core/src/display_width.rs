@@ -0,0 +1,70 @@
+//! Mapping between byte columns and terminal cell columns
+//!
+//! Widths are measured per grapheme cluster, the same way terminal UIs lay
+//! out text: wide CJK characters and emoji take two cells, combining marks
+//! take none, and tabs expand to the next tab stop.
+
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Distance between tab stops, in cells
+pub const TAB_WIDTH: usize = 4;
+
+/// Cells taken by a grapheme cluster drawn at cell `cell`
+fn grapheme_width(grapheme: &str, cell: usize) -> usize {
+    if grapheme == "\t" {
+        TAB_WIDTH - cell % TAB_WIDTH
+    } else {
+        grapheme.width()
+    }
+}
+
+/// Cell at which the text before a byte column ends
+pub fn column_to_cell(line: &str, column: usize) -> usize {
+    let column = column.min(line.len());
+    line.grapheme_indices(true)
+        .take_while(|(start, _)| *start < column)
+        .fold(0, |cell, (_, grapheme)| {
+            cell + grapheme_width(grapheme, cell)
+        })
+}
+
+/// Byte column of the grapheme cluster drawn over a cell. Cells past the
+/// end of the line map to the line length.
+pub fn cell_to_column(line: &str, cell: usize) -> usize {
+    let mut current = 0;
+    for (start, grapheme) in line.grapheme_indices(true) {
+        let width = grapheme_width(grapheme, current);
+        if cell < current + width {
+            return start;
+        }
+        current += width;
+    }
+    line.len()
+}
+
+/// Total cells taken by a line
+pub fn line_width(line: &str) -> usize {
+    column_to_cell(line, line.len())
+}
+
+/// Replace tabs with spaces up to the next tab stop, for text that starts
+/// at cell `start_cell`
+pub fn expand_tabs(text: &str, start_cell: usize) -> Cow<'_, str> {
+    if !text.contains('\t') {
+        return Cow::Borrowed(text);
+    }
+    let mut expanded = String::with_capacity(text.len() + TAB_WIDTH);
+    let mut cell = start_cell;
+    for grapheme in text.graphemes(true) {
+        let width = grapheme_width(grapheme, cell);
+        if grapheme == "\t" {
+            expanded.extend(std::iter::repeat_n(' ', width));
+        } else {
+            expanded.push_str(grapheme);
+        }
+        cell += width;
+    }
+    Cow::Owned(expanded)
+}
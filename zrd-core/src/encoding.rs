@@ -0,0 +1,114 @@
+//! Text encoding detection for loading and saving files
+
+pub use encoding_rs::Encoding;
+use encoding_rs::{UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::io;
+
+/// Encoding a file was read with, so it can be written back the same way
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FileEncoding {
+    pub encoding: &'static Encoding,
+    /// Whether the file started with a byte order mark
+    pub bom: bool,
+}
+
+impl FileEncoding {
+    pub const UTF8: Self = Self {
+        encoding: UTF_8,
+        bom: false,
+    };
+
+    /// Legacy encoding used when a file has no BOM and is not valid UTF-8
+    pub const DEFAULT_FALLBACK: &'static Encoding = WINDOWS_1252;
+
+    pub fn name(&self) -> &'static str {
+        self.encoding.name()
+    }
+
+    /// Look up an encoding by a WHATWG label such as `latin1` or `shift_jis`
+    pub fn for_label(label: &str) -> Option<&'static Encoding> {
+        Encoding::for_label(label.trim().as_bytes())
+    }
+
+    /// Decode file bytes: BOM first, then strict UTF-8, then `fallback`.
+    /// Fails rather than substituting replacement characters.
+    pub fn decode(bytes: Vec<u8>, fallback: &'static Encoding) -> io::Result<(String, Self)> {
+        if let Some((encoding, bom_len)) = Encoding::for_bom(&bytes) {
+            let text = encoding
+                .decode_without_bom_handling_and_without_replacement(&bytes[bom_len..])
+                .ok_or_else(|| decode_error(encoding))?;
+            return Ok((
+                text.into_owned(),
+                Self {
+                    encoding,
+                    bom: true,
+                },
+            ));
+        }
+
+        let bytes = match String::from_utf8(bytes) {
+            Ok(text) => return Ok((text, Self::UTF8)),
+            Err(err) => err.into_bytes(),
+        };
+
+        let text = fallback
+            .decode_without_bom_handling_and_without_replacement(&bytes)
+            .ok_or_else(|| decode_error(fallback))?;
+        Ok((
+            text.into_owned(),
+            Self {
+                encoding: fallback,
+                bom: false,
+            },
+        ))
+    }
+
+    /// Encode text back to this encoding, including the BOM if there was one.
+    /// Fails if the text contains characters the encoding cannot represent.
+    pub fn encode(&self, text: &str) -> io::Result<Vec<u8>> {
+        let mut bytes = Vec::with_capacity(text.len() + 3);
+        if self.encoding == UTF_16LE || self.encoding == UTF_16BE {
+            if self.bom {
+                bytes.extend_from_slice(if self.encoding == UTF_16LE {
+                    &[0xFF, 0xFE]
+                } else {
+                    &[0xFE, 0xFF]
+                });
+            }
+            for unit in text.encode_utf16() {
+                if self.encoding == UTF_16LE {
+                    bytes.extend_from_slice(&unit.to_le_bytes());
+                } else {
+                    bytes.extend_from_slice(&unit.to_be_bytes());
+                }
+            }
+            return Ok(bytes);
+        }
+
+        if self.bom && self.encoding == UTF_8 {
+            bytes.extend_from_slice(&[0xEF, 0xBB, 0xBF]);
+        }
+        let (encoded, _, had_errors) = self.encoding.encode(text);
+        if had_errors {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("text cannot be represented in {}", self.name()),
+            ));
+        }
+        bytes.extend_from_slice(&encoded);
+        Ok(bytes)
+    }
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self::UTF8
+    }
+}
+
+fn decode_error(encoding: &'static Encoding) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("file is not valid {}", encoding.name()),
+    )
+}
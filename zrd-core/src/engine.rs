@@ -1,27 +1,239 @@
 //! Core editor engine with platform-agnostic business logic
 
-use crate::{BufferPosition, EditorAction, EditorState};
+use crate::anchor::Anchors;
+use crate::autosave;
+use crate::binary::{self, BYTES_PER_ROW};
+use crate::bookmark_file;
+use crate::clipboard::{Clipboard, MemoryClipboard};
+use crate::display_width;
+use crate::encoding::Encoding;
+use crate::kill_ring::KillRing;
+use crate::search::SearchQuery;
+use crate::undo::{CursorState, EditOperation, UndoHistory};
+use crate::undo_file;
+use crate::{
+    AnchorId, BlockSelection, Buffer, BufferPosition, ChangeEvent, EditorAction, EditorState,
+    FileEncoding, Gravity, HexDocument, LineEnding, Selection, TextEdit,
+};
 use std::fs;
 use std::io;
+use std::mem;
+use std::ops::RangeInclusive;
 use std::path::{Path, PathBuf};
 use std::time::{Duration, Instant};
 
 pub struct EditorEngine {
     state: EditorState,
-    undo_stack: Vec<EditorState>,
-    redo_stack: Vec<EditorState>,
+    history: UndoHistory,
     last_edit_time: Option<Instant>,
+    encoding: FileEncoding,
+    fallback_encoding: &'static Encoding,
+    save_blocked: bool,
+    hex: Option<HexDocument>,
+    /// Display column that vertical motion tries to return to
+    goal_column: Option<usize>,
+    /// Goal columns of `state.extra_selections`, in the same order
+    extra_goal_columns: Vec<Option<usize>>,
+    /// Rectangle the selections were laid out from, while it is being resized
+    block: Option<BlockSelection>,
+    /// Where undo history is kept between sessions, if anywhere
+    undo_dir: Option<PathBuf>,
+    /// Where bookmarks are kept between sessions, if anywhere
+    bookmark_dir: Option<PathBuf>,
+    /// Anchors at the start of bookmarked lines
+    bookmarks: Vec<AnchorId>,
+    /// Open transactions, innermost last
+    transactions: Vec<Transaction>,
+    /// Change events not yet taken, when they are being recorded
+    changes: Option<Vec<ChangeEvent>>,
+    /// Edits made by the action in progress, when changes are recorded or
+    /// anchors need to follow them
+    pending_edits: Vec<TextEdit>,
+    anchors: Anchors,
+    /// The find in progress, if any
+    search: Option<Search>,
+    /// Where cut and copied text goes, unless the frontend passes its own
+    clipboard: Box<dyn Clipboard>,
+    kill_ring: KillRing,
+    /// Where the kill ring is kept between sessions, if anywhere
+    kill_ring_file: Option<PathBuf>,
+    /// What the action in progress did that the next one may follow on from
+    streak: Streak,
+    /// What the previous action did, for joining kills and cycling pastes
+    previous_streak: Streak,
+    /// Kills made by each selection of a multi-cursor action, in the order
+    /// they ran, and whether they were backwards
+    selection_kills: Option<Vec<(String, bool)>>,
 }
 
+/// Where the undo history stood when a transaction began
+struct Transaction {
+    checkpoint: usize,
+    start: usize,
+    cursors: CursorState,
+}
+
+/// A query and its matches, kept up to date as the text changes
+struct Search {
+    query: SearchQuery,
+    matches: Vec<(BufferPosition, BufferPosition)>,
+    /// Where matches are looked for from as the query changes
+    origin: BufferPosition,
+    /// Anchors at the start and end of each selection the find started with
+    selections: Vec<(AnchorId, AnchorId)>,
+    /// Whether only matches inside `selections` count
+    in_selection: bool,
+}
+
+impl Search {
+    /// Matches in the lines `rows`, found as if they were all the text
+    fn find_in_rows(
+        &self,
+        buffer: &Buffer,
+        rows: RangeInclusive<usize>,
+    ) -> Vec<(BufferPosition, BufferPosition)> {
+        let line_start = |row: usize| {
+            if row < buffer.line_count() {
+                buffer.position_to_byte(BufferPosition::new(row, 0))
+            } else {
+                buffer.len_bytes()
+            }
+        };
+        let offset = line_start(*rows.start());
+        let end = line_start(rows.end() + 1);
+        let text = buffer.text_range(
+            buffer.byte_to_position(offset),
+            buffer.byte_to_position(end),
+        );
+        self.query
+            .find_in(&text)
+            .into_iter()
+            .map(|found| {
+                (
+                    buffer.byte_to_position(offset + found.start),
+                    buffer.byte_to_position(offset + found.end),
+                )
+            })
+            .collect()
+    }
+
+    /// Move the matches past `edits`, finding them again on the lines the
+    /// edits touched. Only right for queries whose matches can't span
+    /// lines.
+    fn follow_edits(&mut self, buffer: &Buffer, edits: &[TextEdit]) {
+        // Rows to find matches on again, first and last
+        let mut rows: Vec<(usize, usize)> = Vec::new();
+        for edit in edits {
+            let (first, last, new_last) = (edit.start.row, edit.old_end.row, edit.new_end.row);
+            // Rows after the edit move by the line breaks it added or removed
+            let moved = |row: usize| {
+                if row > last {
+                    row - last + new_last
+                } else {
+                    row
+                }
+            };
+            self.matches
+                .retain(|(start, _)| start.row < first || start.row > last);
+            for (start, end) in &mut self.matches {
+                start.row = moved(start.row);
+                end.row = moved(end.row);
+            }
+            for (from, to) in &mut rows {
+                *from = if (first..=last).contains(from) {
+                    first
+                } else {
+                    moved(*from)
+                };
+                *to = if (first..=last).contains(to) {
+                    new_last
+                } else {
+                    moved(*to)
+                };
+            }
+            rows.push((first, new_last));
+        }
+
+        rows.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (from, to) in rows {
+            match merged.last_mut() {
+                Some((_, end)) if from <= *end + 1 => *end = (*end).max(to),
+                _ => merged.push((from, to)),
+            }
+        }
+        for (from, to) in merged {
+            let found = self.find_in_rows(buffer, from..=to);
+            let at = self.matches.partition_point(|(start, _)| start.row < from);
+            self.matches.splice(at..at, found);
+        }
+    }
+
+    /// Drop matches outside the selections the find started with, if only
+    /// those count
+    fn keep_in_selections(&mut self, anchors: &Anchors) {
+        if !self.in_selection {
+            return;
+        }
+        let scope: Vec<_> = self
+            .selections
+            .iter()
+            .filter_map(|&(start, end)| Some((anchors.get(start)?, anchors.get(end)?)))
+            .collect();
+        self.matches
+            .retain(|&(start, end)| scope.iter().any(|&(from, to)| from <= start && end <= to));
+    }
+}
+
+/// Actions that carry on from the one before them
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+enum Streak {
+    #[default]
+    None,
+    /// Killed text, so that further kills join the same kill ring entry
+    Kill,
+    /// Pasted text between `start` and `end`, the kill ring entry at
+    /// `entry` if it came from there, which pasting from history replaces
+    Paste {
+        start: BufferPosition,
+        end: BufferPosition,
+        entry: Option<usize>,
+    },
+}
+
+/// A selection being processed by a multi-cursor action, with its goal
+/// column and whether it is the primary selection
+type SelectionSlot = (Selection, Option<usize>, bool);
+
 const UNDO_CHUNK_DURATION: Duration = Duration::from_millis(500);
 
 impl EditorEngine {
     pub fn new() -> Self {
         Self {
             state: EditorState::new(),
-            undo_stack: Vec::new(),
-            redo_stack: Vec::new(),
+            history: UndoHistory::new(),
             last_edit_time: None,
+            encoding: FileEncoding::default(),
+            fallback_encoding: FileEncoding::DEFAULT_FALLBACK,
+            save_blocked: false,
+            hex: None,
+            goal_column: None,
+            extra_goal_columns: Vec::new(),
+            block: None,
+            undo_dir: None,
+            bookmark_dir: None,
+            bookmarks: Vec::new(),
+            transactions: Vec::new(),
+            changes: None,
+            pending_edits: Vec::new(),
+            anchors: Anchors::default(),
+            search: None,
+            clipboard: Box::new(MemoryClipboard::new()),
+            kill_ring: KillRing::new(),
+            kill_ring_file: None,
+            streak: Streak::None,
+            previous_streak: Streak::None,
+            selection_kills: None,
         }
     }
 
@@ -33,6 +245,527 @@ impl EditorEngine {
         &mut self.state
     }
 
+    /// Encoding the current file was loaded with and will be saved with
+    pub fn encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+
+    /// Set the legacy encoding tried when a file is neither BOM-marked nor UTF-8
+    pub fn set_fallback_encoding(&mut self, encoding: &'static Encoding) {
+        self.fallback_encoding = encoding;
+    }
+
+    /// Whether saving is refused because the last load failed
+    pub fn is_save_blocked(&self) -> bool {
+        self.save_blocked
+    }
+
+    /// The open binary file, if the current file was detected as binary
+    pub fn hex_document(&self) -> Option<&HexDocument> {
+        self.hex.as_ref()
+    }
+
+    pub fn is_binary(&self) -> bool {
+        self.hex.is_some()
+    }
+
+    /// Display column (in cells) the cursor returns to when moving between
+    /// lines, kept from the first vertical move until the next other action
+    pub fn goal_column(&self) -> Option<usize> {
+        self.goal_column
+    }
+
+    /// Set the goal column, for frontends that lay out lines themselves
+    pub fn set_goal_column(&mut self, goal_column: Option<usize>) {
+        self.goal_column = goal_column;
+    }
+
+    /// The block selection being made, if the last action created or
+    /// resized one. Its rows hold one selection each in `state()`.
+    pub fn block_selection(&self) -> Option<BlockSelection> {
+        self.block
+    }
+
+    /// Text of every selection in document order, one per line, or an empty
+    /// string when nothing is selected
+    pub fn selected_text(&self) -> String {
+        let selections = self.state.selections();
+        if selections.iter().all(Selection::is_empty) {
+            return String::new();
+        }
+        selections
+            .iter()
+            .map(|selection| {
+                let (start, end) = selection.range();
+                self.state.buffer.text_range(start, end)
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Text between two positions, given in either order
+    pub fn text_in_range(&self, start: BufferPosition, end: BufferPosition) -> String {
+        let buffer = &self.state.buffer;
+        let (start, end) = (buffer.clamp_position(start), buffer.clamp_position(end));
+        buffer.text_range(start.min(end), start.max(end))
+    }
+
+    /// Insert text at a position. Returns the position just after it; see
+    /// `replace_text`.
+    pub fn insert_text(&mut self, pos: BufferPosition, text: &str) -> BufferPosition {
+        self.replace_text(pos, pos, text)
+    }
+
+    /// Delete the text between two positions; see `replace_text`
+    pub fn delete_text(&mut self, start: BufferPosition, end: BufferPosition) {
+        self.replace_text(start, end, "");
+    }
+
+    /// Replace the text between two positions, given in either order, as
+    /// its own undo step. Line breaks in `text` are converted to the
+    /// document's style. Cursors after the range shift with the text, and
+    /// those within it, ends included, move to the end of the new text.
+    /// Returns that position. Binary documents are left unchanged.
+    pub fn replace_text(
+        &mut self,
+        start: BufferPosition,
+        end: BufferPosition,
+        text: &str,
+    ) -> BufferPosition {
+        // Kills and pastes from history don't carry on across the edit
+        self.streak = Streak::None;
+        self.previous_streak = Streak::None;
+        let buffer = &self.state.buffer;
+        let (start, end) = (buffer.clamp_position(start), buffer.clamp_position(end));
+        let (start, end) = (start.min(end), start.max(end));
+        let text = self.state.line_ending.normalize(text);
+        if self.hex.is_some() || (start == end && text.is_empty()) {
+            return start;
+        }
+        let cursors_before = self.change_start();
+
+        let start_byte = buffer.position_to_byte(start);
+        let end_byte = buffer.position_to_byte(end);
+        let shift = |byte: usize| {
+            if byte < start_byte {
+                byte
+            } else if byte <= end_byte {
+                start_byte + text.len()
+            } else {
+                byte + text.len() - (end_byte - start_byte)
+            }
+        };
+        let track = |selection: &Selection| {
+            (
+                shift(buffer.position_to_byte(selection.cursor)),
+                selection
+                    .anchor
+                    .map(|anchor| shift(buffer.position_to_byte(anchor))),
+            )
+        };
+        let tracked: Vec<_> = self
+            .selection_slots()
+            .iter()
+            .map(|slot| (track(&slot.0), slot.2))
+            .collect();
+
+        self.begin_transaction();
+        if start != end {
+            self.delete_range(start, end);
+        }
+        let after = if text.is_empty() {
+            start
+        } else {
+            self.insert_at(start, &text)
+        };
+        let buffer = &self.state.buffer;
+        let slots = tracked
+            .into_iter()
+            .map(|((cursor, anchor), primary)| {
+                let anchor = anchor.map(|anchor| buffer.byte_to_position(anchor));
+                (
+                    Selection::new(buffer.byte_to_position(cursor), anchor),
+                    None,
+                    primary,
+                )
+            })
+            .collect();
+        self.set_selection_slots(slots);
+        self.block = None;
+        self.commit_transaction();
+        self.finish_change(cursors_before);
+        after
+    }
+
+    /// Replace all cursors and selections. The first becomes the primary
+    /// selection, and selections that overlap are merged. Does nothing if
+    /// `selections` is empty.
+    pub fn set_selections(&mut self, selections: impl IntoIterator<Item = Selection>) {
+        let buffer = &self.state.buffer;
+        let slots: Vec<SelectionSlot> = selections
+            .into_iter()
+            .enumerate()
+            .map(|(index, selection)| {
+                let cursor = buffer.clamp_position(selection.cursor);
+                let anchor = selection.anchor.map(|anchor| buffer.clamp_position(anchor));
+                (Selection::new(cursor, anchor), None, index == 0)
+            })
+            .collect();
+        if slots.is_empty() {
+            return;
+        }
+        let cursors_before = self.change_start();
+        self.set_selection_slots(slots);
+        self.block = None;
+        self.finish_change(cursors_before);
+    }
+
+    /// Start or stop recording a `ChangeEvent` for each action or editing
+    /// call that changes the text or cursors. Changes made through
+    /// `state_mut` are not seen.
+    pub fn record_changes(&mut self, enabled: bool) {
+        if enabled {
+            self.changes.get_or_insert_with(Vec::new);
+        } else {
+            self.changes = None;
+        }
+    }
+
+    /// Change events recorded since the last call, oldest first
+    pub fn take_changes(&mut self) -> Vec<ChangeEvent> {
+        self.changes.as_mut().map(mem::take).unwrap_or_default()
+    }
+
+    /// Place an anchor that follows the text around it as it is edited
+    pub fn create_anchor(&mut self, pos: BufferPosition, gravity: Gravity) -> AnchorId {
+        let pos = self.state.buffer.clamp_position(pos);
+        self.anchors.insert(pos, gravity)
+    }
+
+    /// Where an anchor is now, or `None` if it was removed or the text
+    /// around it was deleted
+    pub fn anchor_position(&self, id: AnchorId) -> Option<BufferPosition> {
+        self.anchors.get(id)
+    }
+
+    /// Returns whether the anchor existed
+    pub fn remove_anchor(&mut self, id: AnchorId) -> bool {
+        self.anchors.remove(id)
+    }
+
+    /// Find `query` and keep its matches up to date until `clear_search`.
+    /// The first match at or after where the find started is selected; if
+    /// there is none, the cursor goes back there.
+    pub fn set_search(&mut self, query: SearchQuery) {
+        let cursors_before = self.change_start();
+        self.start_search().query = query;
+        self.refresh_search();
+        self.select_from_origin();
+        self.finish_change(cursors_before);
+    }
+
+    /// Only find matches inside the selections there were when the find
+    /// started, or find them anywhere again
+    pub fn set_search_in_selection(&mut self, enabled: bool) {
+        let cursors_before = self.change_start();
+        let search = self.start_search();
+        search.in_selection = enabled;
+        let has_query = !search.query.text.is_empty();
+        self.refresh_search();
+        if has_query {
+            self.select_from_origin();
+        }
+        self.finish_change(cursors_before);
+    }
+
+    /// Whether matches are only found inside the selections the find
+    /// started with
+    pub fn is_search_in_selection(&self) -> bool {
+        self.search
+            .as_ref()
+            .is_some_and(|search| search.in_selection)
+    }
+
+    /// Stop finding, leaving the selection as it is
+    pub fn clear_search(&mut self) {
+        if let Some(search) = self.search.take() {
+            for (start, end) in search.selections {
+                self.anchors.remove(start);
+                self.anchors.remove(end);
+            }
+        }
+    }
+
+    /// The query being found, if any
+    pub fn search_query(&self) -> Option<&SearchQuery> {
+        self.search.as_ref().map(|search| &search.query)
+    }
+
+    /// Start and end of each match of the query being found, in order
+    pub fn search_matches(&self) -> &[(BufferPosition, BufferPosition)] {
+        self.search
+            .as_ref()
+            .map_or(&[], |search| search.matches.as_slice())
+    }
+
+    /// Index in `search_matches` of the match the primary selection covers
+    pub fn current_match(&self) -> Option<usize> {
+        let range = self.state.primary_selection().range();
+        let matches = self.search_matches();
+        matches
+            .binary_search_by_key(&range.0, |found| found.0)
+            .ok()
+            .filter(|&index| matches[index] == range)
+    }
+
+    /// Replace the selected match with `replacement`, then select the next
+    /// match; with a regular expression, `$1` stands for the first group.
+    /// If no match is selected, only the next one is selected. Returns
+    /// whether anything was replaced.
+    pub fn replace_match(&mut self, replacement: &str) -> bool {
+        let Some(index) = self.current_match() else {
+            self.handle_action(EditorAction::FindNext);
+            return false;
+        };
+        let found = self.search_matches()[index];
+        let text = self.replacements_for(&[found], replacement).remove(0);
+        self.replace_text(found.0, found.1, &text);
+        self.handle_action(EditorAction::FindNext);
+        true
+    }
+
+    /// Replace every match as one undo step, returning how many were
+    /// replaced: `search_matches().len()` before the call. Combined with
+    /// `set_search_in_selection`, this replaces within the selection.
+    pub fn replace_all(&mut self, replacement: &str) -> usize {
+        let matches = self.search_matches().to_vec();
+        if matches.is_empty() {
+            return 0;
+        }
+        let texts = self.replacements_for(&matches, replacement);
+
+        // Find matches again once, after the last replacement. Going from
+        // the end keeps the positions of the matches before valid.
+        let search = self.search.take();
+        self.begin_transaction();
+        for (&(start, end), text) in matches.iter().zip(&texts).rev() {
+            self.replace_text(start, end, text);
+        }
+        self.commit_transaction();
+        self.search = search;
+        self.refresh_search();
+        matches.len()
+    }
+
+    /// Whether edits must be collected in `pending_edits`
+    fn tracks_edits(&self) -> bool {
+        self.changes.is_some() || !self.anchors.is_empty() || self.search.is_some()
+    }
+
+    /// The cursors before a change, if changes are recorded
+    fn change_start(&self) -> Option<CursorState> {
+        self.changes.as_ref().map(|_| CursorState::of(&self.state))
+    }
+
+    /// Move anchors past the edits made since `change_start`, and record
+    /// the change if anything changed
+    fn finish_change(&mut self, cursors_before: Option<CursorState>) {
+        let mut edits = mem::take(&mut self.pending_edits);
+        edits.extend(self.history.take_replayed());
+        for edit in &edits {
+            self.drop_deleted_bookmarks(edit);
+            self.anchors.apply(edit);
+        }
+        if !edits.is_empty() {
+            self.refresh_search_after(&edits);
+        }
+        let (Some(changes), Some(cursors_before)) = (self.changes.as_mut(), cursors_before) else {
+            return;
+        };
+        let cursors_after = CursorState::of(&self.state);
+        if !edits.is_empty() || cursors_after != cursors_before {
+            changes.push(ChangeEvent {
+                edits,
+                cursors_before,
+                cursors_after,
+            });
+        }
+    }
+
+    /// Recorded undo steps
+    pub fn undo_history(&self) -> &UndoHistory {
+        &self.history
+    }
+
+    /// Cap the memory held by undo history, dropping the oldest steps to fit
+    pub fn set_undo_memory_limit(&mut self, bytes: usize) {
+        self.history.set_memory_limit(bytes);
+    }
+
+    /// Keep undo history between sessions in this directory: it is saved
+    /// with the file and restored when the file is loaded unchanged.
+    /// `None`, the default, keeps history in memory only.
+    pub fn set_undo_history_dir(&mut self, dir: Option<PathBuf>) {
+        self.undo_dir = dir;
+    }
+
+    /// Keep bookmarks between sessions in this directory, like
+    /// `set_undo_history_dir`
+    pub fn set_bookmark_dir(&mut self, dir: Option<PathBuf>) {
+        self.bookmark_dir = dir;
+    }
+
+    /// Keep the kill ring between sessions in this file: it is loaded now
+    /// and saved with the document
+    pub fn set_kill_ring_file(&mut self, path: Option<PathBuf>) {
+        if let Some(path) = &path {
+            self.kill_ring = KillRing::load(path);
+        }
+        self.kill_ring_file = path;
+    }
+
+    /// Text recently cut or deleted, newest first
+    pub fn kill_ring(&self) -> &KillRing {
+        &self.kill_ring
+    }
+
+    /// Bookmarked rows in document order
+    pub fn bookmarks(&self) -> Vec<usize> {
+        let mut rows: Vec<usize> = self
+            .bookmarks
+            .iter()
+            .filter_map(|&id| self.anchors.get(id))
+            .map(|pos| pos.row)
+            .collect();
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    }
+
+    fn add_bookmark(&mut self, row: usize) {
+        // Right gravity keeps the bookmark with the line when a line break
+        // is inserted at its start
+        let id = self.create_anchor(BufferPosition::new(row, 0), Gravity::Right);
+        self.bookmarks.push(id);
+    }
+
+    fn clear_bookmarks(&mut self) {
+        for id in mem::take(&mut self.bookmarks) {
+            self.anchors.remove(id);
+        }
+    }
+
+    /// Remove the bookmark of a line `edit` deletes whole. Its anchor sits
+    /// at the start of the deletion, so it would otherwise pass to the line
+    /// after.
+    fn drop_deleted_bookmarks(&mut self, edit: &TextEdit) {
+        let deletes_lines = edit.new_end == edit.start
+            && edit.start.column == 0
+            && edit.old_end.column == 0
+            && edit.old_end.row > edit.start.row;
+        if !deletes_lines || self.bookmarks.is_empty() {
+            return;
+        }
+        let (deleted, kept): (Vec<AnchorId>, Vec<AnchorId>) = self
+            .bookmarks
+            .iter()
+            .partition(|&&id| self.anchors.get(id) == Some(edit.start));
+        for id in deleted {
+            self.anchors.remove(id);
+        }
+        self.bookmarks = kept;
+    }
+
+    fn toggle_bookmark(&mut self) {
+        let row = self.state.cursor.row;
+        let (on_row, others): (Vec<AnchorId>, Vec<AnchorId>) = self
+            .bookmarks
+            .iter()
+            .partition(|&&id| self.anchors.get(id).is_none_or(|pos| pos.row == row));
+        // Bookmarks inside a deletion spanning lines were invalidated; they go too
+        let had_bookmark = on_row.iter().any(|&id| self.anchors.get(id).is_some());
+        for id in on_row {
+            self.anchors.remove(id);
+        }
+        self.bookmarks = others;
+        if !had_bookmark {
+            self.add_bookmark(row);
+        }
+    }
+
+    /// Move to the start of the nearest bookmarked line after the cursor,
+    /// or before it, wrapping around the document
+    fn jump_to_bookmark(&mut self, forward: bool) {
+        let rows = self.bookmarks();
+        let row = self.state.cursor.row;
+        let target = if forward {
+            rows.iter().find(|&&r| r > row).or(rows.first())
+        } else {
+            rows.iter().rev().find(|&&r| r < row).or(rows.last())
+        };
+        if let Some(&target) = target {
+            self.set_cursor_position(target, 0);
+        }
+    }
+
+    /// Group the edits made until the matching `commit_transaction` into a
+    /// single undo step, which undoes to the cursors as they are now.
+    /// Transactions nest; an inner one joins the step of the outer.
+    pub fn begin_transaction(&mut self) {
+        self.transactions.push(Transaction {
+            checkpoint: self.history.checkpoint(),
+            start: self.history.current(),
+            cursors: CursorState::of(&self.state),
+        });
+        // Don't join the step of edits made just before
+        self.history.begin(&self.state);
+    }
+
+    /// Close the innermost transaction, keeping its edits
+    pub fn commit_transaction(&mut self) {
+        let Some(transaction) = self.transactions.pop() else {
+            return;
+        };
+        self.history
+            .merge_since(transaction.checkpoint, transaction.cursors);
+        self.history.finish(&self.state);
+        self.last_edit_time = None;
+    }
+
+    /// Close the innermost transaction, reverting its edits and restoring
+    /// the cursors it began with
+    pub fn abort_transaction(&mut self) {
+        let Some(transaction) = self.transactions.pop() else {
+            return;
+        };
+        let cursors_before = self.change_start();
+        self.history
+            .rollback(transaction.checkpoint, transaction.start, &mut self.state);
+        transaction.cursors.restore(&mut self.state);
+        self.block = None;
+        self.goal_column = None;
+        self.extra_goal_columns.clear();
+        self.last_edit_time = None;
+        self.finish_change(cursors_before);
+    }
+
+    pub fn in_transaction(&self) -> bool {
+        !self.transactions.is_empty()
+    }
+
+    /// Run `f` in a transaction, committing it if `f` succeeds and aborting
+    /// it if `f` fails
+    pub fn transaction<T, E>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        self.begin_transaction();
+        let result = f(self);
+        if result.is_ok() {
+            self.commit_transaction();
+        } else {
+            self.abort_transaction();
+        }
+        result
+    }
+
     fn should_push_undo_state(&self) -> bool {
         if let Some(last_time) = self.last_edit_time {
             Instant::now().duration_since(last_time) > UNDO_CHUNK_DURATION
@@ -45,15 +778,143 @@ impl EditorEngine {
         if !self.should_push_undo_state() {
             return;
         }
-        self.undo_stack.push(self.state.clone_for_undo());
-        self.redo_stack.clear();
+        self.history.begin(&self.state);
     }
 
     fn mark_edit_time(&mut self) {
         self.last_edit_time = Some(Instant::now());
     }
 
+    /// Set the clipboard that `handle_action` cuts and copies to and pastes
+    /// from. Until then the engine keeps one of its own.
+    pub fn set_clipboard(&mut self, clipboard: Box<dyn Clipboard>) {
+        self.clipboard = clipboard;
+    }
+
     pub fn handle_action(&mut self, action: EditorAction) {
+        let action = if Self::uses_clipboard(&action) {
+            let mut clipboard = mem::replace(&mut self.clipboard, Box::new(MemoryClipboard::new()));
+            let action = self.use_clipboard(action, clipboard.as_mut());
+            self.clipboard = clipboard;
+            action
+        } else {
+            Some(action)
+        };
+        if let Some(action) = action {
+            self.dispatch(action);
+        }
+    }
+
+    /// Handle an action with a clipboard that only lives as long as the
+    /// call, such as one borrowing the frontend's context
+    pub fn handle_action_with_clipboard(
+        &mut self,
+        action: EditorAction,
+        clipboard: &mut dyn Clipboard,
+    ) {
+        if let Some(action) = self.use_clipboard(action, clipboard) {
+            self.dispatch(action);
+        }
+    }
+
+    fn uses_clipboard(action: &EditorAction) -> bool {
+        matches!(
+            action,
+            EditorAction::Cut | EditorAction::Copy | EditorAction::PasteFromClipboard
+        )
+    }
+
+    /// Do the clipboard's part of `action`: Cut and Copy put the selected
+    /// text on it, and PasteFromClipboard becomes a Paste of its text.
+    /// Returns what is left to dispatch. Nothing is cut unless the text
+    /// reached the clipboard.
+    fn use_clipboard(
+        &self,
+        action: EditorAction,
+        clipboard: &mut dyn Clipboard,
+    ) -> Option<EditorAction> {
+        match action {
+            EditorAction::Cut | EditorAction::Copy => {
+                let text = self.selected_text();
+                if text.is_empty() || clipboard.write(&text).is_err() {
+                    return None;
+                }
+                (action == EditorAction::Cut).then_some(action)
+            }
+            EditorAction::PasteFromClipboard => clipboard
+                .read()
+                .filter(|text| !text.is_empty())
+                .map(EditorAction::Paste),
+            action => Some(action),
+        }
+    }
+
+    fn dispatch(&mut self, action: EditorAction) {
+        self.previous_streak = mem::take(&mut self.streak);
+        if action == EditorAction::Cut {
+            // All selections together, as they went to the clipboard
+            let text = self.selected_text();
+            self.kill(text, false);
+        }
+        let is_view_action = matches!(
+            action,
+            EditorAction::IncreaseFontSize
+                | EditorAction::DecreaseFontSize
+                | EditorAction::ResetFontSize
+        );
+        if self.hex.is_some() && !is_view_action {
+            self.handle_hex_action(action);
+            return;
+        }
+        let cursors_before = self.change_start();
+        // Frontends may set positions directly; keep them on char boundaries
+        let buffer = &self.state.buffer;
+        self.state.cursor = buffer.clamp_position(self.state.cursor);
+        self.state.selection_anchor = self
+            .state
+            .selection_anchor
+            .map(|anchor| buffer.clamp_position(anchor));
+        for selection in &mut self.state.extra_selections {
+            selection.cursor = buffer.clamp_position(selection.cursor);
+            selection.anchor = selection.anchor.map(|anchor| buffer.clamp_position(anchor));
+        }
+
+        let is_vertical_motion = matches!(
+            action,
+            EditorAction::MoveUp
+                | EditorAction::MoveDown
+                | EditorAction::SelectUp
+                | EditorAction::SelectDown
+                | EditorAction::AddCursorAbove
+                | EditorAction::AddCursorBelow
+        );
+        if !is_vertical_motion && !is_view_action {
+            self.goal_column = None;
+            self.extra_goal_columns.clear();
+        }
+
+        let is_block_action = matches!(
+            action,
+            EditorAction::BlockSelectLeft
+                | EditorAction::BlockSelectRight
+                | EditorAction::BlockSelectUp
+                | EditorAction::BlockSelectDown
+                | EditorAction::ExtendBlockSelection { .. }
+        );
+        if !is_block_action && !is_view_action {
+            self.block = None;
+        }
+
+        if !self.state.extra_selections.is_empty() && Self::applies_to_each_selection(&action) {
+            self.apply_to_each_selection(action);
+        } else {
+            self.apply(action);
+        }
+        self.history.finish(&self.state);
+        self.finish_change(cursors_before);
+    }
+
+    fn apply(&mut self, action: EditorAction) {
         match action {
             EditorAction::TypeCharacter(c) => self.type_character(c),
             EditorAction::TypeString(s) => self.type_string(&s),
@@ -70,6 +931,21 @@ impl EditorEngine {
             EditorAction::MoveWordRight => self.move_word_right(),
             EditorAction::Undo => self.undo(),
             EditorAction::Redo => self.redo(),
+            EditorAction::UndoEarlier(duration) => {
+                self.travel_history(|history, state| history.earlier(duration, state))
+            }
+            EditorAction::RedoLater(duration) => {
+                self.travel_history(|history, state| history.later(duration, state))
+            }
+            EditorAction::PreviousUndoBranch => {
+                self.travel_history(|history, state| history.switch_branch(false, state))
+            }
+            EditorAction::NextUndoBranch => {
+                self.travel_history(|history, state| history.switch_branch(true, state))
+            }
+            EditorAction::GoToUndoState(id) => {
+                self.travel_history(|history, state| history.go_to(id, state))
+            }
             EditorAction::DeleteLine => self.delete_line(),
             EditorAction::DeleteToBeginningOfLine => self.delete_to_beginning_of_line(),
             EditorAction::DeleteToEndOfLine => self.delete_to_end_of_line(),
@@ -79,6 +955,7 @@ impl EditorEngine {
             EditorAction::MoveLineDown => self.move_line_down(),
             EditorAction::Tab => self.tab(),
             EditorAction::Outdent => self.outdent(),
+            EditorAction::ConvertLineEndings(line_ending) => self.convert_line_endings(line_ending),
             EditorAction::SelectLeft => self.select_left(),
             EditorAction::SelectRight => self.select_right(),
             EditorAction::SelectUp => self.select_up(),
@@ -86,6 +963,18 @@ impl EditorEngine {
             EditorAction::SelectWordLeft => self.select_word_left(),
             EditorAction::SelectWordRight => self.select_word_right(),
             EditorAction::SelectAll => self.select_all(),
+            EditorAction::AddCursorAbove => self.add_cursor_vertically(false),
+            EditorAction::AddCursorBelow => self.add_cursor_vertically(true),
+            EditorAction::SelectNextOccurrence => self.select_next_occurrence(),
+            EditorAction::BlockSelectLeft => self.move_block_cursor(0, -1),
+            EditorAction::BlockSelectRight => self.move_block_cursor(0, 1),
+            EditorAction::BlockSelectUp => self.move_block_cursor(-1, 0),
+            EditorAction::BlockSelectDown => self.move_block_cursor(1, 0),
+            EditorAction::FindNext => self.find_next(true),
+            EditorAction::FindPrevious => self.find_next(false),
+            EditorAction::ToggleBookmark => self.toggle_bookmark(),
+            EditorAction::NextBookmark => self.jump_to_bookmark(true),
+            EditorAction::PreviousBookmark => self.jump_to_bookmark(false),
             EditorAction::IncreaseFontSize => {
                 self.state.font_size = (self.state.font_size + 2.0).min(72.0);
             }
@@ -95,8 +984,11 @@ impl EditorEngine {
             EditorAction::ResetFontSize => {
                 self.state.font_size = 14.0;
             }
-            EditorAction::Cut | EditorAction::Copy | EditorAction::Paste(_) => {
-                // Clipboard operations need platform-specific handling
+            EditorAction::Cut => self.cut(),
+            EditorAction::Paste(text) => self.paste(&text),
+            EditorAction::PasteFromHistory => self.paste_from_history(),
+            EditorAction::Copy | EditorAction::PasteFromClipboard => {
+                // Done with the clipboard before dispatch
             }
             EditorAction::Quit => {
                 // Handled by platform-specific code
@@ -106,6 +998,527 @@ impl EditorEngine {
             }
             EditorAction::StartSelection { row, column } => self.start_selection(row, column),
             EditorAction::ExtendSelection { row, column } => self.extend_selection(row, column),
+            EditorAction::ExtendBlockSelection { row, cell } => {
+                self.extend_block_selection(row, cell)
+            }
+        }
+    }
+
+    /// Whether an action runs once for every cursor when there are several.
+    /// The rest act on the selection set as a whole or ignore it.
+    fn applies_to_each_selection(action: &EditorAction) -> bool {
+        !matches!(
+            action,
+            EditorAction::Undo
+                | EditorAction::Redo
+                | EditorAction::UndoEarlier(_)
+                | EditorAction::RedoLater(_)
+                | EditorAction::PreviousUndoBranch
+                | EditorAction::NextUndoBranch
+                | EditorAction::GoToUndoState(_)
+                | EditorAction::Copy
+                | EditorAction::Paste(_)
+                | EditorAction::PasteFromClipboard
+                | EditorAction::PasteFromHistory
+                | EditorAction::ConvertLineEndings(_)
+                | EditorAction::SelectAll
+                | EditorAction::AddCursorAbove
+                | EditorAction::AddCursorBelow
+                | EditorAction::SelectNextOccurrence
+                | EditorAction::BlockSelectLeft
+                | EditorAction::BlockSelectRight
+                | EditorAction::BlockSelectUp
+                | EditorAction::BlockSelectDown
+                | EditorAction::FindNext
+                | EditorAction::FindPrevious
+                | EditorAction::ToggleBookmark
+                | EditorAction::NextBookmark
+                | EditorAction::PreviousBookmark
+                | EditorAction::IncreaseFontSize
+                | EditorAction::DecreaseFontSize
+                | EditorAction::ResetFontSize
+                | EditorAction::Quit
+                | EditorAction::SetCursorPosition { .. }
+                | EditorAction::StartSelection { .. }
+                | EditorAction::ExtendSelection { .. }
+                | EditorAction::ExtendBlockSelection { .. }
+        )
+    }
+
+    /// Run an action once per selection as if each were the only cursor,
+    /// recording a single undo step for the whole action.
+    ///
+    /// Selections are processed from the bottom of the document up, so an
+    /// edit can only shift selections that were already processed; those are
+    /// tracked by their distance from the end of the buffer. Moving lines up
+    /// runs top-down instead and tracks distance from the start.
+    fn apply_to_each_selection(&mut self, action: EditorAction) {
+        let top_down = matches!(action, EditorAction::MoveLineUp);
+        let line_wise = matches!(
+            action,
+            EditorAction::DeleteLine | EditorAction::MoveLineUp | EditorAction::MoveLineDown
+        );
+        // The selections' kills make one entry, in document order and
+        // joined as cut text is
+        self.selection_kills = Some(Vec::new());
+        self.for_each_selection(top_down, line_wise, |engine, _| {
+            engine.apply(action.clone())
+        });
+        let mut kills = self.selection_kills.take().unwrap_or_default();
+        if !top_down {
+            kills.reverse();
+        }
+        if let Some(&(_, before)) = kills.first() {
+            let texts: Vec<String> = kills.into_iter().map(|(text, _)| text).collect();
+            self.kill(texts.join("\n"), before);
+        }
+    }
+
+    /// Run `f` with each selection in turn made the only one, passing its
+    /// index in document order. `line_wise` runs once per line.
+    fn for_each_selection(
+        &mut self,
+        top_down: bool,
+        line_wise: bool,
+        mut f: impl FnMut(&mut Self, usize),
+    ) {
+        let before = CursorState::of(&self.state);
+        let checkpoint = self.history.checkpoint();
+
+        let mut slots: Vec<SelectionSlot> = Vec::new();
+        for slot in self.selection_slots() {
+            // Line operations act once per line
+            if let Some(last) = slots.last_mut() {
+                if line_wise && last.0.cursor.row == slot.0.cursor.row {
+                    last.2 |= slot.2;
+                    continue;
+                }
+            }
+            slots.push(slot);
+        }
+        let mut slots: Vec<_> = slots.into_iter().enumerate().collect();
+        if !top_down {
+            slots.reverse();
+        }
+
+        let mut processed = Vec::with_capacity(slots.len());
+        for (index, (selection, goal_column, primary)) in slots {
+            self.state.cursor = selection.cursor;
+            self.state.selection_anchor = selection.anchor;
+            self.goal_column = goal_column;
+            f(self, index);
+
+            let buffer = &self.state.buffer;
+            let len = buffer.len_bytes();
+            let track = |pos| {
+                let byte = buffer.position_to_byte(pos);
+                if top_down {
+                    byte
+                } else {
+                    len - byte
+                }
+            };
+            processed.push((
+                track(self.state.cursor),
+                self.state.selection_anchor.map(track),
+                self.goal_column,
+                primary,
+            ));
+        }
+
+        let buffer = &self.state.buffer;
+        let len = buffer.len_bytes();
+        let restore = |tracked: usize| {
+            let byte = if top_down {
+                tracked
+            } else {
+                len.saturating_sub(tracked)
+            };
+            buffer.byte_to_position(byte)
+        };
+        let slots = processed
+            .into_iter()
+            .map(|(cursor, anchor, goal_column, primary)| {
+                let selection = Selection::new(restore(cursor), anchor.map(restore));
+                (selection, goal_column, primary)
+            })
+            .collect();
+        self.set_selection_slots(slots);
+
+        self.history.merge_since(checkpoint, before);
+    }
+
+    /// All selections in document order, with their goal columns
+    fn selection_slots(&self) -> Vec<SelectionSlot> {
+        let mut slots: Vec<SelectionSlot> = self
+            .state
+            .extra_selections
+            .iter()
+            .enumerate()
+            .map(|(i, selection)| {
+                let goal_column = self.extra_goal_columns.get(i).copied().flatten();
+                (*selection, goal_column, false)
+            })
+            .collect();
+        slots.push((self.state.primary_selection(), self.goal_column, true));
+        slots.sort_by_key(|slot| slot.0.range());
+        slots
+    }
+
+    /// Replace all selections, merging any that overlap
+    fn set_selection_slots(&mut self, mut slots: Vec<SelectionSlot>) {
+        slots.sort_by_key(|slot| slot.0.range());
+        let mut merged: Vec<SelectionSlot> = Vec::with_capacity(slots.len());
+        for slot in slots {
+            if let Some(last) = merged.last_mut() {
+                let (last_start, last_end) = last.0.range();
+                let (start, end) = slot.0.range();
+                if start < last_end || start == last_start {
+                    let end = end.max(last_end);
+                    last.0 = if last_start == end {
+                        Selection::caret(end)
+                    } else {
+                        Selection::new(end, Some(last_start))
+                    };
+                    last.2 |= slot.2;
+                    continue;
+                }
+            }
+            merged.push(slot);
+        }
+
+        let primary_index = merged.iter().position(|slot| slot.2).unwrap_or(0);
+        let (primary, goal_column, _) = merged.remove(primary_index);
+        self.state.cursor = primary.cursor;
+        self.state.selection_anchor = primary.anchor;
+        self.goal_column = goal_column;
+        self.state.extra_selections = merged.iter().map(|slot| slot.0).collect();
+        self.extra_goal_columns = merged.iter().map(|slot| slot.1).collect();
+    }
+
+    /// Make a new selection primary, keeping the current one as an extra
+    fn add_primary_selection(&mut self, selection: Selection, goal_column: Option<usize>) {
+        let mut slots = self.selection_slots();
+        for slot in &mut slots {
+            slot.2 = false;
+        }
+        slots.push((selection, goal_column, true));
+        self.set_selection_slots(slots);
+    }
+
+    /// Add a cursor on the line above the topmost cursor, or below the
+    /// bottommost one, at the same display column
+    fn add_cursor_vertically(&mut self, down: bool) {
+        let slots = self.selection_slots();
+        let edge = if down {
+            slots.iter().max_by_key(|slot| slot.0.cursor)
+        } else {
+            slots.iter().min_by_key(|slot| slot.0.cursor)
+        };
+        let Some(&(edge, goal_column, _)) = edge else {
+            return;
+        };
+        let edge = edge.cursor;
+        let row = if down {
+            edge.row + 1
+        } else if let Some(row) = edge.row.checked_sub(1) {
+            row
+        } else {
+            return;
+        };
+        if row >= self.state.line_count() {
+            return;
+        }
+
+        let goal = goal_column.unwrap_or_else(|| {
+            let line = self.state.line(edge.row).unwrap_or_default();
+            display_width::column_to_cell(&line, edge.column)
+        });
+        let line = self.state.line(row).unwrap_or_default();
+        let column = display_width::cell_to_column(&line, goal);
+        let cursor = BufferPosition::new(row, column);
+        self.add_primary_selection(Selection::caret(cursor), Some(goal));
+    }
+
+    /// Select the word under the cursor, or if text is already selected,
+    /// add a selection at its next occurrence, wrapping around the end
+    fn select_next_occurrence(&mut self) {
+        let (start, end) = self.state.primary_selection().range();
+        if start == end {
+            if let Some((word_start, word_end)) = self.word_range_at(start) {
+                self.state.selection_anchor = Some(word_start);
+                self.state.cursor = word_end;
+            }
+            return;
+        }
+
+        let buffer = &self.state.buffer;
+        let needle = buffer.text_range(start, end);
+        let text = buffer.to_string();
+        let from = buffer.position_to_byte(end);
+        let selected: Vec<_> = self
+            .state
+            .selections()
+            .iter()
+            .map(|selection| selection.range().0)
+            .collect();
+
+        let after = text[from..].match_indices(&needle).map(|(i, _)| from + i);
+        let before = text[..from].match_indices(&needle).map(|(i, _)| i);
+        let next = after
+            .chain(before)
+            .map(|i| {
+                (
+                    buffer.byte_to_position(i),
+                    buffer.byte_to_position(i + needle.len()),
+                )
+            })
+            .find(|(start, _)| !selected.contains(start));
+
+        if let Some((start, end)) = next {
+            self.add_primary_selection(Selection::new(end, Some(start)), None);
+        }
+    }
+
+    /// The find in progress, starting one from the current selections if
+    /// there is none
+    fn start_search(&mut self) -> &mut Search {
+        if self.search.is_none() {
+            let selections = self
+                .state
+                .selections()
+                .iter()
+                .filter(|selection| !selection.is_empty())
+                .map(|selection| {
+                    let (start, end) = selection.range();
+                    // Text inserted at either edge joins the selection
+                    (
+                        self.anchors.insert(start, Gravity::Left),
+                        self.anchors.insert(end, Gravity::Right),
+                    )
+                })
+                .collect();
+            self.search = Some(Search {
+                query: SearchQuery::default(),
+                matches: Vec::new(),
+                origin: self.state.primary_selection().range().0,
+                selections,
+                in_selection: false,
+            });
+        }
+        self.search.as_mut().expect("search was just started")
+    }
+
+    /// Find the query's matches again in the current text
+    fn refresh_search(&mut self) {
+        let Some(search) = self.search.as_mut() else {
+            return;
+        };
+        search.matches.clear();
+        if self.hex.is_some() {
+            return;
+        }
+        let buffer = &self.state.buffer;
+        search.matches = search.find_in_rows(buffer, 0..=buffer.line_count() - 1);
+        search.keep_in_selections(&self.anchors);
+    }
+
+    /// Find the query's matches again after `edits`, only on the lines they
+    /// touched unless a match could span lines
+    fn refresh_search_after(&mut self, edits: &[TextEdit]) {
+        match self.search.as_mut() {
+            Some(search) if self.hex.is_none() && !search.query.can_span_lines() => {
+                search.follow_edits(&self.state.buffer, edits);
+                search.keep_in_selections(&self.anchors);
+            }
+            _ => self.refresh_search(),
+        }
+    }
+
+    /// Select the first match at or after where the find started, or put
+    /// the cursor back there if there is none
+    fn select_from_origin(&mut self) {
+        let Some(search) = &self.search else {
+            return;
+        };
+        let origin = self.state.buffer.clamp_position(search.origin);
+        let found = search
+            .matches
+            .iter()
+            .find(|(start, _)| *start >= origin)
+            .or(search.matches.first())
+            .copied();
+        match found {
+            Some(found) => self.select_match(found),
+            None => self.set_cursor_position(origin.row, origin.column),
+        }
+        self.goal_column = None;
+        self.block = None;
+    }
+
+    /// What to replace each of `matches` with, for the query being found
+    fn replacements_for(
+        &self,
+        matches: &[(BufferPosition, BufferPosition)],
+        replacement: &str,
+    ) -> Vec<String> {
+        let Some(search) = &self.search else {
+            return Vec::new();
+        };
+        let buffer = &self.state.buffer;
+        let ranges: Vec<_> = matches
+            .iter()
+            .map(|&(start, end)| buffer.position_to_byte(start)..buffer.position_to_byte(end))
+            .collect();
+        search
+            .query
+            .replacements(&buffer.to_string(), &ranges, replacement)
+    }
+
+    /// Select the match after the current one, or before it, wrapping
+    /// around the document. Later changes to the query search from there.
+    fn find_next(&mut self, forward: bool) {
+        let matches = self.search_matches();
+        if matches.is_empty() {
+            return;
+        }
+        let last = matches.len() - 1;
+        let index = match self.current_match() {
+            Some(index) if forward => (index + 1) % matches.len(),
+            Some(index) => index.checked_sub(1).unwrap_or(last),
+            None => {
+                let start = self.state.primary_selection().range().0;
+                let after = matches.partition_point(|found| found.0 < start);
+                if forward {
+                    after % matches.len()
+                } else {
+                    after.checked_sub(1).unwrap_or(last)
+                }
+            }
+        };
+        let found = matches[index];
+        self.select_match(found);
+        if let Some(search) = self.search.as_mut() {
+            search.origin = found.0;
+        }
+    }
+
+    /// Make a match the only selection, with the cursor at its end
+    fn select_match(&mut self, (start, end): (BufferPosition, BufferPosition)) {
+        self.set_selection_slots(vec![(Selection::new(end, Some(start)), None, true)]);
+    }
+
+    /// Range of the word containing or just before a position
+    fn word_range_at(&self, pos: BufferPosition) -> Option<(BufferPosition, BufferPosition)> {
+        let line = self.state.line(pos.row)?;
+        let is_word = |c: char| c.is_alphanumeric() || c == '_';
+
+        let start = line[..pos.column]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, c)| is_word(c))
+            .last()
+            .map_or(pos.column, |(i, _)| i);
+        let end = line[pos.column..]
+            .char_indices()
+            .find(|&(_, c)| !is_word(c))
+            .map_or(line.len(), |(i, _)| pos.column + i);
+
+        (start < end).then(|| {
+            (
+                BufferPosition::new(pos.row, start),
+                BufferPosition::new(pos.row, end),
+            )
+        })
+    }
+
+    /// A block starting at the primary cursor
+    fn block_at_cursor(&self) -> BlockSelection {
+        let cursor = self.state.cursor;
+        let line = self.state.line(cursor.row).unwrap_or_default();
+        BlockSelection::at(
+            cursor.row,
+            display_width::column_to_cell(&line, cursor.column),
+        )
+    }
+
+    /// Grow or shrink the block selection from its moving corner, starting
+    /// one at the cursor if none is active
+    fn move_block_cursor(&mut self, rows: isize, cells: isize) {
+        let mut block = self.block.unwrap_or_else(|| self.block_at_cursor());
+        let last_row = self.state.line_count().saturating_sub(1);
+        block.cursor_row = block.cursor_row.saturating_add_signed(rows).min(last_row);
+        if cells > 0 {
+            // Stop at the widest line so the corner never drifts off-screen
+            let widest = block
+                .rows()
+                .map(|row| display_width::line_width(&self.state.line(row).unwrap_or_default()))
+                .max()
+                .unwrap_or(0);
+            if block.cursor_cell < widest {
+                block.cursor_cell += 1;
+            }
+        } else {
+            block.cursor_cell = block.cursor_cell.saturating_add_signed(cells);
+        }
+        self.set_block_selection(block);
+    }
+
+    /// Drag the block selection's moving corner to a cell
+    fn extend_block_selection(&mut self, row: usize, cell: usize) {
+        let mut block = self.block.unwrap_or_else(|| self.block_at_cursor());
+        block.cursor_row = row.min(self.state.line_count().saturating_sub(1));
+        block.cursor_cell = cell;
+        self.set_block_selection(block);
+    }
+
+    /// Replace the selections with one per row of the block. Rows that end
+    /// inside or before the block get the part that exists, possibly a caret
+    /// at the end of the line.
+    fn set_block_selection(&mut self, block: BlockSelection) {
+        let cells = block.cells();
+        let backwards = block.cursor_cell < block.anchor_cell;
+        let slots = block
+            .rows()
+            .map(|row| {
+                let line = self.state.line(row).unwrap_or_default();
+                let start =
+                    BufferPosition::new(row, display_width::cell_to_column(&line, cells.start));
+                let end = BufferPosition::new(row, display_width::cell_to_column(&line, cells.end));
+                let selection = if start == end {
+                    Selection::caret(start)
+                } else if backwards {
+                    Selection::new(start, Some(end))
+                } else {
+                    Selection::new(end, Some(start))
+                };
+                (selection, None, row == block.cursor_row)
+            })
+            .collect();
+        self.set_selection_slots(slots);
+        self.block = Some(block);
+    }
+
+    /// Binary files are read-only as text; only cursor motion and
+    /// overwriting bytes with hex digits are supported
+    fn handle_hex_action(&mut self, action: EditorAction) {
+        let Some(hex) = self.hex.as_mut() else {
+            return;
+        };
+        match action {
+            EditorAction::TypeCharacter(c) => {
+                hex.type_hex_digit(c);
+            }
+            EditorAction::MoveLeft => hex.move_by(-1),
+            EditorAction::MoveRight => hex.move_by(1),
+            EditorAction::MoveUp => hex.move_by(-(BYTES_PER_ROW as isize)),
+            EditorAction::MoveDown => hex.move_by(BYTES_PER_ROW as isize),
+            EditorAction::MoveToBeginningOfLine => hex.move_to_row_start(),
+            EditorAction::MoveToEndOfLine => hex.move_to_row_end(),
+            EditorAction::SetCursorPosition { row, column } => {
+                hex.set_cursor(row * BYTES_PER_ROW + column.min(BYTES_PER_ROW - 1));
+            }
+            _ => {}
         }
     }
 
@@ -133,35 +1546,48 @@ impl EditorEngine {
         }
     }
 
+    /// Delete text, recording it for undo
     fn delete_range(&mut self, start: BufferPosition, end: BufferPosition) {
-        if start.row == end.row {
-            let line = &mut self.state.lines[start.row];
-            line.replace_range(start.column..end.column, "");
-        } else {
-            let first_part = self.state.lines[start.row][..start.column].to_string();
-            let last_part = self.state.lines[end.row][end.column..].to_string();
-            self.state.lines[start.row] = first_part + &last_part;
-            self.state.lines.drain((start.row + 1)..=(end.row));
+        let buffer = &self.state.buffer;
+        let operation = EditOperation::Delete {
+            offset: buffer.position_to_byte(start),
+            text: buffer.text_range(start, end),
+        };
+        self.history.record(operation, &self.state);
+        self.state.buffer.delete(start, end);
+        if self.tracks_edits() {
+            self.pending_edits.push(TextEdit::deletion(start, end));
         }
     }
 
+    /// Insert text verbatim, recording it for undo. Returns the position
+    /// just after it.
+    fn insert_at(&mut self, pos: BufferPosition, text: &str) -> BufferPosition {
+        let operation = EditOperation::Insert {
+            offset: self.state.buffer.position_to_byte(pos),
+            text: text.to_string(),
+        };
+        self.history.record(operation, &self.state);
+        let end = self.state.buffer.insert(pos, text);
+        if self.tracks_edits() {
+            self.pending_edits.push(TextEdit::insertion(pos, end, text));
+        }
+        end
+    }
+
+    /// Insert text at the cursor, converting line breaks to the document's style
+    fn insert_at_cursor(&mut self, text: &str) {
+        let text = self.state.line_ending.normalize(text);
+        self.state.cursor = self.insert_at(self.state.cursor, &text);
+    }
+
     fn type_character(&mut self, c: char) {
         self.push_undo_state();
         self.mark_edit_time();
         self.delete_selection();
 
-        if c == '\n' {
-            let line = self.state.lines[self.state.cursor.row].clone();
-            let (before, after) = line.split_at(self.state.cursor.column);
-            self.state.lines[self.state.cursor.row] = before.to_string();
-            self.state
-                .lines
-                .insert(self.state.cursor.row + 1, after.to_string());
-            self.state.cursor = BufferPosition::new(self.state.cursor.row + 1, 0);
-        } else {
-            self.state.lines[self.state.cursor.row].insert(self.state.cursor.column, c);
-            self.state.cursor.column += c.len_utf8();
-        }
+        let mut encoded = [0u8; 4];
+        self.insert_at_cursor(c.encode_utf8(&mut encoded));
     }
 
     fn type_string(&mut self, s: &str) {
@@ -169,42 +1595,131 @@ impl EditorEngine {
         self.mark_edit_time();
         self.delete_selection();
 
-        for c in s.chars() {
-            if c == '\n' {
-                let line = self.state.lines[self.state.cursor.row].clone();
-                let (before, after) = line.split_at(self.state.cursor.column);
-                self.state.lines[self.state.cursor.row] = before.to_string();
-                self.state
-                    .lines
-                    .insert(self.state.cursor.row + 1, after.to_string());
-                self.state.cursor = BufferPosition::new(self.state.cursor.row + 1, 0);
+        self.insert_at_cursor(s);
+    }
+
+    /// Delete the selection as its own undo step; it is already on the
+    /// clipboard and kill ring
+    fn cut(&mut self) {
+        if self.selection_range().is_none() {
+            return;
+        }
+        self.begin_transaction();
+        self.delete_selection();
+        self.commit_transaction();
+    }
+
+    /// Insert pasted text as its own undo step. With several cursors and
+    /// one line of text per cursor, as copied from a block, each cursor
+    /// gets its own line; otherwise every cursor gets the whole text.
+    fn paste(&mut self, text: &str) {
+        self.begin_transaction();
+        if self.state.extra_selections.is_empty() {
+            let start = self
+                .selection_range()
+                .map_or(self.state.cursor, |(start, _)| start);
+            self.type_string(text);
+            self.streak = Streak::Paste {
+                start,
+                end: self.state.cursor,
+                entry: (self.kill_ring.get(0) == Some(text)).then_some(0),
+            };
+        } else {
+            let lines: Vec<&str> = text.lines().collect();
+            if lines.len() == self.state.extra_selections.len() + 1 {
+                self.for_each_selection(false, false, |engine, index| {
+                    engine.type_string(lines[index])
+                });
             } else {
-                self.state.lines[self.state.cursor.row].insert(self.state.cursor.column, c);
-                self.state.cursor.column += c.len_utf8();
+                self.for_each_selection(false, false, |engine, _| engine.type_string(text));
             }
         }
+        self.commit_transaction();
+    }
+
+    /// Right after a paste, replace the pasted text with the kill ring entry
+    /// before the one pasted, wrapping around to the newest. Otherwise paste
+    /// the newest entry.
+    fn paste_from_history(&mut self) {
+        let Some(newest) = self.kill_ring.get(0).map(str::to_string) else {
+            return;
+        };
+        let (start, end, entry) = match self.previous_streak {
+            Streak::Paste { start, end, entry }
+                if end == self.state.cursor
+                    && self.selection_range().is_none()
+                    && self.state.extra_selections.is_empty() =>
+            {
+                (start, end, entry)
+            }
+            _ => {
+                self.paste(&newest);
+                return;
+            }
+        };
+        let index = entry.map_or(0, |index| (index + 1) % self.kill_ring.len());
+        let text = self.kill_ring.get(index).unwrap_or_default().to_string();
+        self.begin_transaction();
+        self.push_undo_state();
+        self.last_edit_time = None;
+        self.delete_range(start, end);
+        self.state.cursor = start;
+        self.insert_at_cursor(&text);
+        self.commit_transaction();
+        self.streak = Streak::Paste {
+            start,
+            end: self.state.cursor,
+            entry: Some(index),
+        };
+    }
+
+    /// Put killed text on the kill ring. Kills straight after another join
+    /// its entry, in front of it when `before`, as when deleting backwards.
+    fn kill(&mut self, text: String, before: bool) {
+        if text.is_empty() {
+            return;
+        }
+        if let Some(kills) = self.selection_kills.as_mut() {
+            kills.push((text, before));
+            return;
+        }
+        if self.streak == Streak::Kill || self.previous_streak == Streak::Kill {
+            self.kill_ring.extend_latest(&text, before);
+        } else {
+            self.kill_ring.push(text);
+        }
+        self.streak = Streak::Kill;
+    }
+
+    /// Delete text between two positions onto the kill ring
+    fn kill_range(&mut self, start: BufferPosition, end: BufferPosition, before: bool) {
+        let text = self.state.buffer.text_range(start, end);
+        self.kill(text, before);
+        self.delete_range(start, end);
     }
 
     fn backspace(&mut self) {
         self.push_undo_state();
         self.mark_edit_time();
 
+        let cursor = self.state.cursor;
         if let Some((start, end)) = self.selection_range() {
             self.delete_range(start, end);
             self.state.cursor = start;
             self.clear_selection();
-        } else if self.state.cursor.column > 0 {
-            let line = &self.state.lines[self.state.cursor.row];
-            let before = &line[..self.state.cursor.column];
-            if let Some((last_char_start, _)) = before.char_indices().last() {
-                self.state.lines[self.state.cursor.row].remove(last_char_start);
-                self.state.cursor.column = last_char_start;
-            }
-        } else if self.state.cursor.row > 0 {
-            let current_line = self.state.lines.remove(self.state.cursor.row);
-            self.state.cursor.row -= 1;
-            self.state.cursor.column = self.state.lines[self.state.cursor.row].len();
-            self.state.lines[self.state.cursor.row].push_str(&current_line);
+        } else if cursor.column > 0 {
+            let start = BufferPosition::new(
+                cursor.row,
+                self.state
+                    .buffer
+                    .prev_grapheme_column(cursor.row, cursor.column),
+            );
+            self.delete_range(start, cursor);
+            self.state.cursor = start;
+        } else if cursor.row > 0 {
+            let start = BufferPosition::new(cursor.row - 1, self.state.line_len(cursor.row - 1));
+            self.delete_range(start, cursor);
+            self.state.cursor = start;
         }
     }
 
@@ -212,18 +1727,19 @@ impl EditorEngine {
         self.push_undo_state();
         self.mark_edit_time();
 
+        let cursor = self.state.cursor;
         if let Some((start, end)) = self.selection_range() {
             self.delete_range(start, end);
             self.state.cursor = start;
             self.clear_selection();
-        } else {
-            let line_len = self.state.lines[self.state.cursor.row].len();
-            if self.state.cursor.column < line_len {
-                self.state.lines[self.state.cursor.row].remove(self.state.cursor.column);
-            } else if self.state.cursor.row + 1 < self.state.lines.len() {
-                let next_line = self.state.lines.remove(self.state.cursor.row + 1);
-                self.state.lines[self.state.cursor.row].push_str(&next_line);
-            }
+        } else if cursor.column < self.state.line_len(cursor.row) {
+            let end = self
+                .state
+                .buffer
+                .next_grapheme_column(cursor.row, cursor.column);
+            self.delete_range(cursor, BufferPosition::new(cursor.row, end));
+        } else if cursor.row + 1 < self.state.line_count() {
+            self.delete_range(cursor, BufferPosition::new(cursor.row + 1, 0));
         }
     }
 
@@ -267,57 +1783,53 @@ impl EditorEngine {
         self.last_edit_time = None;
         self.delete_selection();
 
-        let line = self.state.lines[self.state.cursor.row].clone();
+        let row = self.state.cursor.row;
+        let line = self.state.line(row).unwrap_or_default().into_owned();
 
-        if let Some((pattern, pattern_len, is_empty)) = Self::detect_list_pattern(&line) {
+        if let Some((pattern, _pattern_len, is_empty)) = Self::detect_list_pattern(&line) {
             if is_empty {
-                let before_pattern = &line[..line.len() - pattern_len];
-                self.state.lines[self.state.cursor.row] = before_pattern.to_string();
-                self.state
-                    .lines
-                    .insert(self.state.cursor.row + 1, String::new());
-                self.state.cursor = BufferPosition::new(self.state.cursor.row + 1, 0);
+                let line_start = BufferPosition::new(row, 0);
+                self.delete_range(line_start, BufferPosition::new(row, line.len()));
+                self.state.cursor = self.insert_at(line_start, self.state.line_ending.as_str());
             } else {
-                let (before, after) = line.split_at(self.state.cursor.column);
-                self.state.lines[self.state.cursor.row] = before.to_string();
-                self.state
-                    .lines
-                    .insert(self.state.cursor.row + 1, pattern.clone() + after);
-                self.state.cursor = BufferPosition::new(self.state.cursor.row + 1, pattern.len());
+                self.insert_at_cursor(&format!("\n{}", pattern));
             }
         } else {
-            let (before, after) = line.split_at(self.state.cursor.column);
-            self.state.lines[self.state.cursor.row] = before.to_string();
-            self.state
-                .lines
-                .insert(self.state.cursor.row + 1, after.to_string());
-            self.state.cursor = BufferPosition::new(self.state.cursor.row + 1, 0);
+            self.insert_at_cursor("\n");
         }
     }
 
     fn move_left(&mut self) {
         self.clear_selection();
-        if self.state.cursor.column > 0 {
-            let line = &self.state.lines[self.state.cursor.row];
-            let before = &line[..self.state.cursor.column];
-            if let Some(prev_char) = before.chars().last() {
-                self.state.cursor.column -= prev_char.len_utf8();
-            }
-        } else if self.state.cursor.row > 0 {
-            self.state.cursor.row -= 1;
-            self.state.cursor.column = self.state.lines[self.state.cursor.row].len();
-        }
+        self.step_cursor_left();
     }
 
     fn move_right(&mut self) {
         self.clear_selection();
-        let line_len = self.state.lines[self.state.cursor.row].len();
-        if self.state.cursor.column < line_len {
-            let after = &self.state.lines[self.state.cursor.row][self.state.cursor.column..];
-            if let Some(next_char) = after.chars().next() {
-                self.state.cursor.column += next_char.len_utf8();
-            }
-        } else if self.state.cursor.row + 1 < self.state.lines.len() {
+        self.step_cursor_right();
+    }
+
+    fn step_cursor_left(&mut self) {
+        let cursor = self.state.cursor;
+        if cursor.column > 0 {
+            self.state.cursor.column = self
+                .state
+                .buffer
+                .prev_grapheme_column(cursor.row, cursor.column);
+        } else if cursor.row > 0 {
+            self.state.cursor.row -= 1;
+            self.state.cursor.column = self.state.line_len(self.state.cursor.row);
+        }
+    }
+
+    fn step_cursor_right(&mut self) {
+        let cursor = self.state.cursor;
+        if cursor.column < self.state.line_len(cursor.row) {
+            self.state.cursor.column = self
+                .state
+                .buffer
+                .next_grapheme_column(cursor.row, cursor.column);
+        } else if cursor.row + 1 < self.state.line_count() {
             self.state.cursor.row += 1;
             self.state.cursor.column = 0;
         }
@@ -326,21 +1838,29 @@ impl EditorEngine {
     fn move_up(&mut self) {
         self.clear_selection();
         if self.state.cursor.row > 0 {
-            self.state.cursor.row -= 1;
-            let line_len = self.state.lines[self.state.cursor.row].len();
-            self.state.cursor.column = self.state.cursor.column.min(line_len);
+            self.move_cursor_to_row(self.state.cursor.row - 1);
         }
     }
 
     fn move_down(&mut self) {
         self.clear_selection();
-        if self.state.cursor.row + 1 < self.state.lines.len() {
-            self.state.cursor.row += 1;
-            let line_len = self.state.lines[self.state.cursor.row].len();
-            self.state.cursor.column = self.state.cursor.column.min(line_len);
+        if self.state.cursor.row + 1 < self.state.line_count() {
+            self.move_cursor_to_row(self.state.cursor.row + 1);
         }
     }
 
+    /// Move the cursor to another line, as close to the goal column as it fits
+    fn move_cursor_to_row(&mut self, row: usize) {
+        let goal = self.goal_column.unwrap_or_else(|| {
+            let line = self.state.line(self.state.cursor.row).unwrap_or_default();
+            display_width::column_to_cell(&line, self.state.cursor.column)
+        });
+        let line = self.state.line(row).unwrap_or_default();
+        let column = display_width::cell_to_column(&line, goal);
+        self.state.cursor = BufferPosition::new(row, column);
+        self.goal_column = Some(goal);
+    }
+
     fn move_to_line_start(&mut self) {
         self.clear_selection();
         self.state.cursor.column = 0;
@@ -348,7 +1868,7 @@ impl EditorEngine {
 
     fn move_to_line_end(&mut self) {
         self.clear_selection();
-        self.state.cursor.column = self.state.lines[self.state.cursor.row].len();
+        self.state.cursor.column = self.state.line_len(self.state.cursor.row);
     }
 
     fn move_word_left(&mut self) {
@@ -357,31 +1877,29 @@ impl EditorEngine {
         if self.state.cursor.column == 0 {
             if self.state.cursor.row > 0 {
                 self.state.cursor.row -= 1;
-                self.state.cursor.column = self.state.lines[self.state.cursor.row].len();
+                self.state.cursor.column = self.state.line_len(self.state.cursor.row);
             }
             return;
         }
 
-        let line = &self.state.lines[self.state.cursor.row];
+        let line = self.state.line(self.state.cursor.row).unwrap_or_default();
         let mut pos = self.state.cursor.column;
 
         // Skip whitespace
-        while pos > 0
-            && line
-                .chars()
-                .nth(pos - 1)
-                .map_or(false, |c| c.is_whitespace())
+        while let Some(c) = line[..pos]
+            .chars()
+            .next_back()
+            .filter(|c| c.is_whitespace())
         {
-            pos -= 1;
+            pos -= c.len_utf8();
         }
 
         // Skip word characters
-        while pos > 0 {
-            let ch = line.chars().nth(pos - 1);
-            if ch.map_or(false, |c| !c.is_alphanumeric() && c != '_') {
+        while let Some(c) = line[..pos].chars().next_back() {
+            if !c.is_alphanumeric() && c != '_' {
                 break;
             }
-            pos -= 1;
+            pos -= c.len_utf8();
         }
 
         self.state.cursor.column = pos;
@@ -390,10 +1908,10 @@ impl EditorEngine {
     fn move_word_right(&mut self) {
         self.clear_selection();
 
-        let line = &self.state.lines[self.state.cursor.row];
+        let line = self.state.line(self.state.cursor.row).unwrap_or_default();
 
         if self.state.cursor.column >= line.len() {
-            if self.state.cursor.row < self.state.lines.len() - 1 {
+            if self.state.cursor.row < self.state.line_count() - 1 {
                 self.state.cursor.row += 1;
                 self.state.cursor.column = 0;
             }
@@ -403,50 +1921,67 @@ impl EditorEngine {
         let mut pos = self.state.cursor.column;
 
         // Skip current word
-        while pos < line.len() {
-            let ch = line.chars().nth(pos);
-            if ch.map_or(false, |c| !c.is_alphanumeric() && c != '_') {
+        while let Some(c) = line[pos..].chars().next() {
+            if !c.is_alphanumeric() && c != '_' {
                 break;
             }
-            pos += 1;
+            pos += c.len_utf8();
         }
 
         // Skip whitespace
-        while pos < line.len() && line.chars().nth(pos).map_or(false, |c| c.is_whitespace()) {
-            pos += 1;
+        while let Some(c) = line[pos..].chars().next().filter(|c| c.is_whitespace()) {
+            pos += c.len_utf8();
         }
 
         self.state.cursor.column = pos;
     }
 
     fn undo(&mut self) {
-        if let Some(prev_state) = self.undo_stack.pop() {
-            self.redo_stack.push(self.state.clone_for_undo());
-            self.state = prev_state;
-            self.last_edit_time = None;
-        }
+        self.travel_history(UndoHistory::undo);
     }
 
     fn redo(&mut self) {
-        if let Some(next_state) = self.redo_stack.pop() {
-            self.undo_stack.push(self.state.clone_for_undo());
-            self.state = next_state;
+        self.travel_history(UndoHistory::redo);
+    }
+
+    /// Move through undo history; the next edit starts a new step
+    fn travel_history(&mut self, travel: impl FnOnce(&mut UndoHistory, &mut EditorState) -> bool) {
+        if travel(&mut self.history, &mut self.state) {
             self.last_edit_time = None;
         }
     }
 
+    /// The document at a state in undo history, without moving to it
+    pub fn preview_undo_state(&self, id: usize) -> Option<EditorState> {
+        self.history.state_at(id, &self.state)
+    }
+
     fn delete_line(&mut self) {
         self.push_undo_state();
         self.last_edit_time = None;
 
-        if self.state.lines.len() == 1 {
-            self.state.lines[0].clear();
+        let row = self.state.cursor.row;
+        let line_end = BufferPosition::new(row, self.state.line_len(row));
+        if self.state.line_count() == 1 {
+            self.kill_range(BufferPosition::zero(), line_end, false);
             self.state.cursor = BufferPosition::zero();
-        } else if self.state.cursor.row < self.state.lines.len() - 1 {
-            self.state.lines.remove(self.state.cursor.row);
+        } else if row < self.state.line_count() - 1 {
+            self.kill_range(
+                BufferPosition::new(row, 0),
+                BufferPosition::new(row + 1, 0),
+                false,
+            );
             self.state.cursor.column = 0;
         } else {
-            self.state.lines.remove(self.state.cursor.row);
+            // The last line goes on the kill ring with a line break after
+            // it, like the others, though the one before it is deleted
+            let prev_end = BufferPosition::new(row - 1, self.state.line_len(row - 1));
+            let line = self
+                .state
+                .buffer
+                .text_range(BufferPosition::new(row, 0), line_end);
+            self.kill(line + self.state.line_ending.as_str(), false);
+            self.delete_range(prev_end, line_end);
             self.state.cursor.row -= 1;
             self.state.cursor.column = 0;
         }
@@ -456,14 +1991,17 @@ impl EditorEngine {
     fn delete_to_beginning_of_line(&mut self) {
         self.push_undo_state();
         self.last_edit_time = None;
-        self.state.lines[self.state.cursor.row].replace_range(..self.state.cursor.column, "");
+        let line_start = BufferPosition::new(self.state.cursor.row, 0);
+        self.kill_range(line_start, self.state.cursor, true);
         self.state.cursor.column = 0;
     }
 
     fn delete_to_end_of_line(&mut self) {
         self.push_undo_state();
         self.last_edit_time = None;
-        self.state.lines[self.state.cursor.row].replace_range(self.state.cursor.column.., "");
+        let row = self.state.cursor.row;
+        let line_end = BufferPosition::new(row, self.state.line_len(row));
+        self.kill_range(self.state.cursor, line_end, false);
     }
 
     fn delete_word_left(&mut self) {
@@ -472,9 +2010,12 @@ impl EditorEngine {
         let end_pos = self.state.cursor;
 
         if start_pos.row == end_pos.row {
+            // Undo returns the cursor to where the deletion started
+            self.state.cursor = start_pos;
             self.push_undo_state();
             self.last_edit_time = None;
-            self.state.lines[end_pos.row].replace_range(end_pos.column..start_pos.column, "");
+            self.kill_range(end_pos, start_pos, true);
+            self.state.cursor = end_pos;
         }
     }
 
@@ -484,34 +2025,47 @@ impl EditorEngine {
         let end_pos = self.state.cursor;
 
         if start_pos.row == end_pos.row {
+            self.state.cursor = start_pos;
             self.push_undo_state();
             self.last_edit_time = None;
-            self.state.cursor = start_pos;
-            self.state.lines[start_pos.row].replace_range(start_pos.column..end_pos.column, "");
+            self.kill_range(start_pos, end_pos, false);
         }
     }
 
+    /// Swap line `upper` with the line below it
+    fn swap_with_next_line(&mut self, upper: usize) {
+        let upper_line = self.state.line(upper).unwrap_or_default().into_owned();
+        let lower_line = self.state.line(upper + 1).unwrap_or_default().into_owned();
+        let start = BufferPosition::new(upper, 0);
+        self.delete_range(start, BufferPosition::new(upper + 1, lower_line.len()));
+        self.insert_at(
+            start,
+            &format!(
+                "{}{}{}",
+                lower_line,
+                self.state.line_ending.as_str(),
+                upper_line
+            ),
+        );
+    }
+
     fn move_line_up(&mut self) {
         if self.state.cursor.row == 0 {
             return;
         }
         self.push_undo_state();
         self.last_edit_time = None;
-        self.state
-            .lines
-            .swap(self.state.cursor.row, self.state.cursor.row - 1);
+        self.swap_with_next_line(self.state.cursor.row - 1);
         self.state.cursor.row -= 1;
     }
 
     fn move_line_down(&mut self) {
-        if self.state.cursor.row + 1 >= self.state.lines.len() {
+        if self.state.cursor.row + 1 >= self.state.line_count() {
             return;
         }
         self.push_undo_state();
         self.last_edit_time = None;
-        self.state
-            .lines
-            .swap(self.state.cursor.row, self.state.cursor.row + 1);
+        self.swap_with_next_line(self.state.cursor.row);
         self.state.cursor.row += 1;
     }
 
@@ -521,29 +2075,38 @@ impl EditorEngine {
 
         if let Some((start, end)) = self.selection_range() {
             for row in start.row..=end.row {
-                self.state.lines[row].insert_str(0, "    ");
+                self.insert_at(BufferPosition::new(row, 0), "    ");
             }
             self.state.selection_anchor = Some(BufferPosition::new(start.row, start.column + 4));
             self.state.cursor = BufferPosition::new(end.row, end.column + 4);
         } else {
-            self.state.lines[self.state.cursor.row].insert_str(self.state.cursor.column, "    ");
-            self.state.cursor.column += 4;
+            self.insert_at_cursor("    ");
         }
     }
 
+    /// Number of leading spaces (up to four) that outdent would remove from a line
+    fn outdent_width(&self, row: usize) -> usize {
+        self.state
+            .line(row)
+            .unwrap_or_default()
+            .chars()
+            .take(4)
+            .take_while(|&c| c == ' ')
+            .count()
+    }
+
     fn outdent(&mut self) {
         self.push_undo_state();
         self.last_edit_time = None;
 
         if let Some((start, end)) = self.selection_range() {
             for row in start.row..=end.row {
-                let spaces_to_remove = self.state.lines[row]
-                    .chars()
-                    .take(4)
-                    .take_while(|&c| c == ' ')
-                    .count();
+                let spaces_to_remove = self.outdent_width(row);
                 if spaces_to_remove > 0 {
-                    self.state.lines[row].replace_range(..spaces_to_remove, "");
+                    self.delete_range(
+                        BufferPosition::new(row, 0),
+                        BufferPosition::new(row, spaces_to_remove),
+                    );
                 }
             }
             let new_start_col = start.column.saturating_sub(4);
@@ -551,49 +2114,46 @@ impl EditorEngine {
             self.state.selection_anchor = Some(BufferPosition::new(start.row, new_start_col));
             self.state.cursor = BufferPosition::new(end.row, new_end_col);
         } else {
-            let spaces_to_remove = self.state.lines[self.state.cursor.row]
-                .chars()
-                .take(4)
-                .take_while(|&c| c == ' ')
-                .count();
+            let row = self.state.cursor.row;
+            let spaces_to_remove = self.outdent_width(row);
             if spaces_to_remove > 0 {
-                self.state.lines[self.state.cursor.row].replace_range(..spaces_to_remove, "");
+                self.delete_range(
+                    BufferPosition::new(row, 0),
+                    BufferPosition::new(row, spaces_to_remove),
+                );
                 self.state.cursor.column =
                     self.state.cursor.column.saturating_sub(spaces_to_remove);
             }
         }
     }
 
+    fn convert_line_endings(&mut self, line_ending: LineEnding) {
+        if line_ending == LineEnding::Mixed {
+            return;
+        }
+        self.push_undo_state();
+        self.last_edit_time = None;
+
+        // Columns never include the line ending, so cursor and selection stay valid
+        let text = self.state.buffer.to_string();
+        let end = self.state.buffer.byte_to_position(text.len());
+        self.delete_range(BufferPosition::zero(), end);
+        self.insert_at(BufferPosition::zero(), &line_ending.normalize(&text));
+        self.state.line_ending = line_ending;
+    }
+
     fn select_left(&mut self) {
         if self.state.selection_anchor.is_none() {
             self.state.selection_anchor = Some(self.state.cursor);
         }
-        if self.state.cursor.column > 0 {
-            let line = &self.state.lines[self.state.cursor.row];
-            let before = &line[..self.state.cursor.column];
-            if let Some(prev_char) = before.chars().last() {
-                self.state.cursor.column -= prev_char.len_utf8();
-            }
-        } else if self.state.cursor.row > 0 {
-            self.state.cursor.row -= 1;
-            self.state.cursor.column = self.state.lines[self.state.cursor.row].len();
-        }
+        self.step_cursor_left();
     }
 
     fn select_right(&mut self) {
         if self.state.selection_anchor.is_none() {
             self.state.selection_anchor = Some(self.state.cursor);
         }
-        let line_len = self.state.lines[self.state.cursor.row].len();
-        if self.state.cursor.column < line_len {
-            let after = &self.state.lines[self.state.cursor.row][self.state.cursor.column..];
-            if let Some(next_char) = after.chars().next() {
-                self.state.cursor.column += next_char.len_utf8();
-            }
-        } else if self.state.cursor.row + 1 < self.state.lines.len() {
-            self.state.cursor.row += 1;
-            self.state.cursor.column = 0;
-        }
+        self.step_cursor_right();
     }
 
     fn select_up(&mut self) {
@@ -601,9 +2161,7 @@ impl EditorEngine {
             self.state.selection_anchor = Some(self.state.cursor);
         }
         if self.state.cursor.row > 0 {
-            self.state.cursor.row -= 1;
-            let line_len = self.state.lines[self.state.cursor.row].len();
-            self.state.cursor.column = self.state.cursor.column.min(line_len);
+            self.move_cursor_to_row(self.state.cursor.row - 1);
         }
     }
 
@@ -611,10 +2169,8 @@ impl EditorEngine {
         if self.state.selection_anchor.is_none() {
             self.state.selection_anchor = Some(self.state.cursor);
         }
-        if self.state.cursor.row + 1 < self.state.lines.len() {
-            self.state.cursor.row += 1;
-            let line_len = self.state.lines[self.state.cursor.row].len();
-            self.state.cursor.column = self.state.cursor.column.min(line_len);
+        if self.state.cursor.row + 1 < self.state.line_count() {
+            self.move_cursor_to_row(self.state.cursor.row + 1);
         }
     }
 
@@ -633,25 +2189,30 @@ impl EditorEngine {
     }
 
     fn select_all(&mut self) {
+        self.state.extra_selections.clear();
         self.state.selection_anchor = Some(BufferPosition::zero());
-        let last_row = self.state.lines.len().saturating_sub(1);
-        let last_col = self.state.lines[last_row].len();
+        let last_row = self.state.line_count().saturating_sub(1);
+        let last_col = self.state.line_len(last_row);
         self.state.cursor = BufferPosition::new(last_row, last_col);
     }
 
     /// Set cursor to specific position, clamping to valid bounds
     fn set_cursor_position(&mut self, row: usize, column: usize) {
         self.clear_selection();
-        let row = row.min(self.state.lines.len().saturating_sub(1));
-        let column = column.min(self.state.lines[row].len());
-        self.state.cursor = BufferPosition::new(row, column);
+        self.state.extra_selections.clear();
+        self.state.cursor = self
+            .state
+            .buffer
+            .clamp_position(BufferPosition::new(row, column));
     }
 
     /// Start a new selection at position
     fn start_selection(&mut self, row: usize, column: usize) {
-        let row = row.min(self.state.lines.len().saturating_sub(1));
-        let column = column.min(self.state.lines[row].len());
-        self.state.cursor = BufferPosition::new(row, column);
+        self.state.extra_selections.clear();
+        self.state.cursor = self
+            .state
+            .buffer
+            .clamp_position(BufferPosition::new(row, column));
         self.state.selection_anchor = Some(self.state.cursor);
     }
 
@@ -660,45 +2221,153 @@ impl EditorEngine {
         if self.state.selection_anchor.is_none() {
             self.state.selection_anchor = Some(self.state.cursor);
         }
-        let row = row.min(self.state.lines.len().saturating_sub(1));
-        let column = column.min(self.state.lines[row].len());
-        self.state.cursor = BufferPosition::new(row, column);
+        self.state.cursor = self
+            .state
+            .buffer
+            .clamp_position(BufferPosition::new(row, column));
     }
 
-    /// Load editor state from a file
+    /// Load editor state from a file.
+    /// If reading or decoding fails, saving is blocked until a load succeeds,
+    /// so autosave never overwrites a file that was not read correctly.
+    /// Binary files open as a `HexDocument` instead of text.
     pub fn load_from_file<P: AsRef<Path>>(&mut self, path: P) -> io::Result<()> {
-        let content = fs::read_to_string(path)?;
-        self.state.lines = if content.is_empty() {
-            vec![String::new()]
-        } else {
-            content.lines().map(|s| s.to_string()).collect()
-        };
+        let path = path.as_ref();
+        let bytes = fs::read(path).inspect_err(|_| self.save_blocked = true)?;
+        if binary::is_binary(&bytes) {
+            self.open_binary(bytes);
+            return Ok(());
+        }
+        let (content, encoding) = FileEncoding::decode(bytes, self.fallback_encoding)
+            .inspect_err(|_| self.save_blocked = true)?;
+        self.encoding = encoding;
+        self.save_blocked = false;
+        self.hex = None;
+        self.clear_bookmarks();
+        let cursors_before = self.change_start();
+        self.state.line_ending = LineEnding::detect(&content);
+        self.set_buffer(Buffer::from(content));
         self.state.cursor = BufferPosition::zero();
         self.state.selection_anchor = None;
-        self.undo_stack.clear();
-        self.redo_stack.clear();
+        self.state.extra_selections.clear();
+        self.history.clear();
+        self.transactions.clear();
         self.last_edit_time = None;
+
+        if let Some(dir) = &self.undo_dir {
+            undo_file::prune(dir);
+            if let Some(mut history) = undo_file::load(dir, path, &self.state.buffer) {
+                history.set_memory_limit(self.history.memory_limit());
+                self.history = history;
+            }
+        }
+        if let Some(dir) = &self.bookmark_dir {
+            let line_count = self.state.line_count();
+            for row in bookmark_file::load(dir, path) {
+                if row < line_count {
+                    self.add_bookmark(row);
+                }
+            }
+        }
+        self.finish_change(cursors_before);
         Ok(())
     }
 
-    /// Save editor state to a file
+    fn open_binary(&mut self, bytes: Vec<u8>) {
+        self.hex = Some(HexDocument::new(bytes));
+        self.save_blocked = false;
+        self.clear_bookmarks();
+        let cursors_before = self.change_start();
+        self.set_buffer(Buffer::new());
+        self.state.cursor = BufferPosition::zero();
+        self.state.selection_anchor = None;
+        self.state.extra_selections.clear();
+        self.history.clear();
+        self.transactions.clear();
+        self.last_edit_time = None;
+        self.finish_change(cursors_before);
+    }
+
+    /// Swap in a new document, recorded as an edit replacing all the text
+    fn set_buffer(&mut self, buffer: Buffer) {
+        if self.tracks_edits() {
+            let old = &self.state.buffer;
+            self.pending_edits.push(TextEdit {
+                start: BufferPosition::zero(),
+                old_end: old.byte_to_position(old.len_bytes()),
+                new_end: buffer.byte_to_position(buffer.len_bytes()),
+                text: buffer.to_string(),
+            });
+        }
+        self.state.buffer = buffer;
+    }
+
+    /// Save editor state to a file in the encoding it was loaded with.
+    /// Binary files are written back byte for byte. The file is replaced
+    /// atomically, so a crash mid-save never truncates it.
     pub fn save_to_file<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let content = self.state.lines.join("\n");
-        if let Some(parent) = path.as_ref().parent() {
+        self.save(path.as_ref(), false)
+    }
+
+    /// Save like `save_to_file`, returning once the file is on disk
+    pub fn save_to_file_synced<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        self.save(path.as_ref(), true)
+    }
+
+    fn save(&self, path: &Path, sync: bool) -> io::Result<()> {
+        if self.save_blocked {
+            return Err(io::Error::other(
+                "refusing to save: the file could not be loaded",
+            ));
+        }
+        if let Some(parent) = path.parent() {
             fs::create_dir_all(parent)?;
         }
-        fs::write(path, content)
+        if let Some(hex) = &self.hex {
+            return autosave::write_atomically(path, sync, |out| out.write_all(hex.bytes()));
+        }
+        autosave::write_atomically(path, sync, |out| {
+            if self.encoding == FileEncoding::UTF8 {
+                self.state.buffer.write_to(out)
+            } else {
+                out.write_all(&self.encoding.encode(&self.state.buffer.to_string())?)
+            }
+        })?;
+
+        if let Some(dir) = &self.undo_dir {
+            // Losing undo history is not worth failing the save over
+            let _ = undo_file::save(dir, path, &self.state.buffer, &self.history, sync);
+        }
+        if let Some(dir) = &self.bookmark_dir {
+            let _ = bookmark_file::save(dir, path, &self.bookmarks(), sync);
+        }
+        if let Some(kill_ring_file) = &self.kill_ring_file {
+            let _ = self.kill_ring.save(kill_ring_file, sync);
+        }
+        Ok(())
     }
 
-    /// Get default config file path
-    pub fn default_file_path() -> PathBuf {
+    /// Save the bookmarks of the document at `path` without the document,
+    /// as when only they changed, returning once they are on disk. They are
+    /// also saved with the document.
+    pub fn save_bookmarks<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        match &self.bookmark_dir {
+            Some(dir) => bookmark_file::save(dir, path.as_ref(), &self.bookmarks(), true),
+            None => Ok(()),
+        }
+    }
+
+    /// Directory for zrd's files, `~/.config/zrd`
+    pub fn config_dir() -> PathBuf {
         let home = std::env::var("HOME")
             .or_else(|_| std::env::var("USERPROFILE"))
             .unwrap_or_else(|_| ".".to_string());
-        PathBuf::from(home)
-            .join(".config")
-            .join("zrd")
-            .join("default.txt")
+        PathBuf::from(home).join(".config").join("zrd")
+    }
+
+    /// Get default config file path
+    pub fn default_file_path() -> PathBuf {
+        Self::config_dir().join("default.txt")
     }
 }
 
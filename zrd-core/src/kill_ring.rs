@@ -0,0 +1,117 @@
+//! Text recently cut or deleted by the line and word deletions, newest
+//! first, so that earlier kills can be pasted again
+//!
+//! The ring is saved as text, one entry per line with `\`, line feeds and
+//! carriage returns escaped, in a file such as `~/.config/zrd/kill-ring`.
+
+use crate::autosave;
+use std::collections::VecDeque;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// How many kills are remembered
+pub const CAPACITY: usize = 60;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct KillRing {
+    entries: VecDeque<String>,
+}
+
+impl KillRing {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a new newest entry, dropping the oldest past `CAPACITY`. Killing
+    /// the same text twice in a row keeps one entry.
+    pub fn push(&mut self, text: String) {
+        if text.is_empty() || self.entries.front() == Some(&text) {
+            return;
+        }
+        self.entries.push_front(text);
+        self.entries.truncate(CAPACITY);
+    }
+
+    /// Join text to the newest entry, in front of it when `before`, as
+    /// when deleting backwards
+    pub fn extend_latest(&mut self, text: &str, before: bool) {
+        match self.entries.front_mut() {
+            Some(latest) if before => latest.insert_str(0, text),
+            Some(latest) => latest.push_str(text),
+            None => self.push(text.to_string()),
+        }
+    }
+
+    /// The entry `index` kills back, 0 being the newest
+    pub fn get(&self, index: usize) -> Option<&str> {
+        self.entries.get(index).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Entries from newest to oldest
+    pub fn iter(&self) -> impl Iterator<Item = &str> {
+        self.entries.iter().map(String::as_str)
+    }
+
+    /// Save the ring to `path`, replacing it atomically so that a crash
+    /// never leaves it half written. With `sync`, it is on disk before this
+    /// returns.
+    pub fn save(&self, path: &Path, sync: bool) -> io::Result<()> {
+        let mut contents = String::new();
+        for entry in &self.entries {
+            for c in entry.chars() {
+                match c {
+                    '\\' => contents.push_str("\\\\"),
+                    '\n' => contents.push_str("\\n"),
+                    '\r' => contents.push_str("\\r"),
+                    c => contents.push(c),
+                }
+            }
+            contents.push('\n');
+        }
+        // Frontends save after every action; leave the file alone if unchanged
+        if fs::read_to_string(path).is_ok_and(|saved| saved == contents) {
+            return Ok(());
+        }
+
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        autosave::write_atomically(path, sync, |out| out.write_all(contents.as_bytes()))
+    }
+
+    /// The ring saved at `path`, or an empty one if there is none
+    pub fn load(path: &Path) -> Self {
+        let mut ring = Self::new();
+        let Ok(contents) = fs::read_to_string(path) else {
+            return ring;
+        };
+        for line in contents.lines().take(CAPACITY) {
+            let mut entry = String::with_capacity(line.len());
+            let mut chars = line.chars();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => match chars.next() {
+                        Some('n') => entry.push('\n'),
+                        Some('r') => entry.push('\r'),
+                        Some(escaped) => entry.push(escaped),
+                        None => {}
+                    },
+                    c => entry.push(c),
+                }
+            }
+            if !entry.is_empty() {
+                ring.entries.push_back(entry);
+            }
+        }
+        ring
+    }
+}
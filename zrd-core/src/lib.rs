@@ -1,7 +1,32 @@
 pub mod actions;
+pub mod anchor;
+pub mod autosave;
+pub mod binary;
+pub mod bookmark_file;
+pub mod buffer;
+pub mod change;
+pub mod clipboard;
+pub mod display_width;
+pub mod encoding;
 pub mod engine;
+pub mod kill_ring;
+pub mod line_ending;
+pub mod search;
 pub mod state;
+pub mod undo;
+pub mod undo_file;
 
 pub use actions::EditorAction;
+pub use anchor::{AnchorId, Gravity};
+pub use autosave::Autosave;
+pub use binary::HexDocument;
+pub use buffer::Buffer;
+pub use change::{ChangeEvent, TextEdit};
+pub use clipboard::{Clipboard, MemoryClipboard, Osc52Clipboard};
+pub use encoding::FileEncoding;
 pub use engine::EditorEngine;
-pub use state::{BufferPosition, EditorState};
+pub use kill_ring::KillRing;
+pub use line_ending::LineEnding;
+pub use search::SearchQuery;
+pub use state::{BlockSelection, BufferPosition, EditorState, Selection};
+pub use undo::UndoHistory;
@@ -0,0 +1,65 @@
+//! Line-ending detection and normalization
+
+use std::borrow::Cow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LineEnding {
+    /// Unix-style `\n`
+    #[default]
+    Lf,
+    /// Windows-style `\r\n`
+    Crlf,
+    /// Both styles appear in the file; new line breaks use `\n`
+    Mixed,
+}
+
+impl LineEnding {
+    /// Detect the line-ending style of a text. Text without any line
+    /// breaks is reported as `Lf`.
+    pub fn detect(text: &str) -> Self {
+        let mut crlf = 0;
+        let mut lf = 0;
+        let bytes = text.as_bytes();
+        for (i, &b) in bytes.iter().enumerate() {
+            if b == b'\n' {
+                if i > 0 && bytes[i - 1] == b'\r' {
+                    crlf += 1;
+                } else {
+                    lf += 1;
+                }
+            }
+        }
+        match (lf, crlf) {
+            (_, 0) => LineEnding::Lf,
+            (0, _) => LineEnding::Crlf,
+            _ => LineEnding::Mixed,
+        }
+    }
+
+    /// Separator used when inserting a new line break
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Crlf => "\r\n",
+            LineEnding::Lf | LineEnding::Mixed => "\n",
+        }
+    }
+
+    /// Rewrite every line break in `text` to this style
+    pub fn normalize(self, text: &str) -> Cow<'_, str> {
+        let has_crlf = text.contains("\r\n");
+        match self {
+            LineEnding::Lf | LineEnding::Mixed if has_crlf => {
+                Cow::Owned(text.replace("\r\n", "\n"))
+            }
+            LineEnding::Crlf if text.contains('\n') => {
+                let lf_only = if has_crlf {
+                    Cow::Owned(text.replace("\r\n", "\n"))
+                } else {
+                    Cow::Borrowed(text)
+                };
+                Cow::Owned(lf_only.replace('\n', "\r\n"))
+            }
+            _ => Cow::Borrowed(text),
+        }
+    }
+}
@@ -0,0 +1,186 @@
+//! Finding text in a document, and what to replace it with
+
+use regex::{Regex, RegexBuilder};
+use regex_syntax::hir::{Class, Hir, HirKind, Look};
+use regex_syntax::ParserBuilder;
+use std::ops::Range;
+
+/// What to find
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct SearchQuery {
+    pub text: String,
+    /// Match letter case exactly; otherwise either case matches
+    pub case_sensitive: bool,
+    /// Only match text that is not part of a longer word
+    pub whole_word: bool,
+    /// Treat `text` as a regular expression, in which `^` and `$` match at
+    /// line breaks
+    pub regex: bool,
+}
+
+impl SearchQuery {
+    pub fn new(text: impl Into<String>) -> Self {
+        Self {
+            text: text.into(),
+            ..Self::default()
+        }
+    }
+
+    /// Whether the query can be searched for: false for an invalid regular
+    /// expression
+    pub fn is_valid(&self) -> bool {
+        !self.regex || self.text.is_empty() || self.compile().is_ok()
+    }
+
+    /// Byte ranges of the matches in `haystack`, in order and not
+    /// overlapping. An empty query, an invalid regular expression and
+    /// matches of no text find nothing.
+    pub fn find_in(&self, haystack: &str) -> Vec<Range<usize>> {
+        let mut matches = Vec::new();
+        if self.text.is_empty() {
+            return matches;
+        }
+        if self.regex {
+            if let Ok(regex) = self.compile() {
+                matches.extend(regex.find_iter(haystack).map(|found| found.range()).filter(
+                    |found| {
+                        !found.is_empty() && (!self.whole_word || is_whole_word(haystack, found))
+                    },
+                ));
+            }
+            return matches;
+        }
+        let mut from = 0;
+        while let Some(found) = self.find_from(haystack, from) {
+            if !self.whole_word || is_whole_word(haystack, &found) {
+                from = found.end;
+                matches.push(found);
+            } else {
+                // Try again from the next character
+                from = found.start
+                    + haystack[found.start..]
+                        .chars()
+                        .next()
+                        .map_or(1, char::len_utf8);
+            }
+        }
+        matches
+    }
+
+    /// Whether a match may run over a line break, or depend on more than
+    /// the line it is on, as `\A` does. Matches of other queries are found
+    /// again only on the lines an edit touched.
+    pub fn can_span_lines(&self) -> bool {
+        if !self.regex {
+            return self.text.contains('\n');
+        }
+        // An invalid pattern finds nothing, on any line
+        ParserBuilder::new()
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+            .parse(&self.text)
+            .is_ok_and(|hir| spans_lines(&hir))
+    }
+
+    /// The text to replace each of `matches` in `haystack` with. For regular
+    /// expressions, `$1` or `${name}` in `replacement` stands for what a
+    /// group matched, `$0` for the whole match and `$$` for a `$`.
+    pub fn replacements(
+        &self,
+        haystack: &str,
+        matches: &[Range<usize>],
+        replacement: &str,
+    ) -> Vec<String> {
+        let regex = self.regex.then(|| self.compile().ok()).flatten();
+        matches
+            .iter()
+            .map(|found| {
+                let captures = regex
+                    .as_ref()
+                    .and_then(|regex| regex.captures_at(haystack, found.start))
+                    .filter(|captures| captures.get_match().range() == *found);
+                let mut text = String::new();
+                match captures {
+                    Some(captures) => captures.expand(replacement, &mut text),
+                    None => text.push_str(replacement),
+                }
+                text
+            })
+            .collect()
+    }
+
+    fn compile(&self) -> Result<Regex, regex::Error> {
+        RegexBuilder::new(&self.text)
+            .case_insensitive(!self.case_sensitive)
+            .multi_line(true)
+            .build()
+    }
+
+    /// The first match starting at or after byte `from`
+    fn find_from(&self, haystack: &str, from: usize) -> Option<Range<usize>> {
+        let rest = haystack.get(from..)?;
+        if self.case_sensitive {
+            return rest
+                .find(&self.text)
+                .map(|i| from + i..from + i + self.text.len());
+        }
+        rest.char_indices().find_map(|(i, _)| {
+            let len = self.match_len(&rest[i..])?;
+            Some(from + i..from + i + len)
+        })
+    }
+
+    /// Length in bytes of the case-insensitive match at the start of `text`
+    fn match_len(&self, text: &str) -> Option<usize> {
+        let mut chars = text.char_indices();
+        for wanted in self.text.chars() {
+            let (_, c) = chars.next()?;
+            if c != wanted && !c.to_lowercase().eq(wanted.to_lowercase()) {
+                return None;
+            }
+        }
+        Some(chars.next().map_or(text.len(), |(i, _)| i))
+    }
+}
+
+/// Whether what `hir` matches can include a line break or depends on the
+/// start or end of the whole text
+fn spans_lines(hir: &Hir) -> bool {
+    match hir.kind() {
+        HirKind::Empty => false,
+        HirKind::Literal(literal) => literal.0.contains(&b'\n'),
+        HirKind::Class(Class::Unicode(class)) => class
+            .ranges()
+            .iter()
+            .any(|range| range.start() <= '\n' && '\n' <= range.end()),
+        HirKind::Class(Class::Bytes(class)) => class
+            .ranges()
+            .iter()
+            .any(|range| range.start() <= b'\n' && b'\n' <= range.end()),
+        HirKind::Look(look) => matches!(look, Look::Start | Look::End),
+        HirKind::Repetition(repetition) => spans_lines(&repetition.sub),
+        HirKind::Capture(capture) => spans_lines(&capture.sub),
+        HirKind::Concat(hirs) | HirKind::Alternation(hirs) => hirs.iter().any(spans_lines),
+    }
+}
+
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+/// Whether the match at `range` is not joined to word characters on either
+/// side, so "cat" is found in "a cat." but not in "concat"
+fn is_whole_word(haystack: &str, range: &Range<usize>) -> bool {
+    let found = &haystack[range.clone()];
+    let joined = |outside: Option<char>, edge: Option<char>| {
+        outside.is_some_and(is_word_char) && edge.is_some_and(is_word_char)
+    };
+    !joined(
+        haystack[..range.start].chars().next_back(),
+        found.chars().next(),
+    ) && !joined(
+        haystack[range.end..].chars().next(),
+        found.chars().next_back(),
+    )
+}
@@ -1,6 +1,11 @@
 //! Platform-agnostic editor state
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+use crate::{Buffer, LineEnding};
+use std::borrow::Cow;
+use std::fmt;
+use std::ops::{Range, RangeInclusive};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct BufferPosition {
     pub row: usize,
     pub column: usize,
@@ -16,14 +21,84 @@ impl BufferPosition {
     }
 }
 
+/// A cursor together with its optional selection anchor
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Selection {
+    pub cursor: BufferPosition,
+    pub anchor: Option<BufferPosition>,
+}
+
+impl Selection {
+    pub fn new(cursor: BufferPosition, anchor: Option<BufferPosition>) -> Self {
+        Self { cursor, anchor }
+    }
+
+    /// A cursor with nothing selected
+    pub fn caret(cursor: BufferPosition) -> Self {
+        Self::new(cursor, None)
+    }
+
+    /// Start and end of the selected text in document order; both are the
+    /// cursor when nothing is selected
+    pub fn range(&self) -> (BufferPosition, BufferPosition) {
+        match self.anchor {
+            Some(anchor) if anchor < self.cursor => (anchor, self.cursor),
+            Some(anchor) => (self.cursor, anchor),
+            None => (self.cursor, self.cursor),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        let (start, end) = self.range();
+        start == end
+    }
+}
+
+/// A rectangle spanning the rows and display columns (terminal cells)
+/// between two corners. Corners may lie past the end of short lines.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockSelection {
+    pub anchor_row: usize,
+    pub anchor_cell: usize,
+    pub cursor_row: usize,
+    pub cursor_cell: usize,
+}
+
+impl BlockSelection {
+    /// An empty block at a single cell
+    pub fn at(row: usize, cell: usize) -> Self {
+        Self {
+            anchor_row: row,
+            anchor_cell: cell,
+            cursor_row: row,
+            cursor_cell: cell,
+        }
+    }
+
+    /// Rows covered by the block
+    pub fn rows(&self) -> RangeInclusive<usize> {
+        self.anchor_row.min(self.cursor_row)..=self.anchor_row.max(self.cursor_row)
+    }
+
+    /// Display cells covered on each row; empty when the block is a column
+    /// of cursors
+    pub fn cells(&self) -> Range<usize> {
+        self.anchor_cell.min(self.cursor_cell)..self.anchor_cell.max(self.cursor_cell)
+    }
+}
+
 #[derive(Clone)]
 pub struct EditorState {
-    /// Lines of text in the buffer
-    pub lines: Vec<String>,
+    /// Text of the buffer
+    pub buffer: Buffer,
+    /// Line-ending style detected on load, used for new line breaks
+    pub line_ending: LineEnding,
     /// Cursor position (row, column in bytes)
     pub cursor: BufferPosition,
     /// Selection anchor for text selection
     pub selection_anchor: Option<BufferPosition>,
+    /// Cursors besides the primary `cursor`, each with its own selection
+    pub extra_selections: Vec<Selection>,
     /// Font size (may be ignored by TUI)
     pub font_size: f32,
 }
@@ -31,52 +106,72 @@ pub struct EditorState {
 impl EditorState {
     pub fn new() -> Self {
         Self {
-            lines: vec![String::new()],
+            buffer: Buffer::new(),
+            line_ending: LineEnding::default(),
             cursor: BufferPosition::zero(),
             selection_anchor: None,
+            extra_selections: Vec::new(),
             font_size: 14.0,
         }
     }
 
     pub fn clone_for_undo(&self) -> Self {
         Self {
-            lines: self.lines.clone(),
+            buffer: self.buffer.clone(),
+            line_ending: self.line_ending,
             cursor: self.cursor,
             selection_anchor: self.selection_anchor,
+            extra_selections: self.extra_selections.clone(),
             font_size: self.font_size,
         }
     }
 
-    /// Get the content as a single string
-    pub fn to_string(&self) -> String {
-        self.lines.join("\n")
-    }
-
     /// Create from a string
     pub fn from_string(content: String) -> Self {
-        let lines: Vec<String> = if content.is_empty() {
-            vec![String::new()]
-        } else {
-            content.split('\n').map(|s| s.to_string()).collect()
-        };
-
         Self {
-            lines,
+            line_ending: LineEnding::detect(&content),
+            buffer: Buffer::from(content),
             cursor: BufferPosition::zero(),
             selection_anchor: None,
+            extra_selections: Vec::new(),
             font_size: 14.0,
         }
     }
 
+    /// The primary cursor and its selection
+    pub fn primary_selection(&self) -> Selection {
+        Selection::new(self.cursor, self.selection_anchor)
+    }
+
+    /// Every cursor and selection, primary included, in document order
+    pub fn selections(&self) -> Vec<Selection> {
+        let mut selections = self.extra_selections.clone();
+        selections.push(self.primary_selection());
+        selections.sort_by_key(|selection| selection.range());
+        selections
+    }
+
     pub fn line_count(&self) -> usize {
-        self.lines.len()
+        self.buffer.line_count()
     }
 
-    pub fn line(&self, row: usize) -> Option<&str> {
-        self.lines.get(row).map(|s| s.as_str())
+    pub fn line(&self, row: usize) -> Option<Cow<'_, str>> {
+        self.buffer.line(row)
     }
 
     pub fn line_len(&self, row: usize) -> usize {
-        self.lines.get(row).map(|s| s.len()).unwrap_or(0)
+        self.buffer.line_len(row)
+    }
+}
+
+impl Default for EditorState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for EditorState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.buffer, f)
     }
 }
@@ -0,0 +1,1068 @@
+//! Operation-based undo tree
+//!
+//! Each undo step records the edits made to the buffer as insertions and
+//! deletions at byte offsets, together with the cursors before and after, so
+//! memory grows with the size of the edits rather than the document.
+//!
+//! Steps form a tree: editing after an undo starts a new branch instead of
+//! discarding the undone steps, and any state in the tree can be reached
+//! again by undoing to the common ancestor and redoing down the other branch.
+//! The oldest steps are dropped once the recorded text exceeds a memory limit.
+
+use crate::{Buffer, BufferPosition, EditorState, LineEnding, Selection, TextEdit};
+use std::cmp::Reverse;
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::io::{self, Read, Write};
+use std::mem;
+use std::time::{Duration, SystemTime};
+
+/// Default cap on the memory held by the undo history (64 MiB)
+pub const DEFAULT_MEMORY_LIMIT: usize = 64 * 1024 * 1024;
+
+/// A single change to the buffer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EditOperation {
+    /// `text` was inserted at byte `offset`
+    Insert { offset: usize, text: String },
+    /// `text` was removed from byte `offset`
+    Delete { offset: usize, text: String },
+}
+
+impl EditOperation {
+    fn apply(&self, buffer: &mut Buffer) -> TextEdit {
+        match self {
+            Self::Insert { offset, text } => insert(buffer, *offset, text),
+            Self::Delete { offset, text } => delete(buffer, *offset, text.len()),
+        }
+    }
+
+    fn revert(&self, buffer: &mut Buffer) -> TextEdit {
+        match self {
+            Self::Insert { offset, text } => delete(buffer, *offset, text.len()),
+            Self::Delete { offset, text } => insert(buffer, *offset, text),
+        }
+    }
+
+    fn memory_size(&self) -> usize {
+        let (Self::Insert { text, .. } | Self::Delete { text, .. }) = self;
+        mem::size_of::<Self>() + text.len()
+    }
+}
+
+fn insert(buffer: &mut Buffer, offset: usize, text: &str) -> TextEdit {
+    let pos = buffer.byte_to_position(offset);
+    let end = buffer.insert(pos, text);
+    TextEdit::insertion(pos, end, text)
+}
+
+fn delete(buffer: &mut Buffer, offset: usize, len: usize) -> TextEdit {
+    let start = buffer.byte_to_position(offset);
+    let end = buffer.byte_to_position(offset + len);
+    buffer.delete(start, end);
+    TextEdit::deletion(start, end)
+}
+
+/// The cursors and selections of an editor, primary first
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CursorState {
+    pub primary: Selection,
+    pub extras: Vec<Selection>,
+}
+
+impl CursorState {
+    pub fn of(state: &EditorState) -> Self {
+        Self {
+            primary: state.primary_selection(),
+            extras: state.extra_selections.clone(),
+        }
+    }
+
+    /// Put these cursors back into `state`, clamped to its text
+    pub fn restore(&self, state: &mut EditorState) {
+        let buffer = &state.buffer;
+        let clamp = |selection: Selection| Selection {
+            cursor: buffer.clamp_position(selection.cursor),
+            anchor: selection.anchor.map(|anchor| buffer.clamp_position(anchor)),
+        };
+        let primary = clamp(self.primary);
+        state.extra_selections = self.extras.iter().map(|&s| clamp(s)).collect();
+        state.cursor = primary.cursor;
+        state.selection_anchor = primary.anchor;
+    }
+
+    fn memory_size(&self) -> usize {
+        mem::size_of::<Self>() + self.extras.len() * mem::size_of::<Selection>()
+    }
+}
+
+/// Edits undone and redone together
+#[derive(Debug, Clone)]
+pub struct UndoStep {
+    pub operations: Vec<EditOperation>,
+    pub cursors_before: CursorState,
+    pub cursors_after: CursorState,
+    pub line_ending_before: LineEnding,
+    pub line_ending_after: LineEnding,
+}
+
+impl UndoStep {
+    fn memory_size(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.cursors_before.memory_size()
+            + self.cursors_after.memory_size()
+            + self
+                .operations
+                .iter()
+                .map(EditOperation::memory_size)
+                .sum::<usize>()
+    }
+
+    fn revert(&self, state: &mut EditorState, edits: &mut Vec<TextEdit>) {
+        for operation in self.operations.iter().rev() {
+            edits.push(operation.revert(&mut state.buffer));
+        }
+        state.line_ending = self.line_ending_before;
+        self.cursors_before.restore(state);
+    }
+
+    fn apply(&self, state: &mut EditorState, edits: &mut Vec<TextEdit>) {
+        for operation in &self.operations {
+            edits.push(operation.apply(&mut state.buffer));
+        }
+        state.line_ending = self.line_ending_after;
+        self.cursors_after.restore(state);
+    }
+}
+
+/// Id of the state before any recorded step
+pub const ORIGINAL_STATE: usize = 0;
+
+#[derive(Debug, Clone)]
+struct Node {
+    parent: usize,
+    children: Vec<usize>,
+    /// Child that redo follows: the one last undone, or the newest
+    redo_child: Option<usize>,
+    /// The edits leading here from `parent`; `None` for the original state
+    step: Option<UndoStep>,
+    /// When the step was last changed
+    time: SystemTime,
+}
+
+impl Node {
+    fn memory_size(&self) -> usize {
+        mem::size_of::<Self>()
+            + self.children.len() * mem::size_of::<usize>()
+            + self.step.as_ref().map_or(0, UndoStep::memory_size)
+    }
+}
+
+/// One state in the undo tree, for browsing history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HistoryEntry {
+    /// Id to pass to `EditorAction::GoToUndoState`
+    pub id: usize,
+    /// The state this one was edited from; `None` for the original state
+    pub parent: Option<usize>,
+    pub time: SystemTime,
+    /// Bytes inserted and deleted by the step leading here
+    pub inserted: usize,
+    pub deleted: usize,
+    /// Number of steps from the original state
+    pub depth: usize,
+    pub is_current: bool,
+}
+
+/// Tree of `UndoStep`s with a memory limit
+#[derive(Debug, Clone)]
+pub struct UndoHistory {
+    /// Nodes by id; ids increase with creation, so this is oldest first
+    nodes: BTreeMap<usize, Node>,
+    current: usize,
+    next_id: usize,
+    /// Cursors and line ending when the step about to be recorded began;
+    /// the step itself is created by its first edit
+    pending_step: Option<(CursorState, LineEnding)>,
+    /// Whether the current step changed since its cursors-after were recorded
+    dirty: bool,
+    memory_limit: usize,
+    memory_used: usize,
+    /// Edits made by moving through history, until taken
+    replayed: Vec<TextEdit>,
+}
+
+impl UndoHistory {
+    pub fn new() -> Self {
+        let root = Node {
+            parent: ORIGINAL_STATE,
+            children: Vec::new(),
+            redo_child: None,
+            step: None,
+            time: SystemTime::now(),
+        };
+        Self {
+            memory_used: root.memory_size(),
+            nodes: BTreeMap::from([(ORIGINAL_STATE, root)]),
+            current: ORIGINAL_STATE,
+            next_id: ORIGINAL_STATE + 1,
+            pending_step: None,
+            dirty: false,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            replayed: Vec::new(),
+        }
+    }
+
+    pub fn memory_limit(&self) -> usize {
+        self.memory_limit
+    }
+
+    /// Set the memory limit, dropping the oldest steps to fit. The current
+    /// step is always kept, however large.
+    pub fn set_memory_limit(&mut self, limit: usize) {
+        self.memory_limit = limit;
+        self.enforce_memory_limit();
+    }
+
+    /// Approximate memory held by the tree, in bytes
+    pub fn memory_used(&self) -> usize {
+        self.memory_used
+    }
+
+    /// Number of steps that can be undone from the current state
+    pub fn len(&self) -> usize {
+        self.ancestors(self.current).count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.current == ORIGINAL_STATE
+    }
+
+    pub fn can_redo(&self) -> bool {
+        self.redo_target(self.current).is_some()
+    }
+
+    /// Id of the current state
+    pub fn current(&self) -> usize {
+        self.current
+    }
+
+    pub fn clear(&mut self) {
+        let memory_limit = self.memory_limit;
+        *self = Self::new();
+        self.memory_limit = memory_limit;
+    }
+
+    /// Every state in the tree, oldest first
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        let mut depths = BTreeMap::new();
+        self.nodes
+            .iter()
+            .map(|(&id, node)| {
+                let depth = if id == ORIGINAL_STATE {
+                    0
+                } else {
+                    depths.get(&node.parent).map_or(0, |depth| depth + 1)
+                };
+                depths.insert(id, depth);
+                let (inserted, deleted) = node.step.iter().flat_map(|step| &step.operations).fold(
+                    (0, 0),
+                    |(inserted, deleted), operation| match operation {
+                        EditOperation::Insert { text, .. } => (inserted + text.len(), deleted),
+                        EditOperation::Delete { text, .. } => (inserted, deleted + text.len()),
+                    },
+                );
+                HistoryEntry {
+                    id,
+                    parent: node.step.as_ref().map(|_| node.parent),
+                    time: node.time,
+                    inserted,
+                    deleted,
+                    depth,
+                    is_current: id == self.current,
+                }
+            })
+            .collect()
+    }
+
+    /// Start a new step from the given state. It is created by the first
+    /// edit recorded after this; until then nothing changes.
+    pub fn begin(&mut self, state: &EditorState) {
+        self.pending_step = Some((CursorState::of(state), state.line_ending));
+    }
+
+    /// Record an edit into the current step. A new step is started if one
+    /// was begun, or if editing here would change the start of a branch.
+    pub fn record(&mut self, operation: EditOperation, state: &EditorState) {
+        let starts_branch =
+            self.current == ORIGINAL_STATE || !self.nodes[&self.current].children.is_empty();
+        if starts_branch && self.pending_step.is_none() {
+            self.begin(state);
+        }
+        if let Some((cursors, line_ending)) = self.pending_step.take() {
+            self.push_node(UndoStep {
+                operations: Vec::new(),
+                cursors_before: cursors.clone(),
+                cursors_after: cursors,
+                line_ending_before: line_ending,
+                line_ending_after: line_ending,
+            });
+        }
+
+        self.memory_used += operation.memory_size();
+        if let Some(step) = self.current_step_mut() {
+            step.operations.push(operation);
+        }
+        self.dirty = true;
+        self.enforce_memory_limit();
+    }
+
+    fn push_node(&mut self, step: UndoStep) {
+        let id = self.next_id;
+        self.next_id += 1;
+        let node = Node {
+            parent: self.current,
+            children: Vec::new(),
+            redo_child: None,
+            step: Some(step),
+            time: SystemTime::now(),
+        };
+        self.memory_used += node.memory_size();
+        self.nodes.insert(id, node);
+        if let Some(parent) = self.nodes.get_mut(&self.current) {
+            parent.children.push(id);
+            parent.redo_child = Some(id);
+            self.memory_used += mem::size_of::<usize>();
+        }
+        self.current = id;
+    }
+
+    fn current_step_mut(&mut self) -> Option<&mut UndoStep> {
+        self.nodes
+            .get_mut(&self.current)
+            .and_then(|node| node.step.as_mut())
+    }
+
+    /// Note the cursors and line ending after the current step's edits
+    pub fn finish(&mut self, state: &EditorState) {
+        if !mem::take(&mut self.dirty) {
+            return;
+        }
+        let Some(node) = self.nodes.get_mut(&self.current) else {
+            return;
+        };
+        node.time = SystemTime::now();
+        if let Some(step) = node.step.as_mut() {
+            self.memory_used -= step.cursors_after.memory_size();
+            step.cursors_after = CursorState::of(state);
+            step.line_ending_after = state.line_ending;
+            self.memory_used += step.cursors_after.memory_size();
+        }
+    }
+
+    /// A marker for `merge_since`
+    pub fn checkpoint(&self) -> usize {
+        self.next_id
+    }
+
+    /// Combine the steps created since `checkpoint` into one, which undoes
+    /// to `cursors_before`
+    pub fn merge_since(&mut self, checkpoint: usize, cursors_before: CursorState) {
+        let mut chain: Vec<usize> = self
+            .ancestors(self.current)
+            .take_while(|&id| id >= checkpoint)
+            .collect();
+        let Some(first) = chain.pop() else {
+            return;
+        };
+        chain.reverse();
+
+        let mut operations = Vec::new();
+        let mut last_step = None;
+        for id in chain {
+            let node = self.remove_node(id);
+            if let Some(step) = node.step {
+                operations.extend(step.operations);
+                last_step = Some((step.cursors_after, step.line_ending_after));
+            }
+        }
+        let node = self.nodes.get_mut(&first).expect("merged step exists");
+        self.memory_used -= node.memory_size();
+        node.children.clear();
+        node.redo_child = None;
+        if let Some(step) = node.step.as_mut() {
+            step.cursors_before = cursors_before;
+            step.operations.extend(operations);
+            if let Some((cursors_after, line_ending_after)) = last_step {
+                step.cursors_after = cursors_after;
+                step.line_ending_after = line_ending_after;
+            }
+        }
+        self.memory_used += node.memory_size();
+        self.current = first;
+    }
+
+    /// Edits made to the document by undo, redo and other moves through
+    /// history since the last call
+    pub fn take_replayed(&mut self) -> Vec<TextEdit> {
+        mem::take(&mut self.replayed)
+    }
+
+    /// Return to state `start` and forget every step created since
+    /// `checkpoint` was taken. If `start` has since been folded into the
+    /// original state, that is returned to instead.
+    pub fn rollback(&mut self, checkpoint: usize, start: usize, state: &mut EditorState) {
+        let target = if self.nodes.contains_key(&start) {
+            start
+        } else {
+            ORIGINAL_STATE
+        };
+        self.go_to(target, state);
+        let created: Vec<usize> = self.nodes.range(checkpoint..).map(|(&id, _)| id).collect();
+        // Newest first, so children go before their parents
+        for id in created.into_iter().rev() {
+            self.remove_node(id);
+        }
+        self.pending_step = None;
+        self.dirty = false;
+    }
+
+    /// Revert the current step. Returns whether there was one.
+    pub fn undo(&mut self, state: &mut EditorState) -> bool {
+        let Some(node) = self.nodes.get(&self.current) else {
+            return false;
+        };
+        let Some(step) = &node.step else {
+            return false;
+        };
+        step.revert(state, &mut self.replayed);
+        let (id, parent) = (self.current, node.parent);
+        if let Some(parent_node) = self.nodes.get_mut(&parent) {
+            parent_node.redo_child = Some(id);
+        }
+        self.current = parent;
+        self.pending_step = None;
+        self.dirty = false;
+        true
+    }
+
+    /// Reapply the step last undone from here, or the newest one. Returns
+    /// whether there was one.
+    pub fn redo(&mut self, state: &mut EditorState) -> bool {
+        match self.redo_target(self.current) {
+            Some(child) => {
+                self.enter_child(child, state);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn redo_target(&self, id: usize) -> Option<usize> {
+        let node = self.nodes.get(&id)?;
+        node.redo_child.or_else(|| node.children.last().copied())
+    }
+
+    fn enter_child(&mut self, child: usize, state: &mut EditorState) {
+        if let Some(step) = self.nodes.get(&child).and_then(|node| node.step.as_ref()) {
+            step.apply(state, &mut self.replayed);
+        }
+        if let Some(node) = self.nodes.get_mut(&self.current) {
+            node.redo_child = Some(child);
+        }
+        self.current = child;
+        self.pending_step = None;
+        self.dirty = false;
+    }
+
+    /// Move to any state in the tree. Returns whether it exists.
+    pub fn go_to(&mut self, target: usize, state: &mut EditorState) -> bool {
+        let Some((up, down)) = self.path(target) else {
+            return false;
+        };
+        for _ in 0..up {
+            self.undo(state);
+        }
+        for child in down {
+            self.enter_child(child, state);
+        }
+        true
+    }
+
+    /// How the given state would look, without moving to it
+    pub fn state_at(&self, target: usize, state: &EditorState) -> Option<EditorState> {
+        let (up, down) = self.path(target)?;
+        let mut state = state.clone();
+        let mut edits = Vec::new();
+        for id in self.ancestors(self.current).take(up) {
+            if let Some(step) = &self.nodes[&id].step {
+                step.revert(&mut state, &mut edits);
+            }
+        }
+        for id in down {
+            if let Some(step) = &self.nodes[&id].step {
+                step.apply(&mut state, &mut edits);
+            }
+        }
+        Some(state)
+    }
+
+    /// Steps to undo from the current state, then steps to redo, to reach
+    /// `target`
+    fn path(&self, target: usize) -> Option<(usize, Vec<usize>)> {
+        if !self.nodes.contains_key(&target) {
+            return None;
+        }
+        let mut target_path: Vec<usize> = self.ancestors(target).collect();
+        let current_path: Vec<usize> = self.ancestors(self.current).collect();
+        let up = current_path
+            .iter()
+            .position(|id| target_path.contains(id))
+            .unwrap_or(current_path.len());
+        if let Some(&common) = current_path.get(up) {
+            let keep = target_path.iter().position(|&id| id == common).unwrap_or(0);
+            target_path.truncate(keep);
+        }
+        target_path.reverse();
+        Some((up, target_path))
+    }
+
+    /// A state and its ancestors, excluding the original state
+    fn ancestors(&self, id: usize) -> impl Iterator<Item = usize> + '_ {
+        std::iter::successors(Some(id), |id| self.nodes.get(id).map(|node| node.parent))
+            .take_while(|&id| id != ORIGINAL_STATE)
+    }
+
+    /// Move to the newest state last changed at least `duration` before the
+    /// current one, like Vim's `:earlier`
+    pub fn earlier(&mut self, duration: Duration, state: &mut EditorState) -> bool {
+        let time = self.nodes[&self.current].time;
+        let target = time.checked_sub(duration).unwrap_or(SystemTime::UNIX_EPOCH);
+        let id = self
+            .nodes
+            .iter()
+            .rev()
+            .find(|(_, node)| node.time <= target)
+            .map_or(ORIGINAL_STATE, |(&id, _)| id);
+        id != self.current && self.go_to(id, state)
+    }
+
+    /// Move to the newest state last changed at most `duration` after the
+    /// current one, like Vim's `:later`
+    pub fn later(&mut self, duration: Duration, state: &mut EditorState) -> bool {
+        let time = self.nodes[&self.current].time;
+        let target = time.checked_add(duration).unwrap_or(time);
+        let id = self
+            .nodes
+            .iter()
+            .rev()
+            .find(|(_, node)| node.time <= target)
+            .map_or(self.current, |(&id, _)| id);
+        id != self.current && self.go_to(id, state)
+    }
+
+    /// Switch to the latest state of the branch before (`newer` false) or
+    /// after the current one, at the nearest point where branches split
+    pub fn switch_branch(&mut self, newer: bool, state: &mut EditorState) -> bool {
+        let sibling = self.ancestors(self.current).find_map(|id| {
+            let siblings = &self.nodes[&self.nodes[&id].parent].children;
+            let index = siblings.iter().position(|&sibling| sibling == id)?;
+            if newer {
+                siblings.get(index + 1).copied()
+            } else {
+                index.checked_sub(1).map(|index| siblings[index])
+            }
+        });
+        let Some(mut target) = sibling else {
+            return false;
+        };
+        while let Some(child) = self.redo_target(target) {
+            target = child;
+        }
+        self.go_to(target, state)
+    }
+
+    fn remove_node(&mut self, id: usize) -> Node {
+        let node = self.nodes.remove(&id).expect("removed step exists");
+        self.memory_used -= node.memory_size();
+        if let Some(parent) = self.nodes.get_mut(&node.parent) {
+            parent.children.retain(|&child| child != id);
+            if parent.redo_child == Some(id) {
+                parent.redo_child = None;
+            }
+            self.memory_used -= mem::size_of::<usize>();
+        }
+        node
+    }
+
+    /// Drop steps until the limit is met: the first step after the original
+    /// state once it has no alternatives and leads to the current state,
+    /// which moves the original state forward, and otherwise the leaf
+    /// furthest from the current state
+    fn enforce_memory_limit(&mut self) {
+        while self.memory_used > self.memory_limit {
+            let current_path: Vec<usize> = self.ancestors(self.current).collect();
+            let root_children = &self.nodes[&ORIGINAL_STATE].children;
+            // Folding a step the text hasn't been through would leave the
+            // original state out of step with the text
+            let trunk = match root_children[..] {
+                [id] if id != self.current && current_path.contains(&id) => Some(id),
+                _ => None,
+            };
+            let removable = trunk.or_else(|| self.furthest_leaf());
+            let Some(id) = removable else {
+                break;
+            };
+
+            let node = self.remove_node(id);
+            if node.parent == ORIGINAL_STATE && !node.children.is_empty() {
+                // The step is folded into the original state
+                for child in &node.children {
+                    if let Some(child) = self.nodes.get_mut(child) {
+                        child.parent = ORIGINAL_STATE;
+                    }
+                }
+                self.memory_used += node.children.len() * mem::size_of::<usize>();
+                if let Some(root) = self.nodes.get_mut(&ORIGINAL_STATE) {
+                    root.children = node.children;
+                    root.redo_child = node.redo_child;
+                    root.time = node.time;
+                }
+            }
+        }
+    }
+
+    /// The leaf furthest from the current state, other than it; the oldest
+    /// of those equally far
+    fn furthest_leaf(&self) -> Option<usize> {
+        let mut distances = BTreeMap::from([(self.current, 0)]);
+        let mut queue = VecDeque::from([self.current]);
+        while let Some(id) = queue.pop_front() {
+            let node = &self.nodes[&id];
+            let distance = distances[&id] + 1;
+            let parent = (id != ORIGINAL_STATE).then_some(node.parent);
+            for next in parent.into_iter().chain(node.children.iter().copied()) {
+                if let Entry::Vacant(entry) = distances.entry(next) {
+                    entry.insert(distance);
+                    queue.push_back(next);
+                }
+            }
+        }
+        self.nodes
+            .iter()
+            .skip(1)
+            .filter(|&(&id, node)| id != self.current && node.children.is_empty())
+            .max_by_key(|&(id, _)| (distances.get(id), Reverse(*id)))
+            .map(|(&id, _)| id)
+    }
+}
+
+/// Identifies the encoding written by `UndoHistory::write_to`
+const MAGIC: &[u8; 4] = b"ZRDU";
+const FORMAT_VERSION: u32 = 1;
+
+/// Bytes written before the nodes: magic, version, current, next id, count
+const FILE_HEADER_BYTES: usize = 4 + 4 + 3 * 8;
+/// Bytes written for a node besides its children and step: id, parent,
+/// redo child, child count, time and whether there is a step
+const NODE_HEADER_BYTES: usize = 4 * 8 + 12 + 1;
+
+/// Steps chosen by `UndoHistory::steps_within`
+struct KeptSteps {
+    ids: BTreeSet<usize>,
+    /// The oldest step kept before the current state, if steps before it
+    /// were left out, making it the first after the original state
+    first: Option<usize>,
+}
+
+/// Counts the bytes written to it
+struct ByteCounter(usize);
+
+impl Write for ByteCounter {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        self.0 += bytes.len();
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl UndoHistory {
+    /// Write the tree in a compact binary form. The memory limit is not
+    /// saved; `read_from` uses the default.
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<()> {
+        self.write_nodes(writer, None)
+    }
+
+    /// Write the tree as `write_to` does, in about `max_bytes`: the newest
+    /// steps leading to the current state, then the newest of the others.
+    /// Steps left out before the current state move the original state
+    /// forward, as dropping them from memory does. The tree is unchanged.
+    pub fn write_to_within<W: Write>(&self, writer: W, max_bytes: usize) -> io::Result<()> {
+        let mut counter = ByteCounter(0);
+        self.write_to(&mut counter)?;
+        if counter.0 <= max_bytes {
+            return self.write_to(writer);
+        }
+        let kept = self.steps_within(max_bytes);
+        self.write_nodes(writer, Some(&kept))
+    }
+
+    /// The steps to write to fit in `max_bytes`; see `write_to_within`
+    fn steps_within(&self, max_bytes: usize) -> KeptSteps {
+        let size = |id: usize| {
+            let node = &self.nodes[&id];
+            let mut counter = ByteCounter(0);
+            if let Some(step) = &node.step {
+                // Counting cannot fail
+                let _ = write_step(&mut counter, step);
+            }
+            NODE_HEADER_BYTES + node.children.len() * 8 + counter.0
+        };
+        // The current step is always kept, however large
+        let current = match self.current {
+            ORIGINAL_STATE => 0,
+            id => size(id),
+        };
+        let mut budget =
+            max_bytes.saturating_sub(FILE_HEADER_BYTES + size(ORIGINAL_STATE) + current);
+        let mut take = |cost: usize| {
+            let fits = cost <= budget;
+            if fits {
+                budget -= cost;
+            }
+            fits
+        };
+
+        let path: BTreeSet<usize> = self.ancestors(self.current).collect();
+        let mut ids = BTreeSet::new();
+        let mut first = None;
+        for id in self.ancestors(self.current) {
+            if id != self.current && !take(size(id)) {
+                break;
+            }
+            ids.insert(id);
+            first = Some(id);
+        }
+        let folded = first.is_some_and(|id| self.nodes[&id].parent != ORIGINAL_STATE);
+
+        // Other steps come with the steps between them and those kept. Once
+        // the original state moved, steps off the dropped ones can't be.
+        for &id in self.nodes.keys().rev() {
+            let mut chain = Vec::new();
+            let mut next = id;
+            let reachable = loop {
+                if ids.contains(&next) {
+                    break true;
+                }
+                if next == ORIGINAL_STATE {
+                    break !folded;
+                }
+                if path.contains(&next) {
+                    break false;
+                }
+                chain.push(next);
+                next = self.nodes[&next].parent;
+            };
+            if reachable && !chain.is_empty() && take(chain.iter().map(|&id| size(id)).sum()) {
+                ids.extend(chain);
+            }
+        }
+        KeptSteps {
+            ids,
+            first: first.filter(|_| folded),
+        }
+    }
+
+    /// Write all nodes, or only the original state and the `kept` steps
+    fn write_nodes<W: Write>(&self, mut writer: W, kept: Option<&KeptSteps>) -> io::Result<()> {
+        let is_kept =
+            |id: usize| id == ORIGINAL_STATE || kept.is_none_or(|kept| kept.ids.contains(&id));
+        let folded_into_root = kept.and_then(|kept| kept.first);
+
+        let w = &mut writer;
+        w.write_all(MAGIC)?;
+        w.write_all(&FORMAT_VERSION.to_le_bytes())?;
+        write_usize(w, self.current)?;
+        write_usize(w, self.next_id)?;
+        let count = self.nodes.keys().filter(|&&id| is_kept(id)).count();
+        write_usize(w, count)?;
+        for (&id, node) in self.nodes.iter().filter(|&(&id, _)| is_kept(id)) {
+            let parent = if folded_into_root == Some(id) {
+                ORIGINAL_STATE
+            } else {
+                node.parent
+            };
+            let (children, redo_child, time) = match folded_into_root {
+                // The original state is now the one the first kept step was
+                // made from
+                Some(first) if id == ORIGINAL_STATE => {
+                    let time = self.nodes[&self.nodes[&first].parent].time;
+                    (vec![first], Some(first), time)
+                }
+                _ => {
+                    let children = node.children.iter().copied().filter(|&id| is_kept(id));
+                    let redo_child = node.redo_child.filter(|&id| is_kept(id));
+                    (children.collect(), redo_child, node.time)
+                }
+            };
+            write_usize(w, id)?;
+            write_usize(w, parent)?;
+            write_option(w, redo_child.filter(|&id| is_kept(id)))?;
+            write_usize(w, children.len())?;
+            for &child in &children {
+                write_usize(w, child)?;
+            }
+            let time = time
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default();
+            write_u64(w, time.as_secs())?;
+            w.write_all(&time.subsec_nanos().to_le_bytes())?;
+            match &node.step {
+                Some(step) => {
+                    w.write_all(&[1])?;
+                    write_step(w, step)?;
+                }
+                None => w.write_all(&[0])?,
+            }
+        }
+        writer.flush()
+    }
+
+    /// Read a tree written by `write_to`
+    pub fn read_from<R: Read>(mut reader: R) -> io::Result<Self> {
+        let r = &mut reader;
+        let mut magic = [0; 4];
+        r.read_exact(&mut magic)?;
+        let mut version = [0; 4];
+        r.read_exact(&mut version)?;
+        if &magic != MAGIC || u32::from_le_bytes(version) != FORMAT_VERSION {
+            return Err(invalid_data("not an undo history file"));
+        }
+
+        let current = read_usize(r)?;
+        let next_id = read_usize(r)?;
+        let count = read_usize(r)?;
+        let mut nodes = BTreeMap::new();
+        for _ in 0..count {
+            let id = read_usize(r)?;
+            let parent = read_usize(r)?;
+            let redo_child = read_option(r)?;
+            let children = (0..read_usize(r)?)
+                .map(|_| read_usize(r))
+                .collect::<io::Result<_>>()?;
+            let secs = read_u64(r)?;
+            let mut nanos = [0; 4];
+            r.read_exact(&mut nanos)?;
+            let time = SystemTime::UNIX_EPOCH
+                + Duration::new(secs, u32::from_le_bytes(nanos).min(999_999_999));
+            let step = match read_u8(r)? {
+                0 => None,
+                _ => Some(read_step(r)?),
+            };
+            nodes.insert(
+                id,
+                Node {
+                    parent,
+                    children,
+                    redo_child,
+                    step,
+                    time,
+                },
+            );
+        }
+
+        let is_consistent = nodes.contains_key(&ORIGINAL_STATE)
+            && nodes.contains_key(&current)
+            && nodes.iter().all(|(&id, node)| {
+                (id == ORIGINAL_STATE || (node.parent < id && nodes.contains_key(&node.parent)))
+                    && node.children.iter().all(|child| nodes.contains_key(child))
+                    && id < next_id
+            });
+        if !is_consistent {
+            return Err(invalid_data("undo history is corrupt"));
+        }
+
+        let memory_used = nodes.values().map(Node::memory_size).sum();
+        Ok(Self {
+            nodes,
+            current,
+            next_id,
+            pending_step: None,
+            dirty: false,
+            memory_limit: DEFAULT_MEMORY_LIMIT,
+            replayed: Vec::new(),
+            memory_used,
+        })
+    }
+}
+
+fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn write_u64<W: Write>(w: &mut W, value: u64) -> io::Result<()> {
+    w.write_all(&value.to_le_bytes())
+}
+
+fn write_usize<W: Write>(w: &mut W, value: usize) -> io::Result<()> {
+    write_u64(w, value as u64)
+}
+
+fn write_option<W: Write>(w: &mut W, value: Option<usize>) -> io::Result<()> {
+    write_u64(w, value.map_or(u64::MAX, |value| value as u64))
+}
+
+fn write_str<W: Write>(w: &mut W, text: &str) -> io::Result<()> {
+    write_usize(w, text.len())?;
+    w.write_all(text.as_bytes())
+}
+
+fn write_position<W: Write>(w: &mut W, pos: BufferPosition) -> io::Result<()> {
+    write_usize(w, pos.row)?;
+    write_usize(w, pos.column)
+}
+
+fn write_cursors<W: Write>(w: &mut W, cursors: &CursorState) -> io::Result<()> {
+    write_usize(w, cursors.extras.len() + 1)?;
+    for selection in std::iter::once(&cursors.primary).chain(&cursors.extras) {
+        write_position(w, selection.cursor)?;
+        match selection.anchor {
+            Some(anchor) => {
+                w.write_all(&[1])?;
+                write_position(w, anchor)?;
+            }
+            None => w.write_all(&[0])?,
+        }
+    }
+    Ok(())
+}
+
+fn line_ending_code(line_ending: LineEnding) -> u8 {
+    match line_ending {
+        LineEnding::Lf => 0,
+        LineEnding::Crlf => 1,
+        LineEnding::Mixed => 2,
+    }
+}
+
+fn write_step<W: Write>(w: &mut W, step: &UndoStep) -> io::Result<()> {
+    w.write_all(&[
+        line_ending_code(step.line_ending_before),
+        line_ending_code(step.line_ending_after),
+    ])?;
+    write_cursors(w, &step.cursors_before)?;
+    write_cursors(w, &step.cursors_after)?;
+    write_usize(w, step.operations.len())?;
+    for operation in &step.operations {
+        let (kind, offset, text) = match operation {
+            EditOperation::Insert { offset, text } => (0, offset, text),
+            EditOperation::Delete { offset, text } => (1, offset, text),
+        };
+        w.write_all(&[kind])?;
+        write_usize(w, *offset)?;
+        write_str(w, text)?;
+    }
+    Ok(())
+}
+
+fn read_u8<R: Read>(r: &mut R) -> io::Result<u8> {
+    let mut byte = [0; 1];
+    r.read_exact(&mut byte)?;
+    Ok(byte[0])
+}
+
+fn read_u64<R: Read>(r: &mut R) -> io::Result<u64> {
+    let mut bytes = [0; 8];
+    r.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+fn read_usize<R: Read>(r: &mut R) -> io::Result<usize> {
+    usize::try_from(read_u64(r)?).map_err(|_| invalid_data("value out of range"))
+}
+
+fn read_option<R: Read>(r: &mut R) -> io::Result<Option<usize>> {
+    match read_u64(r)? {
+        u64::MAX => Ok(None),
+        value => usize::try_from(value)
+            .map(Some)
+            .map_err(|_| invalid_data("value out of range")),
+    }
+}
+
+fn read_str<R: Read>(r: &mut R) -> io::Result<String> {
+    let len = read_usize(r)?;
+    let mut bytes = Vec::new();
+    r.take(len as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    String::from_utf8(bytes).map_err(|_| invalid_data("text is not UTF-8"))
+}
+
+fn read_position<R: Read>(r: &mut R) -> io::Result<BufferPosition> {
+    Ok(BufferPosition::new(read_usize(r)?, read_usize(r)?))
+}
+
+fn read_cursors<R: Read>(r: &mut R) -> io::Result<CursorState> {
+    let mut selections = Vec::new();
+    for _ in 0..read_usize(r)? {
+        let cursor = read_position(r)?;
+        let anchor = match read_u8(r)? {
+            0 => None,
+            _ => Some(read_position(r)?),
+        };
+        selections.push(Selection::new(cursor, anchor));
+    }
+    if selections.is_empty() {
+        return Err(invalid_data("step has no cursor"));
+    }
+    let primary = selections.remove(0);
+    Ok(CursorState {
+        primary,
+        extras: selections,
+    })
+}
+
+fn read_line_ending<R: Read>(r: &mut R) -> io::Result<LineEnding> {
+    match read_u8(r)? {
+        0 => Ok(LineEnding::Lf),
+        1 => Ok(LineEnding::Crlf),
+        2 => Ok(LineEnding::Mixed),
+        _ => Err(invalid_data("unknown line ending")),
+    }
+}
+
+fn read_step<R: Read>(r: &mut R) -> io::Result<UndoStep> {
+    let line_ending_before = read_line_ending(r)?;
+    let line_ending_after = read_line_ending(r)?;
+    let cursors_before = read_cursors(r)?;
+    let cursors_after = read_cursors(r)?;
+    let mut operations = Vec::new();
+    for _ in 0..read_usize(r)? {
+        let kind = read_u8(r)?;
+        let offset = read_usize(r)?;
+        let text = read_str(r)?;
+        operations.push(match kind {
+            0 => EditOperation::Insert { offset, text },
+            1 => EditOperation::Delete { offset, text },
+            _ => return Err(invalid_data("unknown edit operation")),
+        });
+    }
+    Ok(UndoStep {
+        operations,
+        cursors_before,
+        cursors_after,
+        line_ending_before,
+        line_ending_after,
+    })
+}
+
+impl Default for UndoHistory {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,143 @@
+//! Undo history kept between sessions
+//!
+//! Each document's undo tree is saved in a sidecar file, named after a hash
+//! of the document's path, in a directory such as `~/.config/zrd/undo/`. The
+//! sidecar records the path and a hash of the text the history ends at, so
+//! it is only restored when the document is reopened unchanged.
+
+use crate::autosave;
+use crate::{Buffer, UndoHistory};
+use std::fs;
+use std::io::{self, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+/// Largest history saved for one document; older steps are dropped to fit
+pub const MAX_FILE_BYTES: usize = 8 * 1024 * 1024;
+
+/// Sidecars not written for this long are deleted
+pub const MAX_AGE: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+/// The least recently written sidecars are deleted once the directory
+/// holds more than this
+pub const MAX_TOTAL_BYTES: u64 = 64 * 1024 * 1024;
+
+const EXTENSION: &str = "undo";
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is stable across releases
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Write for Fnv {
+    fn write(&mut self, bytes: &[u8]) -> io::Result<usize> {
+        for &byte in bytes {
+            self.0 = (self.0 ^ u64::from(byte)).wrapping_mul(0x0000_0100_0000_01b3);
+        }
+        Ok(bytes.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hash of a document's text
+pub fn content_hash(buffer: &Buffer) -> u64 {
+    let mut hasher = Fnv::new();
+    // Writing to the hasher cannot fail
+    let _ = buffer.write_to(&mut hasher);
+    hasher.0
+}
+
+/// Absolute form of a document path, so every way of naming the same file
+/// finds the same sidecars
+pub(crate) fn document_key(path: &Path) -> String {
+    fs::canonicalize(path)
+        .unwrap_or_else(|_| path.to_path_buf())
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// File in `dir` holding the sidecar with the given extension for a
+/// document key
+pub(crate) fn sidecar_path(dir: &Path, key: &str, extension: &str) -> PathBuf {
+    let mut hasher = Fnv::new();
+    let _ = hasher.write_all(key.as_bytes());
+    dir.join(format!("{:016x}.{}", hasher.0, extension))
+}
+
+/// Save the history of the document at `path`, whose text is now `buffer`.
+/// Nothing is written if there is no history. The sidecar is replaced
+/// atomically and, with `sync`, is on disk before this returns.
+pub fn save(
+    dir: &Path,
+    path: &Path,
+    buffer: &Buffer,
+    history: &UndoHistory,
+    sync: bool,
+) -> io::Result<()> {
+    if history.is_empty() && !history.can_redo() {
+        return Ok(());
+    }
+    fs::create_dir_all(dir)?;
+    let key = document_key(path);
+    let sidecar = sidecar_path(dir, &key, EXTENSION);
+    autosave::write_atomically(&sidecar, sync, |out| {
+        out.write_all(&(key.len() as u64).to_le_bytes())?;
+        out.write_all(key.as_bytes())?;
+        out.write_all(&content_hash(buffer).to_le_bytes())?;
+        history.write_to_within(out, MAX_FILE_BYTES)
+    })
+}
+
+/// The saved history of the document at `path`, if there is one and it
+/// ends at the text in `buffer`
+pub fn load(dir: &Path, path: &Path, buffer: &Buffer) -> Option<UndoHistory> {
+    let key = document_key(path);
+    let mut reader = BufReader::new(fs::File::open(sidecar_path(dir, &key, EXTENSION)).ok()?);
+
+    let mut len = [0; 8];
+    reader.read_exact(&mut len).ok()?;
+    let mut saved_key = Vec::new();
+    (&mut reader)
+        .take(u64::from_le_bytes(len))
+        .read_to_end(&mut saved_key)
+        .ok()?;
+    let mut hash = [0; 8];
+    reader.read_exact(&mut hash).ok()?;
+    if saved_key != key.as_bytes() || u64::from_le_bytes(hash) != content_hash(buffer) {
+        return None;
+    }
+    UndoHistory::read_from(reader).ok()
+}
+
+/// Delete sidecars older than `MAX_AGE`, then the least recently written
+/// ones until the directory is within `MAX_TOTAL_BYTES`
+pub fn prune(dir: &Path) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+    let now = SystemTime::now();
+    let mut sidecars: Vec<(SystemTime, u64, PathBuf)> = entries
+        .flatten()
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == EXTENSION))
+        .filter_map(|entry| {
+            let metadata = entry.metadata().ok()?;
+            Some((metadata.modified().ok()?, metadata.len(), entry.path()))
+        })
+        .collect();
+    sidecars.sort();
+
+    let mut total: u64 = sidecars.iter().map(|(_, len, _)| len).sum();
+    for (modified, len, path) in sidecars {
+        let expired = now.duration_since(modified).unwrap_or_default() > MAX_AGE;
+        if (expired || total > MAX_TOTAL_BYTES) && fs::remove_file(path).is_ok() {
+            total -= len;
+        }
+    }
+}
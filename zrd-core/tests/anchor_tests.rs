@@ -0,0 +1,104 @@
+mod common;
+
+use common::engine_with;
+use zrd_core::anchor::transform;
+use zrd_core::{BufferPosition, EditorAction, Gravity, TextEdit};
+
+fn pos(row: usize, column: usize) -> BufferPosition {
+    BufferPosition::new(row, column)
+}
+
+#[test]
+fn test_anchor_follows_edits_before_it() {
+    let mut engine = engine_with("one two\nthree");
+    let anchor = engine.create_anchor(pos(0, 4), Gravity::Left);
+    let below = engine.create_anchor(pos(1, 2), Gravity::Left);
+
+    engine.handle_action(EditorAction::TypeString("zero\n".to_string()));
+    assert_eq!(engine.anchor_position(anchor), Some(pos(1, 4)));
+    assert_eq!(engine.anchor_position(below), Some(pos(2, 2)));
+
+    engine.handle_action(EditorAction::Delete);
+    assert_eq!(engine.anchor_position(anchor), Some(pos(1, 3)));
+
+    // Joining lines moves anchors on the joined line
+    engine.handle_action(EditorAction::SetCursorPosition { row: 1, column: 6 });
+    engine.handle_action(EditorAction::Delete);
+    assert_eq!(engine.state().to_string(), "zero\nne twothree");
+    assert_eq!(engine.anchor_position(below), Some(pos(1, 8)));
+
+    // Edits after an anchor leave it alone
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::TypeString("!".to_string()));
+    assert_eq!(engine.anchor_position(anchor), Some(pos(1, 3)));
+}
+
+#[test]
+fn test_gravity_at_insertion_point() {
+    let mut engine = engine_with("ab");
+    let left = engine.create_anchor(pos(0, 1), Gravity::Left);
+    let right = engine.create_anchor(pos(0, 1), Gravity::Right);
+
+    engine.insert_text(pos(0, 1), "xy");
+    assert_eq!(engine.anchor_position(left), Some(pos(0, 1)));
+    assert_eq!(engine.anchor_position(right), Some(pos(0, 3)));
+}
+
+#[test]
+fn test_deleting_around_anchor_invalidates_it() {
+    let mut engine = engine_with("keep delete keep");
+    let inside = engine.create_anchor(pos(0, 7), Gravity::Left);
+    let at_start = engine.create_anchor(pos(0, 5), Gravity::Left);
+    let at_end = engine.create_anchor(pos(0, 12), Gravity::Right);
+
+    engine.delete_text(pos(0, 5), pos(0, 12));
+    assert_eq!(engine.anchor_position(inside), None);
+    assert_eq!(engine.anchor_position(at_start), Some(pos(0, 5)));
+    assert_eq!(engine.anchor_position(at_end), Some(pos(0, 5)));
+
+    // Undo restores the text but not what it marked. The restored text is
+    // inserted where the other anchors are, so gravity sorts them again.
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.anchor_position(inside), None);
+    assert_eq!(engine.anchor_position(at_start), Some(pos(0, 5)));
+    assert_eq!(engine.anchor_position(at_end), Some(pos(0, 12)));
+}
+
+#[test]
+fn test_anchors_follow_undo_and_multiple_cursors() {
+    let mut engine = engine_with("a\nb\nc");
+    let anchor = engine.create_anchor(pos(2, 1), Gravity::Left);
+
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::TypeString("--".to_string()));
+    assert_eq!(engine.anchor_position(anchor), Some(pos(2, 1)));
+
+    engine.insert_text(pos(0, 0), "\n");
+    assert_eq!(engine.anchor_position(anchor), Some(pos(3, 1)));
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.anchor_position(anchor), Some(pos(2, 1)));
+}
+
+#[test]
+fn test_remove_anchor() {
+    let mut engine = engine_with("text");
+    let anchor = engine.create_anchor(pos(0, 2), Gravity::Left);
+
+    assert!(engine.remove_anchor(anchor));
+    assert!(!engine.remove_anchor(anchor));
+    assert_eq!(engine.anchor_position(anchor), None);
+}
+
+#[test]
+fn test_transform_replacement() {
+    let edit = TextEdit {
+        start: pos(1, 2),
+        old_end: pos(3, 4),
+        new_end: pos(1, 5),
+        text: "abc".to_string(),
+    };
+    assert_eq!(transform(pos(1, 1), Gravity::Left, &edit), Some(pos(1, 1)));
+    assert_eq!(transform(pos(2, 0), Gravity::Left, &edit), None);
+    assert_eq!(transform(pos(3, 6), Gravity::Left, &edit), Some(pos(1, 7)));
+    assert_eq!(transform(pos(5, 1), Gravity::Right, &edit), Some(pos(3, 1)));
+}
@@ -0,0 +1,139 @@
+mod common;
+
+use common::{engine_with, temp_dir, temp_file};
+use std::fs;
+use std::time::{Duration, Instant};
+use zrd_core::autosave::{write_atomically, MAX_DELAY};
+use zrd_core::{Autosave, EditorAction, EditorEngine};
+
+#[test]
+fn test_save_replaces_file_atomically() {
+    let dir = temp_dir("atomic-save");
+    let path = dir.join("doc.txt");
+    fs::write(&path, "old contents that are longer").unwrap();
+
+    engine_with("new").save_to_file(&path).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "new");
+    engine_with("synced").save_to_file_synced(&path).unwrap();
+    assert_eq!(fs::read_to_string(&path).unwrap(), "synced");
+    // No temporary files are left behind
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    // A failed write leaves the file as it was
+    let failed = write_atomically(&path, false, |out| {
+        out.write_all(b"partial")?;
+        Err(std::io::Error::other("disk full"))
+    });
+    assert!(failed.is_err());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "synced");
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_save_keeps_permissions_and_symlinks() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let dir = temp_dir("atomic-save-unix");
+    let target = dir.join("target.txt");
+    let link = dir.join("link.txt");
+    fs::write(&target, "old").unwrap();
+    fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+    symlink(&target, &link).unwrap();
+
+    engine_with("new").save_to_file(&link).unwrap();
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(fs::read_to_string(&target).unwrap(), "new");
+    let mode = fs::metadata(&target).unwrap().permissions().mode();
+    assert_eq!(mode & 0o777, 0o600);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_autosave_waits_for_a_pause() {
+    let dir = temp_dir("autosave-delay");
+    let path = dir.join("doc.txt");
+    let engine = engine_with("hello");
+
+    let mut autosave = Autosave::new(&path);
+    autosave.set_delay(Duration::from_secs(60));
+    assert!(!autosave.is_pending());
+    assert_eq!(autosave.due_at(), None);
+
+    autosave.schedule(&engine);
+    let due = autosave.due_at().unwrap();
+    autosave.schedule(&engine);
+    // Each edit pushes the save back
+    assert!(autosave.due_at().unwrap() >= due);
+    assert!(!autosave.save_if_due(&engine).unwrap());
+    assert!(!path.exists());
+
+    assert!(autosave.flush(&engine, true).unwrap());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello");
+    assert!(!autosave.is_pending());
+    assert!(!autosave.flush(&engine, true).unwrap());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_autosave_saves_when_due() {
+    let dir = temp_dir("autosave-due");
+    let path = dir.join("doc.txt");
+    let engine = engine_with("hello");
+
+    let mut autosave = Autosave::new(&path);
+    autosave.schedule(&engine);
+    let waited = autosave.due_at().unwrap() - Instant::now();
+    assert!(waited <= MAX_DELAY);
+
+    autosave.set_delay(Duration::ZERO);
+    assert!(autosave.save_if_due(&engine).unwrap());
+    assert_eq!(fs::read_to_string(&path).unwrap(), "hello");
+    assert!(!autosave.save_if_due(&engine).unwrap());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_failed_autosave_stays_pending() {
+    let dir = temp_dir("autosave-failed");
+    // A file where a directory would have to be
+    fs::write(dir.join("file"), "").unwrap();
+    let engine = engine_with("hello");
+
+    let mut autosave = Autosave::new(dir.join("file").join("doc.txt"));
+    autosave.set_delay(Duration::ZERO);
+    autosave.schedule(&engine);
+    assert!(autosave.save_if_due(&engine).is_err());
+    assert!(autosave.is_pending());
+    // Not tried again straight away, nor once edits resume
+    assert!(autosave.due_at().unwrap() > Instant::now());
+    assert!(!autosave.save_if_due(&engine).unwrap());
+    autosave.schedule(&engine);
+    assert!(!autosave.save_if_due(&engine).unwrap());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_blocked_save_is_not_scheduled() {
+    let path = temp_file("autosave-blocked.txt", b"\xFF\xFEa");
+    let mut engine = EditorEngine::new();
+    assert!(engine.load_from_file(&path).is_err());
+    engine.handle_action(EditorAction::TypeString("oops".to_string()));
+
+    let mut autosave = Autosave::new(&path);
+    autosave.schedule(&engine);
+    assert!(!autosave.is_pending());
+    assert!(!autosave.flush(&engine, true).unwrap());
+    assert_eq!(fs::read(&path).unwrap(), b"\xFF\xFEa");
+
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,96 @@
+mod common;
+
+use common::temp_file;
+use std::fs;
+use zrd_core::binary::is_binary;
+use zrd_core::{EditorAction, EditorEngine};
+
+#[test]
+fn test_detects_nul_bytes() {
+    assert!(is_binary(b"ELF\x00\x01\x02"));
+    assert!(!is_binary(b"plain text\n"));
+    assert!(!is_binary(b""));
+}
+
+#[test]
+fn test_detects_invalid_utf8_with_control_bytes() {
+    assert!(is_binary(b"\x89PNG\r\n\x1a\n\xff\xd8\xfe\x92\x81\x03"));
+    // Legacy 8-bit text is invalid UTF-8 but has no control bytes
+    assert!(!is_binary(b"\xF0\xD2\xC9\xD7\xC5\xD4"));
+    assert!(!is_binary(b"caf\xE9\n"));
+}
+
+#[test]
+fn test_utf16_text_is_not_binary() {
+    assert!(!is_binary(b"\xFF\xFEh\x00i\x00"));
+}
+
+#[test]
+fn test_binary_file_is_read_only_as_text() {
+    let bytes = b"\x00\x01\x02\x03\xff";
+    let path = temp_file("readonly.bin", bytes);
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+
+    assert!(engine.is_binary());
+    engine.handle_action(EditorAction::TypeString("hello".to_string()));
+    engine.handle_action(EditorAction::Backspace);
+    engine.handle_action(EditorAction::Newline);
+    engine.handle_action(EditorAction::TypeCharacter('z'));
+
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_hex_editing_saves_exact_bytes() {
+    let mut bytes: Vec<u8> = (0..=255).collect();
+    let path = temp_file("edit.bin", &bytes);
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+
+    // Second row, third byte
+    engine.handle_action(EditorAction::MoveDown);
+    engine.handle_action(EditorAction::MoveRight);
+    engine.handle_action(EditorAction::MoveRight);
+    for c in "abC0".chars() {
+        engine.handle_action(EditorAction::TypeCharacter(c));
+    }
+    assert_eq!(engine.hex_document().unwrap().cursor(), 20);
+
+    engine.save_to_file(&path).unwrap();
+    bytes[18] = 0xAB;
+    bytes[19] = 0xC0;
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_hex_cursor_stays_in_file() {
+    let path = temp_file("bounds.bin", b"\x00\x01\x02");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+
+    engine.handle_action(EditorAction::MoveLeft);
+    assert_eq!(engine.hex_document().unwrap().cursor(), 0);
+    engine.handle_action(EditorAction::MoveDown);
+    assert_eq!(engine.hex_document().unwrap().cursor(), 2);
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 1 });
+    assert_eq!(engine.hex_document().unwrap().cursor(), 1);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_loading_text_leaves_hex_mode() {
+    let path = temp_file("mode.bin", b"\x00\x00");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+    assert!(engine.is_binary());
+
+    fs::write(&path, "text").unwrap();
+    engine.load_from_file(&path).unwrap();
+    assert!(!engine.is_binary());
+    assert_eq!(engine.state().to_string(), "text");
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,114 @@
+use zrd_core::{BlockSelection, BufferPosition, EditorAction, EditorEngine};
+
+fn engine_with(text: &str) -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString(text.to_string()));
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    engine
+}
+
+/// Select the block from (0, 1) to (2, 3) with the keyboard
+fn select_block(engine: &mut EditorEngine) {
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 1 });
+    engine.handle_action(EditorAction::BlockSelectDown);
+    engine.handle_action(EditorAction::BlockSelectDown);
+    engine.handle_action(EditorAction::BlockSelectRight);
+    engine.handle_action(EditorAction::BlockSelectRight);
+}
+
+#[test]
+fn test_block_select_with_keys() {
+    let mut engine = engine_with("abcdef\nghijkl\nmnopqr");
+    select_block(&mut engine);
+
+    assert_eq!(
+        engine.block_selection(),
+        Some(BlockSelection {
+            anchor_row: 0,
+            anchor_cell: 1,
+            cursor_row: 2,
+            cursor_cell: 3,
+        })
+    );
+    assert_eq!(engine.selected_text(), "bc\nhi\nno");
+    assert_eq!(engine.state().cursor, BufferPosition::new(2, 3));
+}
+
+#[test]
+fn test_type_replaces_block() {
+    let mut engine = engine_with("abcdef\nghijkl\nmnopqr");
+    select_block(&mut engine);
+    std::thread::sleep(std::time::Duration::from_millis(600));
+    engine.handle_action(EditorAction::TypeCharacter('X'));
+
+    assert_eq!(engine.state().to_string(), "aXdef\ngXjkl\nmXpqr");
+    assert_eq!(engine.block_selection(), None);
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "abcdef\nghijkl\nmnopqr");
+}
+
+#[test]
+fn test_delete_and_cut_block() {
+    let mut engine = engine_with("abcdef\nghijkl\nmnopqr");
+    select_block(&mut engine);
+    engine.handle_action(EditorAction::Backspace);
+    assert_eq!(engine.state().to_string(), "adef\ngjkl\nmpqr");
+
+    let mut engine = engine_with("abcdef\nghijkl\nmnopqr");
+    select_block(&mut engine);
+    let cut = engine.selected_text();
+    engine.handle_action(EditorAction::Cut);
+    assert_eq!(engine.state().to_string(), "adef\ngjkl\nmpqr");
+
+    // Pasting into a column of cursors puts one line on each row
+    engine.handle_action(EditorAction::Paste(cut));
+    assert_eq!(engine.state().to_string(), "abcdef\nghijkl\nmnopqr");
+}
+
+#[test]
+fn test_paste_into_block_without_matching_lines() {
+    let mut engine = engine_with("ab\ncd");
+    engine.handle_action(EditorAction::BlockSelectDown);
+    engine.handle_action(EditorAction::Paste("> ".to_string()));
+
+    assert_eq!(engine.state().to_string(), "> ab\n> cd");
+}
+
+#[test]
+fn test_block_over_short_and_wide_lines() {
+    // Columns are display cells: the block starts at cell 2 on every row
+    let mut engine = engine_with("abcd\na\n日本語");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 2 });
+    engine.handle_action(EditorAction::ExtendBlockSelection { row: 2, cell: 4 });
+
+    // The short line contributes an empty piece
+    assert_eq!(engine.selected_text(), "cd\n\n本");
+
+    engine.handle_action(EditorAction::TypeCharacter('|'));
+    assert_eq!(engine.state().to_string(), "ab|\na|\n日|語");
+}
+
+#[test]
+fn test_block_select_left_reverses_direction() {
+    let mut engine = engine_with("abcdef\nghijkl");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 4 });
+    engine.handle_action(EditorAction::BlockSelectDown);
+    engine.handle_action(EditorAction::BlockSelectLeft);
+    engine.handle_action(EditorAction::BlockSelectLeft);
+
+    assert_eq!(engine.selected_text(), "cd\nij");
+    // Cursors sit on the moving (left) edge
+    assert_eq!(engine.state().cursor, BufferPosition::new(1, 2));
+}
+
+#[test]
+fn test_other_actions_end_block_selection() {
+    let mut engine = engine_with("abcdef\nghijkl");
+    engine.handle_action(EditorAction::BlockSelectDown);
+    assert!(engine.block_selection().is_some());
+
+    engine.handle_action(EditorAction::MoveRight);
+    assert_eq!(engine.block_selection(), None);
+    assert_eq!(engine.state().extra_selections.len(), 1);
+}
@@ -0,0 +1,138 @@
+mod common;
+
+use common::{engine_with, temp_dir, temp_file};
+use std::fs;
+use std::path::Path;
+use zrd_core::{bookmark_file, EditorAction, EditorEngine};
+
+fn open(path: &Path, dir: &Path) -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    engine.set_bookmark_dir(Some(dir.to_path_buf()));
+    engine.load_from_file(path).unwrap();
+    engine
+}
+
+fn bookmark_rows(engine: &mut EditorEngine, rows: &[usize]) {
+    for &row in rows {
+        engine.handle_action(EditorAction::SetCursorPosition { row, column: 0 });
+        engine.handle_action(EditorAction::ToggleBookmark);
+    }
+}
+
+#[test]
+fn test_toggle_bookmark() {
+    let mut engine = engine_with("a\nb\nc");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 1, column: 1 });
+
+    engine.handle_action(EditorAction::ToggleBookmark);
+    assert_eq!(engine.bookmarks(), vec![1]);
+    engine.handle_action(EditorAction::ToggleBookmark);
+    assert!(engine.bookmarks().is_empty());
+}
+
+#[test]
+fn test_jump_between_bookmarks_wraps() {
+    let mut engine = engine_with("a\nb\nc\nd\ne");
+    bookmark_rows(&mut engine, &[1, 3]);
+    engine.handle_action(EditorAction::SetCursorPosition { row: 2, column: 1 });
+
+    engine.handle_action(EditorAction::NextBookmark);
+    assert_eq!(engine.state().cursor.row, 3);
+    assert_eq!(engine.state().cursor.column, 0);
+    engine.handle_action(EditorAction::NextBookmark);
+    assert_eq!(engine.state().cursor.row, 1);
+
+    engine.handle_action(EditorAction::PreviousBookmark);
+    assert_eq!(engine.state().cursor.row, 3);
+    engine.handle_action(EditorAction::PreviousBookmark);
+    assert_eq!(engine.state().cursor.row, 1);
+}
+
+#[test]
+fn test_bookmarks_follow_edits() {
+    let mut engine = engine_with("a\nb\nc");
+    bookmark_rows(&mut engine, &[1, 2]);
+
+    // A line break at the start of a bookmarked line keeps it with its text
+    engine.handle_action(EditorAction::SetCursorPosition { row: 1, column: 0 });
+    engine.handle_action(EditorAction::Newline);
+    assert_eq!(engine.bookmarks(), vec![2, 3]);
+
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    engine.handle_action(EditorAction::DeleteLine);
+    assert_eq!(engine.bookmarks(), vec![1, 2]);
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.bookmarks(), vec![2, 3]);
+
+    // Deleting a bookmarked line removes its bookmark rather than passing it
+    // to the line after
+    engine.handle_action(EditorAction::SetCursorPosition { row: 2, column: 0 });
+    engine.handle_action(EditorAction::DeleteLine);
+    assert_eq!(engine.state().to_string(), "a\n\nc");
+    assert_eq!(engine.bookmarks(), vec![2]);
+}
+
+#[test]
+fn test_bookmarks_survive_reopening() {
+    let dir = temp_dir("bookmarks-reopen");
+    let path = temp_file("bookmarks-reopen.txt", b"one\ntwo\nthree");
+
+    let mut engine = open(&path, &dir);
+    bookmark_rows(&mut engine, &[0, 2]);
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(bookmark_file::load(&dir, &path), vec![0, 2]);
+
+    let engine = open(&path, &dir);
+    assert_eq!(engine.bookmarks(), vec![0, 2]);
+
+    fs::remove_file(path).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_saved_rows_past_end_are_dropped() {
+    let dir = temp_dir("bookmarks-past-end");
+    let path = temp_file("bookmarks-past-end.txt", b"one\ntwo");
+
+    bookmark_file::save(&dir, &path, &[1, 5], false).unwrap();
+    let engine = open(&path, &dir);
+    assert_eq!(engine.bookmarks(), vec![1]);
+
+    fs::remove_file(path).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_clearing_bookmarks_removes_sidecar() {
+    let dir = temp_dir("bookmarks-clear");
+    let path = temp_file("bookmarks-clear.txt", b"one\ntwo");
+
+    let mut engine = open(&path, &dir);
+    bookmark_rows(&mut engine, &[1]);
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    engine.handle_action(EditorAction::ToggleBookmark);
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 0);
+
+    fs::remove_file(path).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_save_bookmarks_leaves_document_alone() {
+    let dir = temp_dir("bookmarks-only");
+    let path = temp_file("bookmarks-only.txt", b"one\ntwo");
+
+    let mut engine = open(&path, &dir);
+    engine.handle_action(EditorAction::TypeString("zero\n".to_string()));
+    bookmark_rows(&mut engine, &[1]);
+    engine.save_bookmarks(&path).unwrap();
+    assert_eq!(bookmark_file::load(&dir, &path), vec![1]);
+    assert_eq!(fs::read_to_string(&path).unwrap(), "one\ntwo");
+
+    fs::remove_file(path).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,73 @@
+use zrd_core::{Buffer, BufferPosition, EditorAction, EditorEngine};
+
+#[test]
+fn test_empty_buffer_has_one_line() {
+    let buffer = Buffer::new();
+
+    assert_eq!(buffer.line_count(), 1);
+    assert_eq!(buffer.line(0).as_deref(), Some(""));
+    assert_eq!(buffer.line_len(0), 0);
+    assert!(buffer.line(1).is_none());
+}
+
+#[test]
+fn test_line_access() {
+    let buffer = Buffer::from("hello\nwörld\n");
+
+    assert_eq!(buffer.line_count(), 3);
+    assert_eq!(buffer.line(0).as_deref(), Some("hello"));
+    assert_eq!(buffer.line(1).as_deref(), Some("wörld"));
+    assert_eq!(buffer.line(2).as_deref(), Some(""));
+    assert_eq!(buffer.line_len(1), 6);
+}
+
+#[test]
+fn test_crlf_is_hidden_from_line_views() {
+    let buffer = Buffer::from("a\r\nb\rc");
+
+    assert_eq!(buffer.line_count(), 2);
+    assert_eq!(buffer.line(0).as_deref(), Some("a"));
+    assert_eq!(buffer.line_len(0), 1);
+    assert_eq!(buffer.line(1).as_deref(), Some("b\rc"));
+    assert_eq!(buffer.to_string(), "a\r\nb\rc");
+}
+
+#[test]
+fn test_insert_returns_end_position() {
+    let mut buffer = Buffer::from("ac");
+    let end = buffer.insert(BufferPosition::new(0, 1), "b\nx");
+
+    assert_eq!(buffer.to_string(), "ab\nxc");
+    assert_eq!(end, BufferPosition::new(1, 1));
+}
+
+#[test]
+fn test_delete_across_lines() {
+    let mut buffer = Buffer::from("one\ntwo\nthree");
+    buffer.delete(BufferPosition::new(0, 2), BufferPosition::new(2, 1));
+
+    assert_eq!(buffer.to_string(), "onhree");
+    assert_eq!(buffer.line_count(), 1);
+}
+
+#[test]
+fn test_text_range_and_offsets() {
+    let buffer = Buffer::from("ab\ncd");
+
+    assert_eq!(
+        buffer.text_range(BufferPosition::new(0, 1), BufferPosition::new(1, 1)),
+        "b\nc"
+    );
+    assert_eq!(buffer.position_to_byte(BufferPosition::new(1, 1)), 4);
+    assert_eq!(buffer.byte_to_position(4), BufferPosition::new(1, 1));
+}
+
+#[test]
+fn test_large_paste_is_single_insert() {
+    let text = "line\n".repeat(100_000);
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString(text));
+
+    assert_eq!(engine.state().line_count(), 100_001);
+    assert_eq!(engine.state().cursor, BufferPosition::new(100_000, 0));
+}
@@ -0,0 +1,154 @@
+mod common;
+
+use common::temp_file;
+use std::fs;
+use zrd_core::{BufferPosition, EditorAction, EditorEngine, Selection, TextEdit};
+
+fn pos(row: usize, column: usize) -> BufferPosition {
+    BufferPosition::new(row, column)
+}
+
+/// Apply edits to a copy of the document kept as lines, as a frontend would
+fn apply(lines: &mut Vec<String>, edits: &[TextEdit]) {
+    for edit in edits {
+        let head = &lines[edit.start.row][..edit.start.column];
+        let tail = &lines[edit.old_end.row][edit.old_end.column..];
+        let replacement: Vec<String> = format!("{head}{}{tail}", edit.text)
+            .split('\n')
+            .map(str::to_string)
+            .collect();
+        lines.splice(edit.old_rows(), replacement);
+    }
+}
+
+#[test]
+fn test_no_events_unless_recording() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("text".to_string()));
+    assert!(engine.take_changes().is_empty());
+
+    engine.record_changes(true);
+    engine.handle_action(EditorAction::TypeString("!".to_string()));
+    assert_eq!(engine.take_changes().len(), 1);
+    assert!(engine.take_changes().is_empty());
+}
+
+#[test]
+fn test_typing_reports_insertion_and_cursors() {
+    let mut engine = EditorEngine::new();
+    engine.record_changes(true);
+    engine.handle_action(EditorAction::TypeString("ab\ncd".to_string()));
+
+    let changes = engine.take_changes();
+    assert_eq!(changes.len(), 1);
+    assert_eq!(
+        changes[0].edits,
+        vec![TextEdit {
+            start: pos(0, 0),
+            old_end: pos(0, 0),
+            new_end: pos(1, 2),
+            text: "ab\ncd".to_string(),
+        }]
+    );
+    assert_eq!(
+        changes[0].cursors_before.primary,
+        Selection::caret(pos(0, 0))
+    );
+    assert_eq!(
+        changes[0].cursors_after.primary,
+        Selection::caret(pos(1, 2))
+    );
+}
+
+#[test]
+fn test_cursor_motion_reports_no_edits() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("ab".to_string()));
+    engine.record_changes(true);
+
+    engine.handle_action(EditorAction::MoveLeft);
+    engine.handle_action(EditorAction::MoveToBeginningOfLine);
+    // Already at the start of the line, so nothing changes
+    engine.handle_action(EditorAction::MoveToBeginningOfLine);
+
+    let changes = engine.take_changes();
+    assert_eq!(changes.len(), 2);
+    assert!(changes.iter().all(|change| change.edits.is_empty()));
+    assert_eq!(
+        changes[1].cursors_after.primary,
+        Selection::caret(pos(0, 0))
+    );
+}
+
+#[test]
+fn test_undo_reports_reverted_edits() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("one\ntwo".to_string()));
+    engine.record_changes(true);
+
+    engine.handle_action(EditorAction::Undo);
+    let changes = engine.take_changes();
+    assert_eq!(
+        changes[0].edits,
+        vec![TextEdit {
+            start: pos(0, 0),
+            old_end: pos(1, 3),
+            new_end: pos(0, 0),
+            text: String::new(),
+        }]
+    );
+    assert_eq!(
+        changes[0].cursors_after.primary,
+        Selection::caret(pos(0, 0))
+    );
+}
+
+#[test]
+fn test_edits_replay_onto_copy() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("alpha\nbeta\ngamma".to_string()));
+    engine.record_changes(true);
+    let mut lines: Vec<String> = vec!["alpha".into(), "beta".into(), "gamma".into()];
+
+    engine.set_selections([Selection::caret(pos(0, 5)), Selection::caret(pos(2, 5))]);
+    engine.handle_action(EditorAction::Newline);
+    engine.handle_action(EditorAction::TypeString("x".to_string()));
+    engine.replace_text(pos(1, 0), pos(3, 2), "B\nC");
+    engine.handle_action(EditorAction::MoveLineDown);
+    engine.handle_action(EditorAction::Undo);
+    engine.handle_action(EditorAction::Undo);
+    engine.handle_action(EditorAction::Redo);
+
+    for change in engine.take_changes() {
+        apply(&mut lines, &change.edits);
+    }
+    let expected: Vec<String> = engine
+        .state()
+        .buffer
+        .lines()
+        .map(|line| line.into_owned())
+        .collect();
+    assert_eq!(lines, expected);
+}
+
+#[test]
+fn test_load_reports_whole_document() {
+    let path = temp_file("changes-load.txt", b"new\ntext");
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("old".to_string()));
+    engine.record_changes(true);
+
+    engine.load_from_file(&path).unwrap();
+    let changes = engine.take_changes();
+    assert_eq!(
+        changes[0].edits,
+        vec![TextEdit {
+            start: pos(0, 0),
+            old_end: pos(0, 3),
+            new_end: pos(1, 4),
+            text: "new\ntext".to_string(),
+        }]
+    );
+
+    fs::remove_file(path).unwrap();
+}
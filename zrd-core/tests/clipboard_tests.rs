@@ -0,0 +1,111 @@
+mod common;
+
+use common::engine_with;
+use std::io;
+use zrd_core::{
+    BufferPosition, Clipboard, EditorAction, EditorEngine, MemoryClipboard, Osc52Clipboard,
+};
+
+fn select(engine: &mut EditorEngine, row: usize, start: usize, end: usize) {
+    engine.handle_action(EditorAction::SetCursorPosition { row, column: start });
+    engine.handle_action(EditorAction::ExtendSelection { row, column: end });
+}
+
+/// A clipboard that cannot be written to
+struct Unavailable;
+
+impl Clipboard for Unavailable {
+    fn read(&mut self) -> Option<String> {
+        None
+    }
+
+    fn write(&mut self, _: &str) -> io::Result<()> {
+        Err(io::Error::other("no clipboard"))
+    }
+}
+
+#[test]
+fn test_cut_copy_paste_with_own_clipboard() {
+    let mut engine = engine_with("hello world");
+    select(&mut engine, 0, 0, 5);
+    engine.handle_action(EditorAction::Copy);
+    assert_eq!(engine.state().to_string(), "hello world");
+
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 11 });
+    engine.handle_action(EditorAction::PasteFromClipboard);
+    assert_eq!(engine.state().to_string(), "hello worldhello");
+
+    select(&mut engine, 0, 5, 11);
+    engine.handle_action(EditorAction::Cut);
+    assert_eq!(engine.state().to_string(), "hellohello");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    engine.handle_action(EditorAction::PasteFromClipboard);
+    assert_eq!(engine.state().to_string(), " worldhellohello");
+
+    // Copying nothing leaves the clipboard alone
+    engine.handle_action(EditorAction::Copy);
+    engine.handle_action(EditorAction::PasteFromClipboard);
+    assert_eq!(engine.state().to_string(), " world worldhellohello");
+}
+
+#[test]
+fn test_clipboard_passed_per_action() {
+    let mut engine = engine_with("one two");
+    let mut clipboard = MemoryClipboard::new();
+    select(&mut engine, 0, 4, 7);
+    engine.handle_action_with_clipboard(EditorAction::Cut, &mut clipboard);
+    assert_eq!(clipboard.read().as_deref(), Some("two"));
+    assert_eq!(engine.state().to_string(), "one ");
+
+    // The engine's own clipboard was not used
+    engine.handle_action(EditorAction::PasteFromClipboard);
+    assert_eq!(engine.state().to_string(), "one ");
+    engine.handle_action_with_clipboard(EditorAction::PasteFromClipboard, &mut clipboard);
+    assert_eq!(engine.state().to_string(), "one two");
+}
+
+#[test]
+fn test_cut_keeps_text_when_clipboard_fails() {
+    let mut engine = engine_with("keep me");
+    select(&mut engine, 0, 0, 4);
+    engine.handle_action_with_clipboard(EditorAction::Cut, &mut Unavailable);
+    assert_eq!(engine.state().to_string(), "keep me");
+    assert_eq!(engine.selected_text(), "keep");
+}
+
+#[test]
+fn test_multi_cursor_copy_pastes_line_per_cursor() {
+    let mut engine = engine_with("ab\ncd");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 1 });
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::SelectRight);
+    engine.handle_action(EditorAction::Copy);
+    engine.handle_action(EditorAction::MoveToBeginningOfLine);
+    engine.handle_action(EditorAction::PasteFromClipboard);
+    assert_eq!(engine.state().to_string(), "bab\ndcd");
+    assert_eq!(engine.state().cursor, BufferPosition::new(1, 1));
+}
+
+#[test]
+fn test_osc52_sets_terminal_clipboard() {
+    let mut out = Vec::new();
+    let mut clipboard = Osc52Clipboard::new(&mut out);
+    clipboard.write("zrd ✓").unwrap();
+    assert_eq!(clipboard.read().as_deref(), Some("zrd ✓"));
+    clipboard.write("ab").unwrap();
+    drop(clipboard);
+    assert_eq!(out, b"\x1b]52;c;enJkIOKckw==\x07\x1b]52;c;YWI=\x07");
+}
+
+#[test]
+fn test_paste_is_verbatim_and_one_undo_step() {
+    let mut engine = engine_with("- first");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 7 });
+    engine.handle_action(EditorAction::Paste("\n- second\n- third".to_string()));
+    // No list markers are added to the pasted lines
+    assert_eq!(engine.state().to_string(), "- first\n- second\n- third");
+    assert_eq!(engine.state().cursor, BufferPosition::new(2, 7));
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "- first");
+}
@@ -0,0 +1,29 @@
+// Each test crate includes this module and uses only some of its helpers
+#![allow(dead_code)]
+
+use std::fs;
+use std::path::PathBuf;
+use zrd_core::{EditorAction, EditorEngine};
+
+/// Write `content` to a file in the temp dir, unique per test process
+pub fn temp_file(name: &str, content: &[u8]) -> PathBuf {
+    let path = std::env::temp_dir().join(format!("zrd-{}-{}", std::process::id(), name));
+    fs::write(&path, content).unwrap();
+    path
+}
+
+/// Empty directory in the temp dir, unique per test process
+pub fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("zrd-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// An engine holding `text` with the cursor at the start
+pub fn engine_with(text: &str) -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString(text.to_string()));
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    engine
+}
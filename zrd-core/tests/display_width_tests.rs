@@ -0,0 +1,41 @@
+use zrd_core::display_width::{cell_to_column, column_to_cell, expand_tabs, line_width};
+
+#[test]
+fn test_ascii_cells_match_bytes() {
+    assert_eq!(column_to_cell("hello", 3), 3);
+    assert_eq!(cell_to_column("hello", 3), 3);
+    assert_eq!(cell_to_column("hello", 40), 5);
+}
+
+#[test]
+fn test_wide_characters_take_two_cells() {
+    let line = "a日本b";
+    assert_eq!(line_width(line), 6);
+    assert_eq!(column_to_cell(line, 4), 3);
+    // Both halves of a wide character map to its start
+    assert_eq!(cell_to_column(line, 1), 1);
+    assert_eq!(cell_to_column(line, 2), 1);
+    assert_eq!(cell_to_column(line, 3), 4);
+    assert_eq!(cell_to_column(line, 5), 7);
+}
+
+#[test]
+fn test_emoji_and_combining_marks() {
+    let line = "e\u{0301}🚀x";
+    assert_eq!(column_to_cell(line, 3), 1);
+    assert_eq!(column_to_cell(line, 7), 3);
+    // A click never lands inside a multibyte sequence
+    assert_eq!(cell_to_column(line, 2), 3);
+    assert_eq!(cell_to_column(line, 3), 7);
+}
+
+#[test]
+fn test_tabs_expand_to_tab_stops() {
+    let line = "a\tbc\td";
+    assert_eq!(column_to_cell(line, 2), 4);
+    assert_eq!(column_to_cell(line, 5), 8);
+    assert_eq!(cell_to_column(line, 2), 1);
+    assert_eq!(cell_to_column(line, 8), 5);
+    assert_eq!(expand_tabs(line, 0), "a   bc  d");
+    assert_eq!(expand_tabs("\tx", 1), "   x");
+}
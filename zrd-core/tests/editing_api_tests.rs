@@ -0,0 +1,112 @@
+mod common;
+
+use common::engine_with;
+use zrd_core::{BufferPosition, EditorAction, LineEnding, Selection};
+
+fn pos(row: usize, column: usize) -> BufferPosition {
+    BufferPosition::new(row, column)
+}
+
+#[test]
+fn test_insert_shifts_later_cursors() {
+    let mut engine = engine_with("hello world");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 6 });
+
+    let end = engine.insert_text(pos(0, 0), "oh, ");
+    assert_eq!(end, pos(0, 4));
+    assert_eq!(engine.state().to_string(), "oh, hello world");
+    assert_eq!(engine.state().cursor, pos(0, 10));
+
+    // A cursor before the insertion stays put
+    engine.insert_text(pos(0, 15), "!");
+    assert_eq!(engine.state().cursor, pos(0, 10));
+}
+
+#[test]
+fn test_insert_across_lines() {
+    let mut engine = engine_with("ab\ncd");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 1, column: 1 });
+
+    let end = engine.insert_text(pos(0, 1), "1\n2");
+    assert_eq!(end, pos(1, 1));
+    assert_eq!(engine.state().to_string(), "a1\n2b\ncd");
+    assert_eq!(engine.state().cursor, pos(2, 1));
+}
+
+#[test]
+fn test_delete_and_replace_move_cursors_inside() {
+    let mut engine = engine_with("one two three");
+    engine.set_selections([Selection::caret(pos(0, 5)), Selection::caret(pos(0, 13))]);
+
+    engine.delete_text(pos(0, 7), pos(0, 3));
+    assert_eq!(engine.state().to_string(), "one three");
+    assert_eq!(engine.state().cursor, pos(0, 3));
+    assert_eq!(
+        engine.state().extra_selections,
+        vec![Selection::caret(pos(0, 9))]
+    );
+
+    let end = engine.replace_text(pos(0, 4), pos(0, 9), "3");
+    assert_eq!(end, pos(0, 5));
+    assert_eq!(engine.state().to_string(), "one 3");
+    assert_eq!(
+        engine.state().extra_selections,
+        vec![Selection::caret(pos(0, 5))]
+    );
+}
+
+#[test]
+fn test_selection_shifts_with_edit() {
+    let mut engine = engine_with("abc def");
+    engine.set_selections([Selection::new(pos(0, 7), Some(pos(0, 4)))]);
+
+    engine.insert_text(pos(0, 0), "__");
+    assert_eq!(engine.selected_text(), "def");
+    assert_eq!(engine.text_in_range(pos(0, 9), pos(0, 2)), "abc def");
+}
+
+#[test]
+fn test_edits_are_undo_steps() {
+    let mut engine = engine_with("text");
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.insert_text(pos(0, 0), "a ");
+    engine.replace_text(pos(0, 2), pos(0, 6), "line");
+    assert_eq!(engine.state().to_string(), "a line");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "a text");
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "text");
+    assert_eq!(engine.state().cursor, pos(0, 4));
+    engine.handle_action(EditorAction::Redo);
+    assert_eq!(engine.state().to_string(), "a text");
+}
+
+#[test]
+fn test_insert_uses_document_line_endings() {
+    let mut engine = engine_with("a\nb");
+    engine.handle_action(EditorAction::ConvertLineEndings(LineEnding::Crlf));
+
+    engine.insert_text(pos(1, 1), "\nc");
+    assert_eq!(engine.state().to_string(), "a\r\nb\r\nc");
+}
+
+#[test]
+fn test_set_selections_clamps_and_merges() {
+    let mut engine = engine_with("abcdef\nxy");
+    engine.set_selections([
+        Selection::new(pos(0, 4), Some(pos(0, 1))),
+        Selection::new(pos(0, 5), Some(pos(0, 3))),
+        Selection::caret(pos(1, 10)),
+    ]);
+
+    let state = engine.state();
+    assert_eq!(
+        state.primary_selection(),
+        Selection::new(pos(0, 5), Some(pos(0, 1)))
+    );
+    assert_eq!(state.extra_selections, vec![Selection::caret(pos(1, 2))]);
+
+    engine.set_selections([]);
+    assert_eq!(engine.state().extra_selections.len(), 1);
+}
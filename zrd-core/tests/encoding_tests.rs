@@ -0,0 +1,105 @@
+mod common;
+
+use common::temp_file;
+use std::fs;
+use zrd_core::{EditorAction, EditorEngine, FileEncoding};
+
+fn utf16le_with_bom(text: &str) -> Vec<u8> {
+    let mut bytes = vec![0xFF, 0xFE];
+    for unit in text.encode_utf16() {
+        bytes.extend_from_slice(&unit.to_le_bytes());
+    }
+    bytes
+}
+
+#[test]
+fn test_utf8_without_bom() {
+    let (text, encoding) =
+        FileEncoding::decode("héllo".as_bytes().to_vec(), FileEncoding::DEFAULT_FALLBACK).unwrap();
+
+    assert_eq!(text, "héllo");
+    assert_eq!(encoding, FileEncoding::UTF8);
+}
+
+#[test]
+fn test_utf8_bom_round_trips() {
+    let path = temp_file("utf8bom.txt", b"\xEF\xBB\xBFhi");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+
+    assert_eq!(engine.state().to_string(), "hi");
+    assert!(engine.encoding().bom);
+
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"\xEF\xBB\xBFhi");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_utf16_bom_round_trips() {
+    let bytes = utf16le_with_bom("zrd 🚀\r\nline");
+    let path = temp_file("utf16.txt", &bytes);
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+
+    assert_eq!(engine.encoding().name(), "UTF-16LE");
+    assert_eq!(engine.state().line(0).as_deref(), Some("zrd 🚀"));
+
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), bytes);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_latin1_falls_back_and_round_trips() {
+    let path = temp_file("latin1.txt", b"caf\xE9");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+
+    assert_eq!(engine.state().to_string(), "café");
+    assert_eq!(engine.encoding().name(), "windows-1252");
+
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::TypeCharacter('!'));
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"caf\xE9!");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_chosen_fallback_encoding() {
+    let path = temp_file("koi8.txt", b"\xF0\xD2\xC9");
+    let mut engine = EditorEngine::new();
+    engine.set_fallback_encoding(FileEncoding::for_label("koi8-r").unwrap());
+    engine.load_from_file(&path).unwrap();
+
+    assert_eq!(engine.state().to_string(), "При");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_unrepresentable_character_is_not_saved() {
+    let path = temp_file("unrepresentable.txt", b"caf\xE9");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+    engine.handle_action(EditorAction::TypeCharacter('🚀'));
+
+    assert!(engine.save_to_file(&path).is_err());
+    assert_eq!(fs::read(&path).unwrap(), b"caf\xE9");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_undecodable_file_blocks_save() {
+    // A UTF-16 BOM followed by an odd number of bytes is malformed
+    let path = temp_file("broken.txt", b"\xFF\xFEa");
+    let mut engine = EditorEngine::new();
+
+    assert!(engine.load_from_file(&path).is_err());
+    assert!(engine.is_save_blocked());
+
+    engine.handle_action(EditorAction::TypeString("oops".to_string()));
+    assert!(engine.save_to_file(&path).is_err());
+    assert_eq!(fs::read(&path).unwrap(), b"\xFF\xFEa");
+    fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,83 @@
+use zrd_core::{BufferPosition, EditorAction, EditorEngine};
+
+const FAMILY: &str = "👨\u{200D}👩\u{200D}👧";
+const FLAG: &str = "🇯🇵";
+// Conjoining jamo that render as the syllable 한
+const HANGUL_JAMO: &str = "\u{1112}\u{1161}\u{11AB}";
+const E_ACUTE: &str = "e\u{0301}";
+
+fn engine_with(text: &str) -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString(text.to_string()));
+    engine
+}
+
+#[test]
+fn test_move_left_right_over_clusters() {
+    for cluster in [FAMILY, FLAG, HANGUL_JAMO, E_ACUTE] {
+        let mut engine = engine_with(&format!("a{}b", cluster));
+        engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 1 });
+
+        engine.handle_action(EditorAction::MoveRight);
+        assert_eq!(
+            engine.state().cursor.column,
+            1 + cluster.len(),
+            "{:?}",
+            cluster
+        );
+
+        engine.handle_action(EditorAction::MoveLeft);
+        assert_eq!(engine.state().cursor.column, 1, "{:?}", cluster);
+    }
+}
+
+#[test]
+fn test_backspace_removes_whole_cluster() {
+    for cluster in [FAMILY, FLAG, HANGUL_JAMO, E_ACUTE] {
+        let mut engine = engine_with(&format!("x{}", cluster));
+        engine.handle_action(EditorAction::Backspace);
+
+        assert_eq!(engine.state().to_string(), "x", "{:?}", cluster);
+        assert_eq!(engine.state().cursor, BufferPosition::new(0, 1));
+    }
+}
+
+#[test]
+fn test_delete_removes_whole_cluster() {
+    let mut engine = engine_with(&format!("{}{}", FLAG, FAMILY));
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    engine.handle_action(EditorAction::Delete);
+
+    assert_eq!(engine.state().to_string(), FAMILY);
+}
+
+#[test]
+fn test_adjacent_flags_are_separate_clusters() {
+    let mut engine = engine_with(&format!("{}{}", FLAG, FLAG));
+    engine.handle_action(EditorAction::Backspace);
+
+    assert_eq!(engine.state().to_string(), FLAG);
+}
+
+#[test]
+fn test_select_left_spans_cluster() {
+    let mut engine = engine_with(&format!("ab{}", E_ACUTE));
+    engine.handle_action(EditorAction::SelectLeft);
+    assert_eq!(
+        engine.state().selection_anchor,
+        Some(BufferPosition::new(0, 2 + E_ACUTE.len()))
+    );
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 2));
+
+    engine.handle_action(EditorAction::Backspace);
+    assert_eq!(engine.state().to_string(), "ab");
+}
+
+#[test]
+fn test_select_right_spans_cluster() {
+    let mut engine = engine_with(FAMILY);
+    engine.handle_action(EditorAction::MoveToBeginningOfLine);
+    engine.handle_action(EditorAction::SelectRight);
+
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, FAMILY.len()));
+}
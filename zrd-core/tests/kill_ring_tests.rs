@@ -0,0 +1,166 @@
+mod common;
+
+use common::{engine_with, temp_dir};
+use std::fs;
+use zrd_core::{BufferPosition, EditorAction, EditorEngine, KillRing, Selection};
+
+fn entries(engine: &EditorEngine) -> Vec<&str> {
+    engine.kill_ring().iter().collect()
+}
+
+#[test]
+fn test_consecutive_kills_join() {
+    let mut engine = engine_with("one two three four\nfive");
+    engine.handle_action(EditorAction::DeleteWordRight);
+    engine.handle_action(EditorAction::DeleteWordRight);
+    assert_eq!(entries(&engine), vec!["one two "]);
+
+    // Moving in between starts a new entry, and backward kills go in front
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::DeleteWordLeft);
+    engine.handle_action(EditorAction::DeleteWordLeft);
+    assert_eq!(engine.state().to_string(), "\nfive");
+    assert_eq!(entries(&engine), vec!["three four", "one two "]);
+
+    engine.handle_action(EditorAction::MoveDown);
+    engine.handle_action(EditorAction::DeleteLine);
+    assert_eq!(entries(&engine), vec!["five\n", "three four", "one two "]);
+}
+
+#[test]
+fn test_cut_and_line_kills() {
+    let mut engine = engine_with("alpha beta\ngamma\ndelta");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 5 });
+    engine.handle_action(EditorAction::DeleteToEndOfLine);
+    // Nothing left to kill on the line, so nothing is added
+    engine.handle_action(EditorAction::DeleteToEndOfLine);
+    assert_eq!(entries(&engine), vec![" beta"]);
+
+    engine.handle_action(EditorAction::SetCursorPosition { row: 1, column: 0 });
+    engine.handle_action(EditorAction::ExtendSelection { row: 1, column: 3 });
+    engine.handle_action(EditorAction::Cut);
+    engine.handle_action(EditorAction::DeleteToEndOfLine);
+    assert_eq!(entries(&engine), vec!["gamma", " beta"]);
+}
+
+#[test]
+fn test_multi_cursor_kills_join_in_document_order() {
+    let mut engine = engine_with("one 1\ntwo 2\nthree 3");
+    engine.set_selections((0..3).map(|row| Selection::caret(BufferPosition::new(row, 0))));
+    engine.handle_action(EditorAction::DeleteWordRight);
+    assert_eq!(engine.state().to_string(), "1\n2\n3");
+    assert_eq!(entries(&engine), vec!["one \ntwo \nthree "]);
+
+    // Backward kills come out in the same order
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::DeleteToBeginningOfLine);
+    assert_eq!(engine.state().to_string(), "\n\n");
+    assert_eq!(entries(&engine), vec!["1\n2\n3", "one \ntwo \nthree "]);
+}
+
+#[test]
+fn test_paste_from_history_cycles_in_place() {
+    let mut engine = engine_with("a\nb\nc");
+    for _ in 0..3 {
+        engine.handle_action(EditorAction::DeleteToEndOfLine);
+        engine.handle_action(EditorAction::MoveDown);
+    }
+    assert_eq!(engine.state().to_string(), "\n\n");
+    assert_eq!(entries(&engine), vec!["c", "b", "a"]);
+
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    engine.handle_action(EditorAction::PasteFromHistory);
+    assert_eq!(engine.state().to_string(), "c\n\n");
+    engine.handle_action(EditorAction::PasteFromHistory);
+    assert_eq!(engine.state().to_string(), "b\n\n");
+    engine.handle_action(EditorAction::PasteFromHistory);
+    engine.handle_action(EditorAction::PasteFromHistory);
+    assert_eq!(engine.state().to_string(), "c\n\n");
+
+    // After anything else, it pastes the newest entry again
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::PasteFromHistory);
+    assert_eq!(engine.state().to_string(), "cc\n\n");
+}
+
+#[test]
+fn test_paste_from_history_follows_clipboard_paste() {
+    let mut engine = engine_with("x y");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 1 });
+    engine.handle_action(EditorAction::DeleteToBeginningOfLine);
+    engine.handle_action(EditorAction::MoveRight);
+    engine.handle_action(EditorAction::ExtendSelection { row: 0, column: 2 });
+    engine.handle_action(EditorAction::Cut);
+    assert_eq!(entries(&engine), vec!["y", "x"]);
+
+    engine.handle_action(EditorAction::PasteFromClipboard);
+    assert_eq!(engine.state().to_string(), " y");
+    engine.handle_action(EditorAction::PasteFromHistory);
+    assert_eq!(engine.state().to_string(), " x");
+}
+
+#[test]
+fn test_editing_api_ends_kill_and_paste_streaks() {
+    let mut engine = engine_with("one two");
+    engine.handle_action(EditorAction::DeleteWordRight);
+    engine.insert_text(BufferPosition::new(0, 3), " end");
+    engine.handle_action(EditorAction::DeleteWordRight);
+    assert_eq!(entries(&engine), vec!["two ", "one "]);
+
+    engine.handle_action(EditorAction::PasteFromHistory);
+    engine.insert_text(BufferPosition::new(0, 7), "!");
+    // Pastes the newest entry again instead of cycling
+    engine.handle_action(EditorAction::PasteFromHistory);
+    assert_eq!(engine.state().to_string(), "two two end!");
+}
+
+#[test]
+fn test_kill_ring_persists() {
+    let dir = temp_dir("kill-ring");
+    let document = dir.join("doc.txt");
+    let ring_file = dir.join("config").join("kill-ring");
+
+    let mut engine = engine_with("first\\line\nsecond");
+    engine.set_kill_ring_file(Some(ring_file.clone()));
+    engine.handle_action(EditorAction::DeleteLine);
+    engine.handle_action(EditorAction::MoveDown);
+    engine.handle_action(EditorAction::DeleteToEndOfLine);
+    engine.save_to_file(&document).unwrap();
+
+    let mut engine = EditorEngine::new();
+    engine.set_kill_ring_file(Some(ring_file.clone()));
+    assert_eq!(entries(&engine), vec!["second", "first\\line\n"]);
+
+    let mut ring = KillRing::new();
+    ring.push("\\n\r\n".to_string());
+    ring.save(&ring_file, false).unwrap();
+    assert_eq!(KillRing::load(&ring_file), ring);
+    assert_eq!(KillRing::load(&dir.join("missing")), KillRing::new());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[cfg(unix)]
+#[test]
+fn test_kill_ring_saved_through_symlink() {
+    use std::os::unix::fs::symlink;
+
+    // As when the config directory is kept elsewhere and linked in
+    let dir = temp_dir("kill-ring-link");
+    let target = dir.join("kill-ring-target");
+    let link = dir.join("kill-ring");
+    fs::write(&target, "").unwrap();
+    symlink(&target, &link).unwrap();
+
+    let mut ring = KillRing::new();
+    ring.push("kept".to_string());
+    ring.save(&link, false).unwrap();
+    assert!(fs::symlink_metadata(&link)
+        .unwrap()
+        .file_type()
+        .is_symlink());
+    assert_eq!(KillRing::load(&target), ring);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
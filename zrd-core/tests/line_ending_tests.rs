@@ -0,0 +1,96 @@
+mod common;
+
+use common::temp_file;
+use std::fs;
+use zrd_core::{BufferPosition, EditorAction, EditorEngine, LineEnding};
+
+#[test]
+fn test_detect_line_endings() {
+    assert_eq!(LineEnding::detect("no breaks"), LineEnding::Lf);
+    assert_eq!(LineEnding::detect("a\nb\n"), LineEnding::Lf);
+    assert_eq!(LineEnding::detect("a\r\nb\r\n"), LineEnding::Crlf);
+    assert_eq!(LineEnding::detect("a\r\nb\n"), LineEnding::Mixed);
+}
+
+#[test]
+fn test_crlf_file_round_trips() {
+    let path = temp_file("crlf.txt", b"one\r\ntwo\r\n");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+
+    assert_eq!(engine.state().line_ending, LineEnding::Crlf);
+    assert_eq!(engine.state().line(0).as_deref(), Some("one"));
+    assert!(engine.state().buffer.has_trailing_newline());
+
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"one\r\ntwo\r\n");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_mixed_file_round_trips() {
+    let path = temp_file("mixed.txt", b"one\r\ntwo\nthree");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+
+    assert_eq!(engine.state().line_ending, LineEnding::Mixed);
+
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"one\r\ntwo\nthree");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_trailing_newline_preserved_after_edit() {
+    let path = temp_file("trailing.txt", b"hello\n");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::TypeCharacter('!'));
+
+    engine.save_to_file(&path).unwrap();
+    assert_eq!(fs::read(&path).unwrap(), b"hello!\n");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_newline_uses_document_style() {
+    let path = temp_file("newline.txt", b"a\r\nb");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::Newline);
+    engine.handle_action(EditorAction::TypeString("x\ny".to_string()));
+
+    assert_eq!(engine.state().to_string(), "a\r\nx\r\ny\r\nb");
+    assert_eq!(engine.state().cursor, BufferPosition::new(2, 1));
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_backspace_removes_whole_crlf() {
+    let path = temp_file("backspace.txt", b"a\r\nb");
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+    engine.handle_action(EditorAction::MoveDown);
+    engine.handle_action(EditorAction::Backspace);
+
+    assert_eq!(engine.state().to_string(), "ab");
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn test_convert_line_endings() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("a\nb\n".to_string()));
+
+    engine.handle_action(EditorAction::ConvertLineEndings(LineEnding::Crlf));
+    assert_eq!(engine.state().to_string(), "a\r\nb\r\n");
+    assert_eq!(engine.state().line_ending, LineEnding::Crlf);
+
+    engine.handle_action(EditorAction::ConvertLineEndings(LineEnding::Lf));
+    assert_eq!(engine.state().to_string(), "a\nb\n");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "a\r\nb\r\n");
+}
@@ -0,0 +1,204 @@
+use zrd_core::{BufferPosition, EditorAction, EditorEngine, Selection};
+
+fn engine_with(text: &str) -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString(text.to_string()));
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    engine
+}
+
+fn cursors(engine: &EditorEngine) -> Vec<BufferPosition> {
+    engine
+        .state()
+        .selections()
+        .iter()
+        .map(|selection| selection.cursor)
+        .collect()
+}
+
+#[test]
+fn test_add_cursor_below_and_type() {
+    let mut engine = engine_with("one\ntwo\nthree");
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::TypeString("- ".to_string()));
+
+    assert_eq!(engine.state().to_string(), "- one\n- two\n- three");
+    assert_eq!(
+        cursors(&engine),
+        vec![
+            BufferPosition::new(0, 2),
+            BufferPosition::new(1, 2),
+            BufferPosition::new(2, 2)
+        ]
+    );
+}
+
+#[test]
+fn test_add_cursor_above_keeps_display_column() {
+    let mut engine = engine_with("abcdef\nab\nabcdef");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 2, column: 4 });
+    engine.handle_action(EditorAction::AddCursorAbove);
+    engine.handle_action(EditorAction::AddCursorAbove);
+
+    assert_eq!(
+        cursors(&engine),
+        vec![
+            BufferPosition::new(0, 4),
+            BufferPosition::new(1, 2),
+            BufferPosition::new(2, 4)
+        ]
+    );
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 4));
+}
+
+#[test]
+fn test_backspace_at_each_cursor_is_one_undo_step() {
+    let mut engine = engine_with("ab\nab\nab");
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::AddCursorBelow);
+    // Let the typing above become its own undo step
+    std::thread::sleep(std::time::Duration::from_millis(600));
+    engine.handle_action(EditorAction::Backspace);
+    assert_eq!(engine.state().to_string(), "a\na\na");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "ab\nab\nab");
+    assert_eq!(engine.state().selections().len(), 3);
+}
+
+#[test]
+fn test_overlapping_cursors_merge() {
+    let mut engine = engine_with("ab\nc");
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::MoveToBeginningOfLine);
+    assert_eq!(
+        cursors(&engine),
+        vec![BufferPosition::new(0, 0), BufferPosition::new(1, 0)]
+    );
+
+    // Both cursors end up at the start of the document
+    engine.handle_action(EditorAction::MoveLeft);
+    engine.handle_action(EditorAction::MoveLeft);
+    engine.handle_action(EditorAction::MoveLeft);
+    assert_eq!(cursors(&engine), vec![BufferPosition::zero()]);
+    assert!(engine.state().extra_selections.is_empty());
+}
+
+#[test]
+fn test_newline_at_each_cursor() {
+    let mut engine = engine_with("ab\ncd");
+    engine.handle_action(EditorAction::MoveRight);
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::Newline);
+
+    assert_eq!(engine.state().to_string(), "a\nb\nc\nd");
+    assert_eq!(
+        cursors(&engine),
+        vec![BufferPosition::new(1, 0), BufferPosition::new(3, 0)]
+    );
+}
+
+#[test]
+fn test_word_moves_apply_to_each_cursor() {
+    let mut engine = engine_with(
+        "foo bar
+baz qux",
+    );
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::MoveWordRight);
+    engine.handle_action(EditorAction::DeleteWordRight);
+
+    assert_eq!(engine.state().to_string(), "foo \nbaz ");
+}
+
+#[test]
+fn test_tab_and_outdent_apply_to_each_cursor() {
+    let mut engine = engine_with(
+        "foo
+bar",
+    );
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::Tab);
+    assert_eq!(engine.state().to_string(), "    foo\n    bar");
+
+    engine.handle_action(EditorAction::Outdent);
+    assert_eq!(engine.state().to_string(), "foo\nbar");
+}
+
+#[test]
+fn test_select_next_occurrence() {
+    let mut engine = engine_with("cat dog cat\ncat");
+    engine.handle_action(EditorAction::SelectNextOccurrence);
+    assert_eq!(
+        engine.state().primary_selection().range(),
+        (BufferPosition::new(0, 0), BufferPosition::new(0, 3))
+    );
+
+    engine.handle_action(EditorAction::SelectNextOccurrence);
+    engine.handle_action(EditorAction::SelectNextOccurrence);
+    assert_eq!(engine.state().selections().len(), 3);
+
+    // Every occurrence is already selected
+    engine.handle_action(EditorAction::SelectNextOccurrence);
+    assert_eq!(engine.state().selections().len(), 3);
+
+    engine.handle_action(EditorAction::TypeString("cow".to_string()));
+    assert_eq!(engine.state().to_string(), "cow dog cow\ncow");
+}
+
+#[test]
+fn test_select_next_occurrence_wraps_around() {
+    let mut engine = engine_with("x y x");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 5 });
+    engine.handle_action(EditorAction::SelectNextOccurrence);
+    engine.handle_action(EditorAction::SelectNextOccurrence);
+
+    let ranges: Vec<_> = engine
+        .state()
+        .selections()
+        .iter()
+        .map(Selection::range)
+        .collect();
+    assert_eq!(
+        ranges,
+        vec![
+            (BufferPosition::new(0, 0), BufferPosition::new(0, 1)),
+            (BufferPosition::new(0, 4), BufferPosition::new(0, 5))
+        ]
+    );
+}
+
+#[test]
+fn test_click_returns_to_single_cursor() {
+    let mut engine = engine_with("a\nb\nc");
+    engine.handle_action(EditorAction::AddCursorBelow);
+    engine.handle_action(EditorAction::SetCursorPosition { row: 2, column: 0 });
+
+    assert_eq!(cursors(&engine), vec![BufferPosition::new(2, 0)]);
+}
+
+#[test]
+fn test_delete_line_with_two_cursors_on_one_line() {
+    let mut engine = engine_with("a a\nb\nc");
+    engine.handle_action(EditorAction::SelectNextOccurrence);
+    engine.handle_action(EditorAction::SelectNextOccurrence);
+    engine.handle_action(EditorAction::DeleteLine);
+
+    assert_eq!(engine.state().to_string(), "b\nc");
+}
+
+#[test]
+fn test_move_lines_with_cursors_on_adjacent_lines() {
+    let mut engine = engine_with("1\n2\n3\n4");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 1, column: 0 });
+    engine.handle_action(EditorAction::AddCursorBelow);
+
+    engine.handle_action(EditorAction::MoveLineDown);
+    assert_eq!(engine.state().to_string(), "1\n4\n2\n3");
+    engine.handle_action(EditorAction::MoveLineUp);
+    engine.handle_action(EditorAction::MoveLineUp);
+    assert_eq!(engine.state().to_string(), "2\n3\n1\n4");
+}
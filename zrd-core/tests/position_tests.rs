@@ -0,0 +1,96 @@
+use zrd_core::{Buffer, BufferPosition, EditorAction, EditorEngine};
+
+#[test]
+fn test_clamp_snaps_to_char_boundary() {
+    let buffer = Buffer::from("aé日🚀");
+    // Inside 'é', '日' and '🚀'
+    assert_eq!(
+        buffer.clamp_position(BufferPosition::new(0, 2)),
+        BufferPosition::new(0, 1)
+    );
+    assert_eq!(
+        buffer.clamp_position(BufferPosition::new(0, 5)),
+        BufferPosition::new(0, 3)
+    );
+    assert_eq!(
+        buffer.clamp_position(BufferPosition::new(0, 8)),
+        BufferPosition::new(0, 6)
+    );
+}
+
+#[test]
+fn test_insert_inside_multibyte_char_does_not_panic() {
+    let mut buffer = Buffer::from("日本");
+    let end = buffer.insert(BufferPosition::new(0, 4), "x");
+
+    assert_eq!(buffer.to_string(), "日x本");
+    assert_eq!(end, BufferPosition::new(0, 4));
+}
+
+#[test]
+fn test_engine_repairs_cursor_set_by_frontend() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("héllo".to_string()));
+    engine.state_mut().cursor = BufferPosition::new(0, 2);
+    engine.handle_action(EditorAction::TypeCharacter('x'));
+
+    assert_eq!(engine.state().to_string(), "hxéllo");
+}
+
+#[test]
+fn test_word_motion_over_multibyte_text() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("café über".to_string()));
+    engine.handle_action(EditorAction::MoveWordLeft);
+    assert_eq!(engine.state().cursor.column, "café ".len());
+
+    engine.handle_action(EditorAction::MoveToBeginningOfLine);
+    engine.handle_action(EditorAction::MoveWordRight);
+    assert_eq!(engine.state().cursor.column, "café ".len());
+}
+
+#[test]
+fn test_char_offsets() {
+    let buffer = Buffer::from("aé\n日b");
+    let pos = BufferPosition::new(1, 3);
+
+    assert_eq!(buffer.position_to_char(pos), 4);
+    assert_eq!(buffer.char_to_position(4), pos);
+    assert_eq!(buffer.char_column(pos), 1);
+    assert_eq!(buffer.position_from_char_column(1, 1), pos);
+    assert_eq!(
+        buffer.position_from_char_column(1, 99),
+        BufferPosition::new(1, 4)
+    );
+}
+
+#[test]
+fn test_utf16_offsets() {
+    let buffer = Buffer::from("x\r\n🚀y");
+    let after_rocket = BufferPosition::new(1, 4);
+
+    assert_eq!(buffer.position_to_utf16(after_rocket), 5);
+    assert_eq!(buffer.utf16_to_position(5), after_rocket);
+    assert_eq!(buffer.utf16_column(after_rocket), 2);
+    assert_eq!(buffer.position_from_utf16_column(1, 2), after_rocket);
+    // Between the halves of the surrogate pair
+    assert_eq!(
+        buffer.position_from_utf16_column(1, 1),
+        BufferPosition::new(1, 0)
+    );
+    assert_eq!(buffer.utf16_to_position(4), BufferPosition::new(1, 0));
+}
+
+#[test]
+fn test_grapheme_columns() {
+    let buffer = Buffer::from("e\u{0301}🇯🇵z");
+
+    assert_eq!(buffer.grapheme_column(BufferPosition::new(0, 3)), 1);
+    assert_eq!(buffer.grapheme_column(BufferPosition::new(0, 11)), 2);
+    // Inside a cluster counts only the clusters before it
+    assert_eq!(buffer.grapheme_column(BufferPosition::new(0, 7)), 1);
+    assert_eq!(
+        buffer.position_from_grapheme_column(0, 2),
+        BufferPosition::new(0, 11)
+    );
+}
@@ -0,0 +1,114 @@
+mod common;
+
+use common::engine_with;
+use zrd_core::{BufferPosition, EditorAction, SearchQuery};
+
+fn pos(row: usize, column: usize) -> BufferPosition {
+    BufferPosition::new(row, column)
+}
+
+fn regex(text: &str) -> SearchQuery {
+    SearchQuery {
+        regex: true,
+        ..SearchQuery::new(text)
+    }
+}
+
+#[test]
+fn test_regex_find() {
+    let query = regex(r"^\w+");
+    assert_eq!(query.find_in("one two\nthree"), vec![0..3, 8..13]);
+
+    // Empty matches are skipped
+    assert_eq!(regex("x*").find_in("axxb"), vec![1..3]);
+
+    let invalid = regex("(unclosed");
+    assert!(!invalid.is_valid());
+    assert!(invalid.find_in("(unclosed").is_empty());
+    assert!(SearchQuery::new("(unclosed").is_valid());
+}
+
+#[test]
+fn test_replacements_expand_groups() {
+    let query = regex(r"(\w+)@(?<host>\w+)");
+    let text = "ann@home bob@work";
+    let matches = query.find_in(text);
+    assert_eq!(
+        query.replacements(text, &matches, "${host}:$1 $$"),
+        vec!["home:ann $", "work:bob $"]
+    );
+
+    // Plain queries replace literally
+    let query = SearchQuery::new("ann");
+    assert_eq!(
+        query.replacements(text, &query.find_in(text), "$1"),
+        vec!["$1"]
+    );
+}
+
+#[test]
+fn test_replace_match_moves_to_next() {
+    let mut engine = engine_with("a a a");
+    engine.set_search(SearchQuery::new("a"));
+
+    assert!(engine.replace_match("aa"));
+    assert_eq!(engine.state().to_string(), "aa a a");
+    // The match after the replacement is selected, not one inside it
+    assert_eq!(engine.current_match(), Some(2));
+    assert_eq!(
+        engine.state().primary_selection().range(),
+        (pos(0, 3), pos(0, 4))
+    );
+
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    assert!(!engine.replace_match("b"));
+    assert_eq!(engine.state().to_string(), "aa a a");
+    assert_eq!(engine.current_match(), Some(0));
+}
+
+#[test]
+fn test_replace_all_is_one_undo_step() {
+    let mut engine = engine_with("x1 y22 x333");
+    engine.set_search(regex(r"([a-z])(\d+)"));
+    assert_eq!(engine.search_matches().len(), 3);
+
+    assert_eq!(engine.replace_all("$2$1"), 3);
+    assert_eq!(engine.state().to_string(), "1x 22y 333x");
+    assert!(engine.search_matches().is_empty());
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "x1 y22 x333");
+    assert_eq!(engine.search_matches().len(), 3);
+}
+
+#[test]
+fn test_replace_within_selection() {
+    let mut engine = engine_with("cat\ncat\ncat\ncat");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 1, column: 0 });
+    engine.handle_action(EditorAction::ExtendSelection { row: 2, column: 3 });
+
+    engine.set_search_in_selection(true);
+    // The selection stays until there is a query
+    assert_eq!(
+        engine.state().primary_selection().range(),
+        (pos(1, 0), pos(2, 3))
+    );
+    engine.set_search(SearchQuery::new("cat"));
+    assert!(engine.is_search_in_selection());
+    assert_eq!(
+        engine.search_matches(),
+        &[(pos(1, 0), pos(1, 3)), (pos(2, 0), pos(2, 3))]
+    );
+
+    assert_eq!(engine.replace_all("dog"), 2);
+    assert_eq!(engine.state().to_string(), "cat\ndog\ndog\ncat");
+
+    // The replaced text is still inside the selection
+    engine.set_search(SearchQuery::new("dog"));
+    assert_eq!(engine.search_matches().len(), 2);
+
+    engine.set_search_in_selection(false);
+    engine.set_search(SearchQuery::new("cat"));
+    assert_eq!(engine.search_matches().len(), 2);
+    assert_eq!(engine.search_matches()[0], (pos(0, 0), pos(0, 3)));
+}
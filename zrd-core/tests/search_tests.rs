@@ -0,0 +1,172 @@
+mod common;
+
+use common::engine_with;
+use zrd_core::{BufferPosition, EditorAction, EditorEngine, SearchQuery, Selection};
+
+fn pos(row: usize, column: usize) -> BufferPosition {
+    BufferPosition::new(row, column)
+}
+
+fn selected(engine: &EditorEngine) -> (BufferPosition, BufferPosition) {
+    engine.state().primary_selection().range()
+}
+
+#[test]
+fn test_find_in_options() {
+    let text = "Cat concat cat_x cat. CAT";
+    let mut query = SearchQuery::new("cat");
+    assert_eq!(
+        query.find_in(text),
+        vec![0..3, 7..10, 11..14, 17..20, 22..25]
+    );
+
+    query.case_sensitive = true;
+    assert_eq!(query.find_in(text), vec![7..10, 11..14, 17..20]);
+
+    query.whole_word = true;
+    assert_eq!(query.find_in(text), vec![17..20]);
+
+    // Only word characters at the edges of the query need a boundary
+    let query = SearchQuery {
+        whole_word: true,
+        ..SearchQuery::new(".c")
+    };
+    assert_eq!(query.find_in("a.cat a.c"), vec![7..9]);
+    assert!(SearchQuery::new("").find_in(text).is_empty());
+}
+
+#[test]
+fn test_find_ignores_case_beyond_ascii() {
+    let query = SearchQuery::new("straße");
+    assert_eq!(query.find_in("STRASSE Straße STRAẞE"), vec![8..15, 16..24]);
+}
+
+#[test]
+fn test_search_selects_first_match_from_cursor() {
+    let mut engine = engine_with("foo bar\nfoo baz\nfoo");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 2 });
+
+    engine.set_search(SearchQuery::new("f"));
+    assert_eq!(engine.search_matches().len(), 3);
+    assert_eq!(selected(&engine), (pos(1, 0), pos(1, 1)));
+    assert_eq!(engine.current_match(), Some(1));
+
+    // Typing more searches again from the same place
+    engine.set_search(SearchQuery::new("foo b"));
+    assert_eq!(selected(&engine), (pos(1, 0), pos(1, 5)));
+
+    // Nothing matches: back to where the find started
+    engine.set_search(SearchQuery::new("foo q"));
+    assert!(engine.search_matches().is_empty());
+    assert_eq!(
+        engine.state().primary_selection(),
+        Selection::caret(pos(0, 2))
+    );
+    assert_eq!(engine.current_match(), None);
+}
+
+#[test]
+fn test_find_next_and_previous_wrap() {
+    let mut engine = engine_with("a1 a2\na3");
+    engine.set_search(SearchQuery::new("a"));
+    assert_eq!(engine.current_match(), Some(0));
+
+    engine.handle_action(EditorAction::FindNext);
+    engine.handle_action(EditorAction::FindNext);
+    assert_eq!(selected(&engine), (pos(1, 0), pos(1, 1)));
+    engine.handle_action(EditorAction::FindNext);
+    assert_eq!(engine.current_match(), Some(0));
+    engine.handle_action(EditorAction::FindPrevious);
+    assert_eq!(engine.current_match(), Some(2));
+
+    // From a cursor between matches
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 2 });
+    engine.handle_action(EditorAction::FindPrevious);
+    assert_eq!(engine.current_match(), Some(0));
+}
+
+#[test]
+fn test_matches_follow_edits() {
+    let mut engine = engine_with("x = x + 1");
+    engine.set_search(SearchQuery::new("x"));
+    assert_eq!(engine.search_matches().len(), 2);
+
+    engine.insert_text(pos(0, 9), " - x");
+    assert_eq!(engine.search_matches().len(), 3);
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.search_matches().len(), 2);
+
+    engine.clear_search();
+    assert!(engine.search_matches().is_empty());
+    assert_eq!(engine.search_query(), None);
+}
+
+#[test]
+fn test_queries_that_span_lines() {
+    let regex = |text: &str| SearchQuery {
+        regex: true,
+        ..SearchQuery::new(text)
+    };
+    assert!(!SearchQuery::new("a b").can_span_lines());
+    assert!(SearchQuery::new("a\nb").can_span_lines());
+    assert!(!regex(r"^\w+ \d$").can_span_lines());
+    assert!(!regex(r"[a-z]+\b").can_span_lines());
+    assert!(regex(r"a\s+b").can_span_lines());
+    assert!(regex(r"[^x]").can_span_lines());
+    assert!(regex(r"(?s)a.b").can_span_lines());
+    assert!(regex(r"\Aa").can_span_lines());
+    assert!(!regex("(unclosed").can_span_lines());
+}
+
+/// Check the matches kept up to date are those found afresh
+fn assert_matches_current(engine: &EditorEngine) {
+    let mut fresh = engine_with(&engine.state().to_string());
+    fresh.set_search(engine.search_query().unwrap().clone());
+    assert_eq!(engine.search_matches(), fresh.search_matches());
+}
+
+#[test]
+fn test_matches_follow_edits_across_lines() {
+    for query in [
+        SearchQuery::new("ab"),
+        SearchQuery {
+            regex: true,
+            ..SearchQuery::new(r"^a\w*$")
+        },
+        SearchQuery {
+            regex: true,
+            ..SearchQuery::new(r"b\s*a")
+        },
+    ] {
+        let mut engine = engine_with("ab ab\nxab\r\nab\n\nb\na");
+        engine.set_search(query);
+        assert_matches_current(&engine);
+
+        // Splitting a match, then joining it back
+        engine.insert_text(pos(0, 1), "\n");
+        assert_matches_current(&engine);
+        engine.delete_text(pos(0, 1), pos(1, 0));
+        assert_matches_current(&engine);
+
+        // Lines added and removed above other matches
+        engine.replace_text(pos(1, 0), pos(3, 1), "a\nb\n\nab");
+        assert_matches_current(&engine);
+        engine.delete_text(pos(0, 0), pos(2, 0));
+        assert_matches_current(&engine);
+
+        // Several edits in one action
+        engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+        engine.handle_action(EditorAction::AddCursorBelow);
+        engine.handle_action(EditorAction::AddCursorBelow);
+        engine.handle_action(EditorAction::TypeString("a\nb".to_string()));
+        assert_matches_current(&engine);
+        engine.handle_action(EditorAction::Backspace);
+        assert_matches_current(&engine);
+
+        engine.handle_action(EditorAction::Undo);
+        engine.handle_action(EditorAction::Undo);
+        assert_matches_current(&engine);
+        engine.handle_action(EditorAction::Redo);
+        assert_matches_current(&engine);
+    }
+}
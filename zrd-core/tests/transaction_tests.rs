@@ -0,0 +1,107 @@
+use std::thread::sleep;
+use std::time::Duration;
+use zrd_core::{BufferPosition, EditorAction, EditorEngine};
+
+/// Longer than the undo chunk duration, so the next edit starts a new step
+fn pause() {
+    sleep(Duration::from_millis(600));
+}
+
+fn type_text(engine: &mut EditorEngine, text: &str) {
+    engine.handle_action(EditorAction::TypeString(text.to_string()));
+}
+
+#[test]
+fn test_transaction_is_one_undo_step() {
+    let mut engine = EditorEngine::new();
+    type_text(&mut engine, "one");
+
+    // Neither slow edits nor the edit just before split or join the step
+    engine.begin_transaction();
+    type_text(&mut engine, " two");
+    pause();
+    engine.handle_action(EditorAction::Newline);
+    type_text(&mut engine, "three");
+    engine.commit_transaction();
+    type_text(&mut engine, "!");
+    assert_eq!(engine.state().to_string(), "one two\nthree!");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "one two\nthree");
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "one");
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 3));
+    engine.handle_action(EditorAction::Redo);
+    assert_eq!(engine.state().to_string(), "one two\nthree");
+}
+
+#[test]
+fn test_abort_reverts_edits_and_cursors() {
+    let mut engine = EditorEngine::new();
+    type_text(&mut engine, "keep");
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 2 });
+
+    engine.begin_transaction();
+    type_text(&mut engine, "drop");
+    pause();
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::Backspace);
+    engine.abort_transaction();
+
+    assert_eq!(engine.state().to_string(), "keep");
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 2));
+    assert!(!engine.in_transaction());
+    // Nothing of the aborted edits is left to redo
+    assert!(!engine.undo_history().can_redo());
+    assert_eq!(engine.undo_history().entries().len(), 2);
+}
+
+#[test]
+fn test_transaction_closure() {
+    let mut engine = EditorEngine::new();
+    let result: Result<(), &str> = engine.transaction(|engine| {
+        type_text(engine, "text");
+        Err("failed")
+    });
+    assert_eq!(result, Err("failed"));
+    assert_eq!(engine.state().to_string(), "");
+
+    let length = engine.transaction(|engine| {
+        type_text(engine, "a");
+        type_text(engine, "b");
+        Ok::<_, ()>(engine.state().buffer.len_bytes())
+    });
+    assert_eq!(length, Ok(2));
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "");
+}
+
+#[test]
+fn test_nested_abort_keeps_outer_edits() {
+    let mut engine = EditorEngine::new();
+    engine.begin_transaction();
+    type_text(&mut engine, "outer ");
+    engine.begin_transaction();
+    type_text(&mut engine, "inner");
+    engine.abort_transaction();
+    type_text(&mut engine, "end");
+    engine.commit_transaction();
+    assert_eq!(engine.state().to_string(), "outer end");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "");
+    assert!(engine.undo_history().is_empty());
+}
+
+#[test]
+fn test_paste_is_its_own_step() {
+    let mut engine = EditorEngine::new();
+    type_text(&mut engine, "a");
+    engine.handle_action(EditorAction::Paste("b".to_string()));
+    type_text(&mut engine, "c");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "ab");
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "a");
+}
@@ -0,0 +1,173 @@
+mod common;
+
+use common::{temp_dir, temp_file};
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use zrd_core::{undo_file, EditorAction, EditorEngine, EditorState, UndoHistory};
+
+fn open(path: &Path, dir: &Path) -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    engine.set_undo_history_dir(Some(dir.to_path_buf()));
+    engine.load_from_file(path).unwrap();
+    engine
+}
+
+#[test]
+fn test_undo_history_survives_reopening() {
+    let dir = temp_dir("undo-reopen");
+    let path = temp_file("undo-reopen.txt", b"hello");
+
+    let mut engine = open(&path, &dir);
+    engine.handle_action(EditorAction::MoveToEndOfLine);
+    engine.handle_action(EditorAction::TypeString(" world".to_string()));
+    engine.save_to_file(&path).unwrap();
+
+    let mut engine = open(&path, &dir);
+    assert_eq!(engine.state().to_string(), "hello world");
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "hello");
+    engine.handle_action(EditorAction::Redo);
+    assert_eq!(engine.state().to_string(), "hello world");
+
+    fs::remove_file(path).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_history_dropped_when_file_changed() {
+    let dir = temp_dir("undo-changed");
+    let path = temp_file("undo-changed.txt", b"one");
+
+    let mut engine = open(&path, &dir);
+    engine.handle_action(EditorAction::TypeString("two ".to_string()));
+    engine.save_to_file(&path).unwrap();
+
+    // Edited by another program since the history was saved
+    fs::write(&path, "three").unwrap();
+    let mut engine = open(&path, &dir);
+    assert!(engine.undo_history().is_empty());
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "three");
+
+    fs::remove_file(path).unwrap();
+    fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn test_history_not_saved_without_directory() {
+    let path = temp_file("undo-no-dir.txt", b"text");
+
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+    engine.handle_action(EditorAction::TypeString("more ".to_string()));
+    engine.save_to_file(&path).unwrap();
+
+    let mut engine = EditorEngine::new();
+    engine.load_from_file(&path).unwrap();
+    assert!(engine.undo_history().is_empty());
+
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn test_history_round_trip_keeps_branches() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("a".to_string()));
+    engine.handle_action(EditorAction::Undo);
+    engine.handle_action(EditorAction::TypeString("b".to_string()));
+
+    let mut bytes = Vec::new();
+    engine.undo_history().write_to(&mut bytes).unwrap();
+    let restored = UndoHistory::read_from(bytes.as_slice()).unwrap();
+    assert_eq!(restored.entries(), engine.undo_history().entries());
+
+    assert!(UndoHistory::read_from(&bytes[..bytes.len() - 1]).is_err());
+    assert!(UndoHistory::read_from(&b"not a history"[..]).is_err());
+}
+
+/// An engine with "aaaa", "bbbb" and "cccc" typed as separate steps
+fn three_steps() -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    for text in ["aaaa", "bbbb", "cccc"] {
+        engine.begin_transaction();
+        engine.handle_action(EditorAction::TypeString(text.to_string()));
+        engine.commit_transaction();
+    }
+    engine
+}
+
+/// The history written within one byte less than it needs
+fn written_short(engine: &EditorEngine) -> UndoHistory {
+    let mut full = Vec::new();
+    engine.undo_history().write_to(&mut full).unwrap();
+    let mut bytes = Vec::new();
+    engine
+        .undo_history()
+        .write_to_within(&mut bytes, full.len() - 1)
+        .unwrap();
+    assert!(bytes.len() < full.len());
+    UndoHistory::read_from(bytes.as_slice()).unwrap()
+}
+
+fn undo_all(history: &mut UndoHistory, state: &mut EditorState) {
+    while history.undo(state) {}
+}
+
+#[test]
+fn test_trimmed_history_drops_oldest_steps_before_current() {
+    let engine = three_steps();
+    let mut history = written_short(&engine);
+    let mut state = engine.state().clone();
+
+    // The original state moved forward to after the first step
+    undo_all(&mut history, &mut state);
+    assert_eq!(state.to_string(), "aaaa");
+    while history.redo(&mut state) {}
+    assert_eq!(state.to_string(), "aaaabbbbcccc");
+}
+
+#[test]
+fn test_trimmed_history_drops_newest_undone_steps() {
+    let mut engine = three_steps();
+    for _ in 0..3 {
+        engine.handle_action(EditorAction::Undo);
+    }
+    let memory_used = engine.undo_history().memory_used();
+    let mut history = written_short(&engine);
+    assert_eq!(engine.undo_history().memory_used(), memory_used);
+
+    // Redo replays onto the original text
+    let mut state = engine.state().clone();
+    while history.redo(&mut state) {}
+    assert_eq!(state.to_string(), "aaaabbbb");
+    undo_all(&mut history, &mut state);
+    assert_eq!(state.to_string(), "");
+}
+
+#[test]
+fn test_prune_removes_old_sidecars() {
+    let dir = temp_dir("undo-prune");
+    let old = dir.join("old.undo");
+    let recent = dir.join("recent.undo");
+    let unrelated = dir.join("notes.txt");
+    for path in [&old, &recent, &unrelated] {
+        fs::write(path, "x").unwrap();
+    }
+    let long_ago = SystemTime::now() - undo_file::MAX_AGE - Duration::from_secs(60);
+    for path in [&old, &unrelated] {
+        fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(long_ago)
+            .unwrap();
+    }
+
+    undo_file::prune(&dir);
+    assert!(!old.exists());
+    assert!(recent.exists());
+    assert!(unrelated.exists());
+
+    fs::remove_dir_all(dir).unwrap();
+}
@@ -0,0 +1,122 @@
+use std::thread::sleep;
+use std::time::Duration;
+use zrd_core::{BufferPosition, EditorAction, EditorEngine, LineEnding};
+
+/// Longer than the undo chunk duration, so the next edit starts a new step
+fn pause() {
+    sleep(Duration::from_millis(600));
+}
+
+#[test]
+fn test_undo_redo_restore_text_and_cursor() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("hello world".to_string()));
+    pause();
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 5 });
+    engine.handle_action(EditorAction::DeleteWordLeft);
+    assert_eq!(engine.state().to_string(), " world");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "hello world");
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 5));
+
+    engine.handle_action(EditorAction::Redo);
+    assert_eq!(engine.state().to_string(), " world");
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 0));
+
+    engine.handle_action(EditorAction::Undo);
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "");
+}
+
+#[test]
+fn test_quick_edits_group_into_one_step() {
+    let mut engine = EditorEngine::new();
+    for c in "abc".chars() {
+        engine.handle_action(EditorAction::TypeCharacter(c));
+    }
+    pause();
+    for c in "def".chars() {
+        engine.handle_action(EditorAction::TypeCharacter(c));
+    }
+    assert_eq!(engine.undo_history().len(), 2);
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "abc");
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 3));
+}
+
+#[test]
+fn test_edit_after_undo_starts_branch() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("one".to_string()));
+    engine.handle_action(EditorAction::Undo);
+    engine.handle_action(EditorAction::TypeString("two".to_string()));
+    engine.handle_action(EditorAction::Redo);
+    assert_eq!(engine.state().to_string(), "two");
+    assert!(!engine.undo_history().can_redo());
+
+    // The undone edit is kept on its own branch
+    engine.handle_action(EditorAction::PreviousUndoBranch);
+    assert_eq!(engine.state().to_string(), "one");
+    engine.handle_action(EditorAction::NextUndoBranch);
+    assert_eq!(engine.state().to_string(), "two");
+}
+
+#[test]
+fn test_history_memory_follows_edits_not_document() {
+    let mut engine = EditorEngine::new();
+    let line = "x".repeat(99) + "\n";
+    engine.handle_action(EditorAction::TypeString(line.repeat(10_000)));
+    engine.handle_action(EditorAction::SetCursorPosition { row: 0, column: 0 });
+    let after_load = engine.undo_history().memory_used();
+
+    // Line moves always start a new undo step
+    pause();
+    for _ in 0..20 {
+        engine.handle_action(EditorAction::MoveLineDown);
+    }
+    assert_eq!(engine.undo_history().len(), 21);
+    // Twenty small steps add far less than one copy of the document
+    assert!(engine.undo_history().memory_used() - after_load < 20_000);
+}
+
+#[test]
+fn test_memory_limit_drops_oldest_steps() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("a\nb\nc".to_string()));
+    for _ in 0..10 {
+        engine.handle_action(EditorAction::MoveLineUp);
+        engine.handle_action(EditorAction::MoveLineDown);
+    }
+    let steps = engine.undo_history().len();
+
+    engine.set_undo_memory_limit(1024);
+    assert!(engine.undo_history().len() < steps);
+    assert!(!engine.undo_history().is_empty());
+    assert!(engine.undo_history().memory_used() <= 1024);
+
+    // Whatever is left still undoes cleanly, to one of the two orders the
+    // lines alternated between
+    while !engine.undo_history().is_empty() {
+        engine.handle_action(EditorAction::Undo);
+    }
+    let text = engine.state().to_string();
+    assert!(text == "a\nb\nc" || text == "a\nc\nb", "{text:?}");
+}
+
+#[test]
+fn test_undo_line_ending_conversion() {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString("a\nb\n".to_string()));
+    pause();
+    engine.handle_action(EditorAction::ConvertLineEndings(LineEnding::Crlf));
+    assert_eq!(engine.state().to_string(), "a\r\nb\r\n");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "a\nb\n");
+    assert_eq!(engine.state().line_ending, LineEnding::Lf);
+
+    engine.handle_action(EditorAction::Redo);
+    assert_eq!(engine.state().line_ending, LineEnding::Crlf);
+}
@@ -0,0 +1,109 @@
+use std::thread::sleep;
+use std::time::Duration;
+use zrd_core::undo::ORIGINAL_STATE;
+use zrd_core::{EditorAction, EditorEngine};
+
+/// Longer than the undo chunk duration, so the next edit starts a new step
+fn pause() {
+    sleep(Duration::from_millis(600));
+}
+
+fn type_text(engine: &mut EditorEngine, text: &str) {
+    engine.handle_action(EditorAction::TypeString(text.to_string()));
+    pause();
+}
+
+/// "a" then "ab", undo to "a", then "ac": two branches under "a"
+fn branched_engine() -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    type_text(&mut engine, "a");
+    type_text(&mut engine, "b");
+    engine.handle_action(EditorAction::Undo);
+    type_text(&mut engine, "c");
+    engine
+}
+
+#[test]
+fn test_undo_redo_follow_current_branch() {
+    let mut engine = branched_engine();
+    assert_eq!(engine.state().to_string(), "ac");
+
+    engine.handle_action(EditorAction::Undo);
+    assert_eq!(engine.state().to_string(), "a");
+    engine.handle_action(EditorAction::Redo);
+    assert_eq!(engine.state().to_string(), "ac");
+}
+
+#[test]
+fn test_redo_returns_to_branch_last_undone() {
+    let mut engine = branched_engine();
+    engine.handle_action(EditorAction::PreviousUndoBranch);
+    assert_eq!(engine.state().to_string(), "ab");
+
+    engine.handle_action(EditorAction::Undo);
+    engine.handle_action(EditorAction::Redo);
+    assert_eq!(engine.state().to_string(), "ab");
+}
+
+#[test]
+fn test_history_entries_and_preview() {
+    let mut engine = branched_engine();
+    let entries = engine.undo_history().entries();
+    assert_eq!(entries.len(), 4);
+    assert_eq!(entries[0].id, ORIGINAL_STATE);
+    assert_eq!(entries[0].parent, None);
+    // Both branches hang off the "a" state
+    assert_eq!(entries[2].parent, Some(entries[1].id));
+    assert_eq!(entries[3].parent, Some(entries[1].id));
+    assert!(entries[3].is_current);
+    assert_eq!(entries[3].depth, 2);
+    assert_eq!(entries[3].inserted, 1);
+
+    // Previewing leaves the document alone
+    let preview = engine.preview_undo_state(entries[2].id).unwrap();
+    assert_eq!(preview.to_string(), "ab");
+    assert_eq!(engine.state().to_string(), "ac");
+
+    engine.handle_action(EditorAction::GoToUndoState(entries[2].id));
+    assert_eq!(engine.state().to_string(), "ab");
+    engine.handle_action(EditorAction::GoToUndoState(ORIGINAL_STATE));
+    assert_eq!(engine.state().to_string(), "");
+}
+
+#[test]
+fn test_earlier_and_later_by_time() {
+    let mut engine = EditorEngine::new();
+    type_text(&mut engine, "1");
+    type_text(&mut engine, "2");
+    type_text(&mut engine, "3");
+
+    // Each step is about 600ms after the previous one
+    engine.handle_action(EditorAction::UndoEarlier(Duration::from_millis(500)));
+    assert_eq!(engine.state().to_string(), "12");
+    engine.handle_action(EditorAction::UndoEarlier(Duration::from_secs(60)));
+    assert_eq!(engine.state().to_string(), "");
+    engine.handle_action(EditorAction::RedoLater(Duration::from_secs(60)));
+    assert_eq!(engine.state().to_string(), "123");
+}
+
+#[test]
+fn test_memory_limit_keeps_undone_steps_replayable() {
+    let mut engine = EditorEngine::new();
+    for text in ["aaaa", "bbbb", "cccc"] {
+        engine.begin_transaction();
+        engine.handle_action(EditorAction::TypeString(text.to_string()));
+        engine.commit_transaction();
+    }
+    for _ in 0..3 {
+        engine.handle_action(EditorAction::Undo);
+    }
+    assert_eq!(engine.state().to_string(), "");
+
+    let used = engine.undo_history().memory_used();
+    engine.set_undo_memory_limit(used - 1);
+    while engine.undo_history().can_redo() {
+        engine.handle_action(EditorAction::Redo);
+    }
+    // The newest step went, and the rest replays onto the original text
+    assert_eq!(engine.state().to_string(), "aaaabbbb");
+}
@@ -0,0 +1,70 @@
+use zrd_core::{BufferPosition, EditorAction, EditorEngine};
+
+fn engine_with(text: &str, cursor: BufferPosition) -> EditorEngine {
+    let mut engine = EditorEngine::new();
+    engine.handle_action(EditorAction::TypeString(text.to_string()));
+    engine.handle_action(EditorAction::SetCursorPosition {
+        row: cursor.row,
+        column: cursor.column,
+    });
+    engine
+}
+
+#[test]
+fn test_goal_column_survives_short_line() {
+    let mut engine = engine_with("long line\nab\nanother line", BufferPosition::new(0, 7));
+
+    engine.handle_action(EditorAction::MoveDown);
+    assert_eq!(engine.state().cursor, BufferPosition::new(1, 2));
+    engine.handle_action(EditorAction::MoveDown);
+    assert_eq!(engine.state().cursor, BufferPosition::new(2, 7));
+    engine.handle_action(EditorAction::MoveUp);
+    engine.handle_action(EditorAction::MoveUp);
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 7));
+}
+
+#[test]
+fn test_horizontal_motion_resets_goal() {
+    let mut engine = engine_with("long line\nab\nanother line", BufferPosition::new(0, 7));
+
+    engine.handle_action(EditorAction::MoveDown);
+    engine.handle_action(EditorAction::MoveLeft);
+    engine.handle_action(EditorAction::MoveDown);
+    assert_eq!(engine.state().cursor, BufferPosition::new(2, 1));
+    assert_eq!(engine.goal_column(), Some(1));
+}
+
+#[test]
+fn test_edit_resets_goal() {
+    let mut engine = engine_with("long line\n\nanother line", BufferPosition::new(0, 7));
+
+    engine.handle_action(EditorAction::MoveDown);
+    engine.handle_action(EditorAction::TypeCharacter('x'));
+    assert_eq!(engine.goal_column(), None);
+    engine.handle_action(EditorAction::MoveDown);
+    assert_eq!(engine.state().cursor, BufferPosition::new(2, 1));
+}
+
+#[test]
+fn test_select_keeps_goal_column() {
+    let mut engine = engine_with("abcdef\nx\nabcdef", BufferPosition::new(0, 4));
+
+    engine.handle_action(EditorAction::SelectDown);
+    engine.handle_action(EditorAction::SelectDown);
+    assert_eq!(engine.state().cursor, BufferPosition::new(2, 4));
+    assert_eq!(
+        engine.state().selection_anchor,
+        Some(BufferPosition::new(0, 4))
+    );
+}
+
+#[test]
+fn test_goal_is_a_display_column() {
+    // "日本" is four cells wide, so column 4 of the ASCII line lines up with its end
+    let mut engine = engine_with("abcdef\n日本語", BufferPosition::new(0, 4));
+
+    engine.handle_action(EditorAction::MoveDown);
+    assert_eq!(engine.state().cursor, BufferPosition::new(1, "日本".len()));
+    engine.handle_action(EditorAction::MoveUp);
+    assert_eq!(engine.state().cursor, BufferPosition::new(0, 4));
+}
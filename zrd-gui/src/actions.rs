@@ -9,6 +9,7 @@ actions!(
         ResetFontSize,
         Copy,
         Paste,
+        PasteFromHistory,
         Cut,
         Undo,
         Redo,
@@ -37,5 +38,26 @@ actions!(
         SelectWordRight,
         Tab,
         Outdent,
+        AddCursorAbove,
+        AddCursorBelow,
+        SelectNextOccurrence,
+        BlockSelectUp,
+        BlockSelectDown,
+        ToggleBookmark,
+        NextBookmark,
+        PreviousBookmark,
+        Find,
+        FindNext,
+        FindPrevious,
+        CloseFind,
+        FindBackspace,
+        ToggleCaseSensitive,
+        ToggleWholeWord,
+        ToggleRegex,
+        ToggleInSelection,
+        Replace,
+        ReplaceNext,
+        ReplaceAll,
+        SwitchFindField,
     ]
 );
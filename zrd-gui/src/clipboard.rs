@@ -0,0 +1,17 @@
+use gpui::{App, ClipboardItem};
+use std::io;
+use zrd_core::Clipboard;
+
+/// The system clipboard, reached through the app for the length of one action
+pub struct GpuiClipboard<'a>(pub &'a mut App);
+
+impl Clipboard for GpuiClipboard<'_> {
+    fn read(&mut self) -> Option<String> {
+        self.0.read_from_clipboard()?.text()
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.0.write_to_clipboard(ClipboardItem::new_string(text.to_string()));
+        Ok(())
+    }
+}
@@ -1,10 +1,11 @@
 use crate::actions::*;
+use crate::clipboard::GpuiClipboard;
 use crate::text_buffer::{BufferPosition, TextBuffer, WrapType};
 use crate::theme::Theme;
 use gpui::prelude::*;
 use gpui::*;
 use std::time::{Duration, Instant};
-use zrd_core::{EditorAction, EditorEngine};
+use zrd_core::{display_width, Autosave, EditorAction, EditorEngine, FileEncoding, SearchQuery};
 
 pub struct TextEditor {
     engine: EditorEngine,
@@ -18,6 +19,19 @@ pub struct TextEditor {
     last_modified: Option<std::time::SystemTime>,
     scroll_offset: f32,
     was_modified: bool,
+    find: Option<FindBar>,
+    autosave: Autosave,
+    /// Timer for the pending save, if any
+    autosave_task: Option<Task<()>>,
+}
+
+/// The open find bar
+struct FindBar {
+    query: SearchQuery,
+    /// Text to replace matches with, when replacing
+    replacement: Option<String>,
+    /// Whether typing goes to the replacement rather than the query
+    editing_replacement: bool,
 }
 
 // Global flag for exit code - starts true (will exit with error unless modified)
@@ -36,6 +50,27 @@ impl TextEditor {
     pub fn new(file_path: std::path::PathBuf, cx: &mut Context<Self>) -> Self {
         let mut engine = EditorEngine::new();
 
+        // Legacy encoding for files that are neither UTF-8 nor BOM-marked
+        if let Some(encoding) = std::env::var("ZRD_FALLBACK_ENCODING")
+            .ok()
+            .and_then(|label| FileEncoding::for_label(&label))
+        {
+            engine.set_fallback_encoding(encoding);
+        }
+
+        // Memory cap for undo history, in megabytes
+        if let Some(megabytes) = std::env::var("ZRD_UNDO_MEMORY_MB")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+        {
+            engine.set_undo_memory_limit(megabytes * 1024 * 1024);
+        }
+
+        // Keep undo history, bookmarks and the kill ring across sessions
+        engine.set_undo_history_dir(Some(EditorEngine::config_dir().join("undo")));
+        engine.set_bookmark_dir(Some(EditorEngine::config_dir().join("bookmarks")));
+        engine.set_kill_ring_file(Some(EditorEngine::config_dir().join("kill-ring")));
+
         // Ensure parent directory exists
         if let Some(parent) = file_path.parent() {
             let _ = std::fs::create_dir_all(parent);
@@ -43,13 +78,18 @@ impl TextEditor {
 
         // Load existing file if it exists
         let last_modified = if file_path.exists() {
-            let _ = engine.load_from_file(&file_path);
+            if let Err(err) = engine.load_from_file(&file_path) {
+                eprintln!("[zrd-gui] Could not open file: {} (autosave disabled)", err);
+            }
             std::fs::metadata(&file_path).ok().and_then(|m| m.modified().ok())
         } else {
             None
         };
 
-        let buffer = TextBuffer::from_string(engine.state().to_string());
+        let autosave = Autosave::new(&file_path);
+        let buffer = TextBuffer::from_buffer(&engine.state().buffer);
+        // Later edits reshape only the lines they touch
+        engine.record_changes(true);
         let focus_handle = cx.focus_handle();
 
         Self {
@@ -64,32 +104,76 @@ impl TextEditor {
             last_modified,
             scroll_offset: 0.0,
             was_modified: false,
+            find: None,
+            autosave,
+            autosave_task: None,
         }
     }
 
-    fn sync_buffer_from_engine(&mut self) {
-        let state = self.engine.state();
-        self.buffer = TextBuffer::from_string(state.to_string());
+    /// Reshape the lines the engine edited, returning whether any text changed
+    fn sync_buffer_from_engine(&mut self) -> bool {
+        let changes = self.engine.take_changes();
+        let changed = changes.iter().any(|change| !change.edits.is_empty());
+        let edits = changes.iter().flat_map(|change| &change.edits);
+        self.buffer.apply_edits(&self.engine.state().buffer, edits);
+        changed
     }
 
-    fn save_to_file(&self) {
-        let _ = self.engine.save_to_file(&self.file_path);
+    /// Save any edits still waiting, synced to disk, as before quitting
+    pub fn flush_autosave(&mut self) {
+        let saved = self.autosave.flush(&self.engine, true);
+        self.note_save(saved);
     }
 
-    fn sync_and_save(&mut self) {
-        self.sync_buffer_from_engine();
-        self.save_to_file();
-        self.was_modified = true;
-        mark_as_modified(); // Clear the exit error flag since we modified content
-        // Update last modified time after save
-        if let Ok(metadata) = std::fs::metadata(&self.file_path) {
-            if let Ok(modified) = metadata.modified() {
-                self.last_modified = Some(modified);
+    fn save_if_due(&mut self) {
+        let saved = self.autosave.save_if_due(&self.engine);
+        self.note_save(saved);
+    }
+
+    fn note_save(&mut self, saved: std::io::Result<bool>) {
+        if let Ok(true) = saved {
+            // Update last modified time after save
+            if let Ok(metadata) = std::fs::metadata(&self.file_path) {
+                if let Ok(modified) = metadata.modified() {
+                    self.last_modified = Some(modified);
+                }
             }
         }
+    }
+
+    fn sync_and_save(&mut self, cx: &mut Context<Self>) {
+        // Actions that changed nothing, such as pasting an empty clipboard,
+        // don't save
+        if self.sync_buffer_from_engine() {
+            self.autosave.schedule(&self.engine);
+            self.spawn_autosave_task(cx);
+            self.was_modified = true;
+            mark_as_modified(); // Clear the exit error flag since we modified content
+        }
         self.ensure_cursor_visible();
     }
 
+    /// Save once edits pause, and keep trying after a failed save; the next
+    /// edit replaces this task
+    fn spawn_autosave_task(&mut self, cx: &mut Context<Self>) {
+        if let Some(due) = self.autosave.due_at() {
+            let wait = due.saturating_duration_since(Instant::now());
+            self.autosave_task = Some(cx.spawn(async move |this, cx| {
+                cx.background_executor().timer(wait).await;
+                let _ = this.update(cx, |editor, cx| {
+                    editor.save_if_due();
+                    editor.spawn_autosave_task(cx);
+                });
+            }));
+        }
+    }
+
+    fn quit(&mut self, _: &Quit, _: &mut Window, cx: &mut Context<Self>) {
+        self.flush_autosave();
+        // The global handler exits
+        cx.propagate();
+    }
+
     fn ensure_cursor_visible(&mut self) {
         let line_height = self.get_font_size() * 1.5;
         let cursor_row = self.get_cursor().row as f32;
@@ -111,14 +195,18 @@ impl TextEditor {
     }
 
     fn check_and_reload(&mut self, cx: &mut Context<Self>) {
+        // Unsaved edits win over changes made elsewhere
+        if self.autosave.is_pending() {
+            return;
+        }
         if let Ok(metadata) = std::fs::metadata(&self.file_path) {
             if let Ok(modified) = metadata.modified() {
-                if self.last_modified.map_or(true, |last| modified > last) {
-                    if self.engine.load_from_file(&self.file_path).is_ok() {
-                        self.last_modified = Some(modified);
-                        self.sync_buffer_from_engine();
-                        cx.notify();
-                    }
+                if self.last_modified.is_none_or(|last| modified > last)
+                    && self.engine.load_from_file(&self.file_path).is_ok()
+                {
+                    self.last_modified = Some(modified);
+                    self.sync_buffer_from_engine();
+                    cx.notify();
                 }
             }
         }
@@ -131,6 +219,41 @@ impl TextEditor {
 
     fn set_cursor(&mut self, pos: BufferPosition) {
         self.engine.state_mut().cursor = zrd_core::BufferPosition::new(pos.row, pos.column);
+        self.engine.set_goal_column(None);
+    }
+
+    /// Move one wrapped row up or down, keeping the engine's goal column.
+    /// With several cursors the engine moves each by a whole line instead.
+    fn move_visual(&mut self, down: bool, extend_selection: bool) {
+        if !self.engine.state().extra_selections.is_empty() {
+            let action = match (down, extend_selection) {
+                (false, false) => EditorAction::MoveUp,
+                (true, false) => EditorAction::MoveDown,
+                (false, true) => EditorAction::SelectUp,
+                (true, true) => EditorAction::SelectDown,
+            };
+            self.engine.handle_action(action);
+            return;
+        }
+
+        let cursor = self.get_cursor();
+        let goal = self
+            .engine
+            .goal_column()
+            .unwrap_or_else(|| self.buffer.visual_column(cursor));
+        let target = if down {
+            self.buffer.move_visual_down(cursor, goal)
+        } else {
+            self.buffer.move_visual_up(cursor, goal)
+        };
+
+        if !extend_selection {
+            self.set_selection_anchor(None);
+        } else if self.get_selection_anchor().is_none() {
+            self.set_selection_anchor(Some(cursor));
+        }
+        self.set_cursor(target);
+        self.engine.set_goal_column(Some(goal));
     }
 
     fn get_selection_anchor(&self) -> Option<BufferPosition> {
@@ -145,29 +268,16 @@ impl TextEditor {
         self.engine.state().font_size
     }
 
-    fn selection_range(&self) -> Option<(BufferPosition, BufferPosition)> {
-        self.get_selection_anchor().map(|anchor| {
-            let cursor = self.get_cursor();
-            if anchor.row < cursor.row
-                || (anchor.row == cursor.row && anchor.column < cursor.column)
-            {
-                (anchor, cursor)
-            } else {
-                (cursor, anchor)
-            }
-        })
-    }
-
     // All action handlers delegate to engine
     fn undo(&mut self, _: &Undo, _window: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::Undo);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn redo(&mut self, _: &Redo, _window: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::Redo);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
@@ -191,31 +301,31 @@ impl TextEditor {
 
     fn handle_newline(&mut self, _: &Newline, _window: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::Newline);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn handle_backspace(&mut self, _: &Backspace, _window: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::Backspace);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn handle_delete(&mut self, _: &Delete, _window: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::Delete);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn delete_to_beginning_of_line(&mut self, _: &DeleteToBeginningOfLine, _window: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::DeleteToBeginningOfLine);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn delete_to_end_of_line(&mut self, _: &DeleteToEndOfLine, _window: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::DeleteToEndOfLine);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
@@ -244,13 +354,13 @@ impl TextEditor {
     }
 
     fn move_up(&mut self, _: &MoveUp, _window: &mut Window, cx: &mut Context<Self>) {
-        self.engine.handle_action(EditorAction::MoveUp);
+        self.move_visual(false, false);
         self.ensure_cursor_visible();
         cx.notify();
     }
 
     fn move_down(&mut self, _: &MoveDown, _window: &mut Window, cx: &mut Context<Self>) {
-        self.engine.handle_action(EditorAction::MoveDown);
+        self.move_visual(true, false);
         self.ensure_cursor_visible();
         cx.notify();
     }
@@ -269,31 +379,31 @@ impl TextEditor {
 
     fn move_line_up(&mut self, _: &MoveLineUp, _: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::MoveLineUp);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn move_line_down(&mut self, _: &MoveLineDown, _: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::MoveLineDown);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn delete_line(&mut self, _: &DeleteLine, _: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::DeleteLine);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn handle_tab(&mut self, _: &Tab, _: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::Tab);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
     fn handle_outdent(&mut self, _: &Outdent, _: &mut Window, cx: &mut Context<Self>) {
         self.engine.handle_action(EditorAction::Outdent);
-        self.sync_and_save();
+        self.sync_and_save(cx);
         cx.notify();
     }
 
@@ -308,64 +418,246 @@ impl TextEditor {
     }
 
     fn select_up(&mut self, _: &SelectUp, _: &mut Window, cx: &mut Context<Self>) {
-        self.engine.handle_action(EditorAction::SelectUp);
+        self.move_visual(false, true);
         cx.notify();
     }
 
     fn select_down(&mut self, _: &SelectDown, _: &mut Window, cx: &mut Context<Self>) {
-        self.engine.handle_action(EditorAction::SelectDown);
+        self.move_visual(true, true);
         cx.notify();
     }
 
     fn select_word_left(&mut self, _: &SelectWordLeft, _: &mut Window, cx: &mut Context<Self>) {
-        self.engine.handle_action(EditorAction::SelectWordLeft);
+        // Alt+Shift+Left resizes a block selection once one is started
+        if self.engine.block_selection().is_some() {
+            self.engine.handle_action(EditorAction::BlockSelectLeft);
+        } else {
+            self.engine.handle_action(EditorAction::SelectWordLeft);
+        }
         cx.notify();
     }
 
     fn select_word_right(&mut self, _: &SelectWordRight, _: &mut Window, cx: &mut Context<Self>) {
-        self.engine.handle_action(EditorAction::SelectWordRight);
+        if self.engine.block_selection().is_some() {
+            self.engine.handle_action(EditorAction::BlockSelectRight);
+        } else {
+            self.engine.handle_action(EditorAction::SelectWordRight);
+        }
         cx.notify();
     }
 
-    fn select_all(&mut self, _: &SelectAll, _window: &mut Window, cx: &mut Context<Self>) {
-        self.engine.handle_action(EditorAction::SelectAll);
+    fn block_select_up(&mut self, _: &BlockSelectUp, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::BlockSelectUp);
+        self.ensure_cursor_visible();
         cx.notify();
     }
 
-    fn copy(&mut self, _: &Copy, _: &mut Window, cx: &mut Context<Self>) {
-        if let Some((start, end)) = self.selection_range() {
-            let start_offset = self.buffer.position_to_byte_offset(start);
-            let end_offset = self.buffer.position_to_byte_offset(end);
-            let content = self.buffer.to_string();
-            if end_offset <= content.len() {
-                let selected_text = content[start_offset..end_offset].to_string();
-                cx.write_to_clipboard(selected_text.into());
+    fn block_select_down(&mut self, _: &BlockSelectDown, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::BlockSelectDown);
+        self.ensure_cursor_visible();
+        cx.notify();
+    }
+
+    fn add_cursor_above(&mut self, _: &AddCursorAbove, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::AddCursorAbove);
+        self.ensure_cursor_visible();
+        cx.notify();
+    }
+
+    fn add_cursor_below(&mut self, _: &AddCursorBelow, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::AddCursorBelow);
+        self.ensure_cursor_visible();
+        cx.notify();
+    }
+
+    fn select_next_occurrence(&mut self, _: &SelectNextOccurrence, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::SelectNextOccurrence);
+        self.ensure_cursor_visible();
+        cx.notify();
+    }
+
+    fn toggle_bookmark(&mut self, _: &ToggleBookmark, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::ToggleBookmark);
+        self.sync_and_save(cx);
+        // The text is unchanged, so only the bookmarks are saved
+        let _ = self.engine.save_bookmarks(&self.file_path);
+        cx.notify();
+    }
+
+    fn next_bookmark(&mut self, _: &NextBookmark, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::NextBookmark);
+        self.ensure_cursor_visible();
+        cx.notify();
+    }
+
+    fn previous_bookmark(&mut self, _: &PreviousBookmark, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::PreviousBookmark);
+        self.ensure_cursor_visible();
+        cx.notify();
+    }
+
+    fn find(&mut self, _: &Find, _: &mut Window, cx: &mut Context<Self>) {
+        self.open_find(false);
+        cx.notify();
+    }
+
+    fn replace(&mut self, _: &Replace, _: &mut Window, cx: &mut Context<Self>) {
+        self.open_find(true);
+        cx.notify();
+    }
+
+    /// Open the find bar with the selected text as the query, if it is on
+    /// one line. When replacing, a selection over several lines limits the
+    /// replacements. If the bar is open, switch to the replacement.
+    fn open_find(&mut self, replace: bool) {
+        if let Some(find) = self.find.as_mut() {
+            if replace {
+                find.replacement.get_or_insert_with(String::new);
+                find.editing_replacement = true;
             }
+            return;
+        }
+        let mut query = SearchQuery::default();
+        let selected = self.engine.selected_text();
+        if !selected.is_empty() && !selected.contains('\n') {
+            query.text = selected;
+            self.engine.set_search(query.clone());
+        } else if replace && selected.contains('\n') {
+            self.engine.set_search_in_selection(true);
         }
+        self.find = Some(FindBar {
+            query,
+            replacement: replace.then(String::new),
+            editing_replacement: false,
+        });
     }
 
-    fn cut(&mut self, _: &Cut, _: &mut Window, cx: &mut Context<Self>) {
-        if let Some((start, end)) = self.selection_range() {
-            let start_offset = self.buffer.position_to_byte_offset(start);
-            let end_offset = self.buffer.position_to_byte_offset(end);
-            let content = self.buffer.to_string();
-            if end_offset <= content.len() {
-                let selected_text = content[start_offset..end_offset].to_string();
-                cx.write_to_clipboard(selected_text.into());
-                self.engine.handle_action(EditorAction::Cut);
-                self.sync_and_save();
-                cx.notify();
+    fn close_find(&mut self, _: &CloseFind, _: &mut Window, cx: &mut Context<Self>) {
+        // The match found stays selected
+        self.find = None;
+        self.engine.clear_search();
+        cx.notify();
+    }
+
+    fn find_next(&mut self, _: &FindNext, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::FindNext);
+        self.ensure_cursor_visible();
+        cx.notify();
+    }
+
+    fn find_previous(&mut self, _: &FindPrevious, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::FindPrevious);
+        self.ensure_cursor_visible();
+        cx.notify();
+    }
+
+    fn find_backspace(&mut self, _: &FindBackspace, _: &mut Window, cx: &mut Context<Self>) {
+        self.edit_find_field(cx, |text| {
+            text.pop();
+        });
+    }
+
+    fn replace_next(&mut self, _: &ReplaceNext, _: &mut Window, cx: &mut Context<Self>) {
+        if let Some(replacement) = self.find.as_ref().and_then(|find| find.replacement.clone()) {
+            self.engine.replace_match(&replacement);
+            self.sync_and_save(cx);
+            cx.notify();
+        }
+    }
+
+    fn replace_all(&mut self, _: &ReplaceAll, _: &mut Window, cx: &mut Context<Self>) {
+        if let Some(replacement) = self.find.as_ref().and_then(|find| find.replacement.clone()) {
+            if self.engine.replace_all(&replacement) > 0 {
+                self.sync_and_save(cx);
             }
+            cx.notify();
         }
     }
 
-    fn paste(&mut self, _: &Paste, _: &mut Window, cx: &mut Context<Self>) {
-        if let Some(clipboard_item) = cx.read_from_clipboard() {
-            if let Some(text) = clipboard_item.text() {
-                self.engine.handle_action(EditorAction::Paste(text));
-                self.sync_and_save();
+    fn switch_find_field(&mut self, _: &SwitchFindField, _: &mut Window, cx: &mut Context<Self>) {
+        if let Some(find) = self.find.as_mut().filter(|find| find.replacement.is_some()) {
+            find.editing_replacement = !find.editing_replacement;
+            cx.notify();
+        }
+    }
+
+    fn toggle_regex(&mut self, _: &ToggleRegex, _: &mut Window, cx: &mut Context<Self>) {
+        self.update_find(cx, |query| query.regex = !query.regex);
+    }
+
+    fn toggle_in_selection(&mut self, _: &ToggleInSelection, _: &mut Window, cx: &mut Context<Self>) {
+        if self.find.is_some() {
+            self.engine.set_search_in_selection(!self.engine.is_search_in_selection());
+            self.ensure_cursor_visible();
+            cx.notify();
+        }
+    }
+
+    fn toggle_case_sensitive(&mut self, _: &ToggleCaseSensitive, _: &mut Window, cx: &mut Context<Self>) {
+        self.update_find(cx, |query| query.case_sensitive = !query.case_sensitive);
+    }
+
+    fn toggle_whole_word(&mut self, _: &ToggleWholeWord, _: &mut Window, cx: &mut Context<Self>) {
+        self.update_find(cx, |query| query.whole_word = !query.whole_word);
+    }
+
+    /// Change the find bar's query and find it again
+    fn update_find(&mut self, cx: &mut Context<Self>, change: impl FnOnce(&mut SearchQuery)) {
+        if let Some(find) = self.find.as_mut() {
+            change(&mut find.query);
+            self.engine.set_search(find.query.clone());
+            self.ensure_cursor_visible();
+            cx.notify();
+        }
+    }
+
+    /// Change the text being typed in the find bar
+    fn edit_find_field(&mut self, cx: &mut Context<Self>, change: impl FnOnce(&mut String)) {
+        match self.find.as_mut() {
+            Some(FindBar {
+                replacement: Some(replacement),
+                editing_replacement: true,
+                ..
+            }) => {
+                change(replacement);
                 cx.notify();
             }
+            Some(_) => self.update_find(cx, |query| change(&mut query.text)),
+            None => {}
+        }
+    }
+
+    fn select_all(&mut self, _: &SelectAll, _window: &mut Window, cx: &mut Context<Self>) {
+        self.engine.handle_action(EditorAction::SelectAll);
+        cx.notify();
+    }
+
+    fn copy(&mut self, _: &Copy, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine
+            .handle_action_with_clipboard(EditorAction::Copy, &mut GpuiClipboard(cx));
+    }
+
+    fn cut(&mut self, _: &Cut, _: &mut Window, cx: &mut Context<Self>) {
+        if !self.engine.selected_text().is_empty() {
+            self.engine
+                .handle_action_with_clipboard(EditorAction::Cut, &mut GpuiClipboard(cx));
+            self.sync_and_save(cx);
+            cx.notify();
+        }
+    }
+
+    fn paste(&mut self, _: &Paste, _: &mut Window, cx: &mut Context<Self>) {
+        self.engine
+            .handle_action_with_clipboard(EditorAction::PasteFromClipboard, &mut GpuiClipboard(cx));
+        self.sync_and_save(cx);
+        cx.notify();
+    }
+
+    fn paste_from_history(&mut self, _: &PasteFromHistory, _: &mut Window, cx: &mut Context<Self>) {
+        if !self.engine.kill_ring().is_empty() {
+            self.engine.handle_action(EditorAction::PasteFromHistory);
+            self.sync_and_save(cx);
+            cx.notify();
         }
     }
 
@@ -391,7 +683,7 @@ impl TextEditor {
         let font_size_px = px(self.get_font_size());
 
         for buffer_row in 0..self.buffer.line_count() {
-            self.buffer.get_or_shape_line(buffer_row, font_size_px, wrap_width, &text_system);
+            self.buffer.get_or_shape_line(buffer_row, font_size_px, wrap_width, text_system);
         }
 
         let mut visual_row_counter = 0;
@@ -402,9 +694,9 @@ impl TextEditor {
                     .map(|vl| (vl.byte_range.clone(), vl.wrap_type))
                     .collect();
 
-                for (_idx, (byte_range, _wrap_type)) in visual_lines_vec.iter().enumerate() {
+                for (byte_range, _wrap_type) in visual_lines_vec.iter() {
                     if visual_row_counter == visual_row {
-                        if let Some(layout) = self.buffer.get_or_shape_line(buffer_row, font_size_px, wrap_width, &text_system) {
+                        if let Some(layout) = self.buffer.get_or_shape_line(buffer_row, font_size_px, wrap_width, text_system) {
                             let full_line_x = layout.x_for_index(byte_range.start);
                             let relative_segment_x = relative_x + full_line_x;
                             let column_in_full_line = layout.closest_index_for_x(relative_segment_x);
@@ -416,7 +708,7 @@ impl TextEditor {
                 }
             } else {
                 if visual_row_counter == visual_row {
-                    if let Some(layout) = self.buffer.get_or_shape_line(buffer_row, font_size_px, wrap_width, &text_system) {
+                    if let Some(layout) = self.buffer.get_or_shape_line(buffer_row, font_size_px, wrap_width, text_system) {
                         let column = layout.closest_index_for_x(relative_x);
                         return BufferPosition::new(buffer_row, column);
                     }
@@ -495,6 +787,7 @@ impl TextEditor {
         let window_size = window.viewport_size();
         let wrap_width = window_size.width - px(32.0);
         let position = self.position_from_mouse(event.position, window, wrap_width);
+        self.engine.state_mut().extra_selections.clear();
 
         let now = Instant::now();
         let is_double_click = if let (Some(last_time), Some(last_pos)) =
@@ -533,7 +826,17 @@ impl TextEditor {
             let window_size = window.viewport_size();
             let wrap_width = window_size.width - px(32.0);
             let position = self.position_from_mouse(event.position, window, wrap_width);
-            self.set_cursor(position);
+            if event.modifiers.alt {
+                // Alt+drag selects a block of display columns
+                let line = self.engine.state().line(position.row).unwrap_or_default();
+                let cell = display_width::column_to_cell(&line, position.column);
+                self.engine.handle_action(EditorAction::ExtendBlockSelection {
+                    row: position.row,
+                    cell,
+                });
+            } else {
+                self.set_cursor(position);
+            }
             cx.notify();
         }
     }
@@ -554,8 +857,12 @@ impl TextEditor {
                 && !event.keystroke.modifiers.control
                 && !event.keystroke.modifiers.alt
             {
+                if self.find.is_some() {
+                    self.edit_find_field(cx, |text| text.push_str(key_char));
+                    return;
+                }
                 self.engine.handle_action(EditorAction::TypeString(key_char.clone()));
-                self.sync_and_save();
+                self.sync_and_save(cx);
                 cx.notify();
             }
         }
@@ -577,6 +884,80 @@ impl TextEditor {
 
         cx.notify();
     }
+
+    /// The find bar in the top right corner: query, match count and
+    /// options, and the replacement below when replacing
+    fn render_find_bar(&self, find: &FindBar) -> Div {
+        let query = &find.query;
+        let count = self.engine.search_matches().len();
+        let position = match self.engine.current_match() {
+            _ if !query.is_valid() => "Invalid regex".to_string(),
+            Some(index) => format!("{} of {}", index + 1, count),
+            None if query.text.is_empty() => String::new(),
+            None if count == 0 => "No results".to_string(),
+            None => format!("{} matches", count),
+        };
+        let option = |label: &'static str, enabled: bool| {
+            div()
+                .px_1()
+                .rounded(px(3.0))
+                .when(enabled, |div| div.bg(self.theme.selection))
+                .when(!enabled, |div| div.text_color(self.theme.text_muted))
+                .child(label)
+        };
+        // The field being typed in has a cursor after its text
+        let field = |text: &str, placeholder: &'static str, active: bool| {
+            div()
+                .flex()
+                .items_center()
+                .min_w(px(160.0))
+                .when(!text.is_empty(), |field| field.child(text.to_string()))
+                .when(active, |field| {
+                    field.child(div().w(px(2.0)).h(px(14.0)).bg(self.theme.cursor))
+                })
+                .when(text.is_empty(), |field| {
+                    field.child(div().text_color(self.theme.text_muted).child(placeholder))
+                })
+        };
+
+        let find_row = div()
+            .flex()
+            .items_center()
+            .gap_2()
+            .child(field(&query.text, "Find", !find.editing_replacement))
+            .child(div().text_color(self.theme.text_muted).child(position))
+            .child(option("Aa", query.case_sensitive))
+            .child(option("W", query.whole_word))
+            .child(option(".*", query.regex));
+        // How many replace all would replace
+        let replace_row = find.replacement.as_ref().map(|replacement| {
+            div()
+                .flex()
+                .items_center()
+                .gap_2()
+                .child(field(replacement, "Replace", find.editing_replacement))
+                .child(div().text_color(self.theme.text_muted).child(format!("All: {}", count)))
+                .child(option("Selection", self.engine.is_search_in_selection()))
+        });
+
+        div()
+            .absolute()
+            .top(px(8.0))
+            .right(px(16.0))
+            .flex()
+            .flex_col()
+            .gap_1()
+            .px_2()
+            .py_1()
+            .rounded(px(4.0))
+            .border_1()
+            .border_color(self.theme.text_muted)
+            .bg(self.theme.background)
+            .font_family("Monaco")
+            .text_size(px(12.0))
+            .child(find_row)
+            .children(replace_row)
+    }
 }
 
 impl Focusable for TextEditor {
@@ -589,15 +970,31 @@ impl Render for TextEditor {
     fn render(&mut self, _window: &mut Window, _cx: &mut Context<Self>) -> impl IntoElement {
         // Check for file changes on every render
         self.check_and_reload(_cx);
+        // Take changes from actions that don't save, such as cursor motion
+        self.sync_buffer_from_engine();
 
         let font_size_px = px(self.get_font_size());
-        let cursor = self.get_cursor();
         let is_empty = self.buffer.line_count() == 1 && self.buffer.line_len(0) == 0;
+        // Binary files are only viewable in the terminal editor's hex view
+        let placeholder = match self.engine.hex_document() {
+            Some(hex) => format!(
+                "Binary file ({} bytes), open it with zrd to edit in hex",
+                hex.bytes().len()
+            ),
+            None => "Start typing...".to_string(),
+        };
         let window_size = _window.viewport_size();
         let wrap_width = window_size.width - px(32.0);
 
         div()
             .track_focus(&self.focus_handle)
+            .when_some(self.find.as_ref(), |div, find| {
+                div.key_context(if find.editing_replacement {
+                    "FindBar replacing"
+                } else {
+                    "FindBar"
+                })
+            })
             .on_action(_cx.listener(Self::increase_font_size))
             .on_action(_cx.listener(Self::decrease_font_size))
             .on_action(_cx.listener(Self::reset_font_size))
@@ -623,9 +1020,32 @@ impl Render for TextEditor {
             .on_action(_cx.listener(Self::select_word_left))
             .on_action(_cx.listener(Self::select_word_right))
             .on_action(_cx.listener(Self::select_all))
+            .on_action(_cx.listener(Self::add_cursor_above))
+            .on_action(_cx.listener(Self::add_cursor_below))
+            .on_action(_cx.listener(Self::select_next_occurrence))
+            .on_action(_cx.listener(Self::block_select_up))
+            .on_action(_cx.listener(Self::block_select_down))
+            .on_action(_cx.listener(Self::toggle_bookmark))
+            .on_action(_cx.listener(Self::next_bookmark))
+            .on_action(_cx.listener(Self::previous_bookmark))
+            .on_action(_cx.listener(Self::find))
+            .on_action(_cx.listener(Self::close_find))
+            .on_action(_cx.listener(Self::find_next))
+            .on_action(_cx.listener(Self::find_previous))
+            .on_action(_cx.listener(Self::find_backspace))
+            .on_action(_cx.listener(Self::toggle_case_sensitive))
+            .on_action(_cx.listener(Self::toggle_whole_word))
+            .on_action(_cx.listener(Self::toggle_regex))
+            .on_action(_cx.listener(Self::toggle_in_selection))
+            .on_action(_cx.listener(Self::replace))
+            .on_action(_cx.listener(Self::replace_next))
+            .on_action(_cx.listener(Self::replace_all))
+            .on_action(_cx.listener(Self::switch_find_field))
+            .on_action(_cx.listener(Self::quit))
             .on_action(_cx.listener(Self::copy))
             .on_action(_cx.listener(Self::cut))
             .on_action(_cx.listener(Self::paste))
+            .on_action(_cx.listener(Self::paste_from_history))
             .on_action(_cx.listener(Self::undo))
             .on_action(_cx.listener(Self::redo))
             .on_action(_cx.listener(Self::delete_line))
@@ -660,7 +1080,7 @@ impl Render for TextEditor {
                                 .child(
                                     div()
                                         .text_color(self.theme.text_muted)
-                                        .child("Start typing..."),
+                                        .child(placeholder),
                                 )
                                 .child(
                                     div()
@@ -674,29 +1094,46 @@ impl Render for TextEditor {
                         )
                     })
                     .when(!is_empty, |parent| {
-                        let selection_range = self.selection_range();
+                        let selections = self.engine.state().selections();
+                        let selection_ranges: Vec<_> = selections
+                            .iter()
+                            .map(|selection| {
+                                let (start, end) = selection.range();
+                                (
+                                    BufferPosition::new(start.row, start.column),
+                                    BufferPosition::new(end.row, end.column),
+                                )
+                            })
+                            .collect();
+                        let bookmarks = self.engine.bookmarks();
+                        let matches = self.engine.search_matches();
                         let mut container = parent;
                         let text_system = _window.text_system();
 
                         for row in 0..self.buffer.line_count() {
-                            let line_text = self.buffer.line(row).unwrap_or("").to_string();
+                            let line_text = self.buffer.line(row).unwrap_or_default().into_owned();
 
                             self.buffer.get_or_shape_line(
                                 row,
                                 font_size_px,
                                 wrap_width,
-                                &text_system,
+                                text_system,
                             );
 
+                            let row_matches: Vec<_> = matches
+                                [matches.partition_point(|found| found.1.row < row)..]
+                                .iter()
+                                .take_while(|found| found.0.row <= row)
+                                .copied()
+                                .collect();
+
                             if let Some(visual_lines) = self.buffer.get_visual_lines(row) {
                                 let visual_lines_vec: Vec<_> = visual_lines
                                     .iter()
                                     .map(|vl| (vl.byte_range.clone(), vl.wrap_type))
                                     .collect();
 
-                                for (_visual_idx, (byte_range, wrap_type)) in
-                                    visual_lines_vec.iter().enumerate()
-                                {
+                                for (byte_range, wrap_type) in visual_lines_vec.iter() {
                                     let segment_text = &line_text[byte_range.clone()];
                                     let mut display_text = segment_text.to_string();
 
@@ -704,9 +1141,16 @@ impl Render for TextEditor {
                                         display_text.push('-');
                                     }
 
-                                    let is_cursor_on_this_segment = row == cursor.row
-                                        && cursor.column >= byte_range.start
-                                        && cursor.column <= byte_range.end;
+                                    let cursor_columns: Vec<usize> = selections
+                                        .iter()
+                                        .map(|selection| selection.cursor)
+                                        .filter(|cursor| {
+                                            cursor.row == row
+                                                && cursor.column >= byte_range.start
+                                                && cursor.column <= byte_range.end
+                                        })
+                                        .map(|cursor| cursor.column)
+                                        .collect();
 
                                     let mut line_div = div()
                                         .relative()
@@ -717,7 +1161,46 @@ impl Render for TextEditor {
                                             display_text.clone(),
                                         )));
 
-                                    if let Some((sel_start, sel_end)) = selection_range {
+                                    // Find matches, drawn under the selection
+                                    for &(match_start, match_end) in &row_matches {
+                                        let from = if match_start.row == row {
+                                            match_start.column
+                                        } else {
+                                            0
+                                        };
+                                        let to = if match_end.row == row {
+                                            match_end.column
+                                        } else {
+                                            line_text.len()
+                                        };
+                                        let from = from.max(byte_range.start);
+                                        let to = to.min(byte_range.end);
+
+                                        if from < to {
+                                            if let Some(shaped) = self.buffer.get_or_shape_line(
+                                                row,
+                                                font_size_px,
+                                                wrap_width,
+                                                text_system,
+                                            ) {
+                                                let seg_x_offset = shaped.x_for_index(byte_range.start);
+                                                let match_x = shaped.x_for_index(from) - seg_x_offset;
+                                                let match_end_x = shaped.x_for_index(to) - seg_x_offset;
+
+                                                line_div = line_div.child(
+                                                    div()
+                                                        .absolute()
+                                                        .left(match_x)
+                                                        .top(px(0.0))
+                                                        .bottom(px(0.0))
+                                                        .w(match_end_x - match_x)
+                                                        .bg(self.theme.find_match),
+                                                );
+                                            }
+                                        }
+                                    }
+
+                                    for &(sel_start, sel_end) in &selection_ranges {
                                         if sel_start.row <= row && row <= sel_end.row {
                                             let seg_start = byte_range.start;
                                             let seg_end = byte_range.end;
@@ -741,7 +1224,7 @@ impl Render for TextEditor {
                                                     row,
                                                     font_size_px,
                                                     wrap_width,
-                                                    &text_system,
+                                                    text_system,
                                                 ) {
                                                     let seg_x_offset =
                                                         shaped.x_for_index(seg_start);
@@ -767,16 +1250,32 @@ impl Render for TextEditor {
                                         }
                                     }
 
-                                    if is_cursor_on_this_segment {
+                                    // Mark bookmarked lines in the left padding
+                                    if byte_range.start == 0
+                                        && bookmarks.binary_search(&row).is_ok()
+                                    {
+                                        line_div = line_div.child(
+                                            div()
+                                                .absolute()
+                                                .left(px(-12.0))
+                                                .top(px(2.0))
+                                                .bottom(px(2.0))
+                                                .w(px(4.0))
+                                                .rounded(px(1.0))
+                                                .bg(self.theme.bookmark),
+                                        );
+                                    }
+
+                                    for cursor_column in cursor_columns {
                                         if let Some(shaped) = self.buffer.get_or_shape_line(
                                             row,
                                             font_size_px,
                                             wrap_width,
-                                            &text_system,
+                                            text_system,
                                         ) {
                                             let seg_x_offset = shaped.x_for_index(byte_range.start);
                                             let cursor_x = shaped.x_for_index(
-                                                cursor.column.min(line_text.len()),
+                                                cursor_column.min(line_text.len()),
                                             ) - seg_x_offset;
 
                                             line_div = line_div.child(
@@ -798,5 +1297,8 @@ impl Render for TextEditor {
                         container
                     }),
             )
+            .when_some(self.find.as_ref(), |parent, find| {
+                parent.child(self.render_find_bar(find))
+            })
     }
 }
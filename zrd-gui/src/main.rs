@@ -1,4 +1,5 @@
 mod actions;
+mod clipboard;
 mod editor;
 mod text_buffer;
 mod theme;
@@ -6,9 +7,18 @@ mod theme;
 use actions::*;
 use editor::{should_exit_with_error, TextEditor};
 use gpui::*;
+#[cfg(unix)]
+use signal_hook::consts::SIGHUP;
+use signal_hook::consts::TERM_SIGNALS;
 use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 use zrd_core::EditorEngine;
 
+/// How often a termination signal is checked for
+const SIGNAL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
 fn resolve_file_path() -> PathBuf {
     let args: Vec<String> = std::env::args().collect();
 
@@ -31,6 +41,18 @@ fn resolve_file_path() -> PathBuf {
 fn main() {
     let file_path = resolve_file_path();
 
+    // A terminal closing or a kill saves pending edits, like quitting does
+    let quit_requested = Arc::new(AtomicBool::new(false));
+    #[cfg(unix)]
+    let signals = std::iter::once(&SIGHUP).chain(TERM_SIGNALS);
+    #[cfg(not(unix))]
+    let signals = TERM_SIGNALS.iter();
+    for signal in signals {
+        if let Err(err) = signal_hook::flag::register(*signal, Arc::clone(&quit_requested)) {
+            eprintln!("[zrd-gui] Could not handle signal {}: {}", signal, err);
+        }
+    }
+
     Application::new().run(move |app| {
         // Global quit handler - force exit immediately
         app.on_action(|_: &Quit, _app| {
@@ -50,6 +72,7 @@ fn main() {
             KeyBinding::new("cmd-c", Copy, None),
             KeyBinding::new("cmd-x", Cut, None),
             KeyBinding::new("cmd-v", Paste, None),
+            KeyBinding::new("cmd-shift-v", PasteFromHistory, None),
             KeyBinding::new("cmd-z", Undo, None),
             KeyBinding::new("cmd-shift-z", Redo, None),
             KeyBinding::new("cmd-shift-k", DeleteLine, None),
@@ -76,6 +99,31 @@ fn main() {
             KeyBinding::new("alt-shift-right", SelectWordRight, None),
             KeyBinding::new("alt-up", MoveLineUp, None),
             KeyBinding::new("alt-down", MoveLineDown, None),
+            KeyBinding::new("cmd-alt-up", AddCursorAbove, None),
+            KeyBinding::new("cmd-alt-down", AddCursorBelow, None),
+            KeyBinding::new("cmd-d", SelectNextOccurrence, None),
+            KeyBinding::new("alt-shift-up", BlockSelectUp, None),
+            KeyBinding::new("alt-shift-down", BlockSelectDown, None),
+            KeyBinding::new("cmd-f2", ToggleBookmark, None),
+            KeyBinding::new("f2", NextBookmark, None),
+            KeyBinding::new("shift-f2", PreviousBookmark, None),
+            KeyBinding::new("cmd-f", Find, None),
+            KeyBinding::new("cmd-g", FindNext, None),
+            KeyBinding::new("cmd-shift-g", FindPrevious, None),
+            KeyBinding::new("cmd-alt-f", Replace, None),
+            // While the find bar is open these win over the bindings above,
+            // which were added earlier
+            KeyBinding::new("escape", CloseFind, Some("FindBar")),
+            KeyBinding::new("enter", FindNext, Some("FindBar")),
+            KeyBinding::new("shift-enter", FindPrevious, Some("FindBar")),
+            KeyBinding::new("backspace", FindBackspace, Some("FindBar")),
+            KeyBinding::new("alt-cmd-c", ToggleCaseSensitive, Some("FindBar")),
+            KeyBinding::new("alt-cmd-w", ToggleWholeWord, Some("FindBar")),
+            KeyBinding::new("alt-cmd-r", ToggleRegex, Some("FindBar")),
+            KeyBinding::new("alt-cmd-l", ToggleInSelection, Some("FindBar")),
+            KeyBinding::new("tab", SwitchFindField, Some("FindBar")),
+            KeyBinding::new("cmd-alt-enter", ReplaceAll, Some("FindBar")),
+            KeyBinding::new("enter", ReplaceNext, Some("FindBar && replacing")),
         ]);
 
         let window_options = WindowOptions {
@@ -105,14 +153,30 @@ fn main() {
             // Focus the editor so user can start typing immediately
             window.focus(&editor.focus_handle(app));
 
-            // Handle red X button click - force exit
-            window.on_window_should_close(app, |_window, _app| {
+            // Handle red X button click - save, then force exit
+            let closing_editor = editor.clone();
+            window.on_window_should_close(app, move |_window, app| {
                 eprintln!("[zrd-gui] Window should_close triggered (red X)");
+                closing_editor.update(app, |editor, _| editor.flush_autosave());
                 let exit_code = if should_exit_with_error() { 1 } else { 0 };
                 eprintln!("[zrd-gui] Exiting with code {}", exit_code);
                 std::process::exit(exit_code);
             });
 
+            let signalled_editor = editor.clone();
+            let quit_requested = Arc::clone(&quit_requested);
+            app.spawn(async move |cx| loop {
+                cx.background_executor().timer(SIGNAL_POLL_INTERVAL).await;
+                if quit_requested.load(Ordering::Relaxed) {
+                    eprintln!("[zrd-gui] Termination signal received");
+                    let _ = signalled_editor.update(cx, |editor, _| editor.flush_autosave());
+                    let exit_code = if should_exit_with_error() { 1 } else { 0 };
+                    eprintln!("[zrd-gui] Exiting with code {}", exit_code);
+                    std::process::exit(exit_code);
+                }
+            })
+            .detach();
+
             editor
         })
         .unwrap();
@@ -1,5 +1,7 @@
 use gpui::*;
+use std::borrow::Cow;
 use std::ops::Range;
+use zrd_core::display_width;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct BufferPosition {
@@ -17,6 +19,7 @@ impl BufferPosition {
     }
 }
 
+/// A position on a wrapped display row; `column` is in display cells
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct VisualPosition {
     pub visual_row: usize,
@@ -43,7 +46,7 @@ pub struct VisualLine {
 }
 
 pub struct TextBuffer {
-    lines: Vec<String>,
+    text: zrd_core::Buffer,
     line_layouts: Vec<Option<CachedLineLayout>>,
 }
 
@@ -56,186 +59,47 @@ pub struct CachedLineLayout {
 
 impl TextBuffer {
     pub fn new() -> Self {
-        Self {
-            lines: vec![String::new()],
-            line_layouts: vec![None],
-        }
+        Self::from_buffer(&zrd_core::Buffer::new())
     }
 
-    pub fn from_string(content: String) -> Self {
-        if content.is_empty() {
-            return Self::new();
-        }
-
-        let lines: Vec<String> = content.split('\n').map(|s| s.to_string()).collect();
-        let line_count = lines.len();
+    /// Share the engine's text storage; cloning the rope is cheap
+    pub fn from_buffer(text: &zrd_core::Buffer) -> Self {
+        let line_count = text.line_count();
         Self {
-            lines,
+            text: text.clone(),
             line_layouts: (0..line_count).map(|_| None).collect(),
         }
     }
 
-    pub fn to_string(&self) -> String {
-        self.lines.join("\n")
-    }
-
     pub fn line_count(&self) -> usize {
-        self.lines.len()
+        self.text.line_count()
     }
 
-    pub fn line(&self, row: usize) -> Option<&str> {
-        self.lines.get(row).map(|s| s.as_str())
+    pub fn line(&self, row: usize) -> Option<Cow<'_, str>> {
+        self.text.line(row)
     }
 
     pub fn line_len(&self, row: usize) -> usize {
-        self.lines.get(row).map(|s| s.len()).unwrap_or(0)
-    }
-
-    pub fn insert_char(&mut self, pos: BufferPosition, ch: char) {
-        if pos.row >= self.lines.len() {
-            return;
-        }
-
-        if ch == '\n' {
-            let line = &self.lines[pos.row];
-            let before = line[..pos.column].to_string();
-            let after = line[pos.column..].to_string();
-            self.lines[pos.row] = before;
-            self.lines.insert(pos.row + 1, after);
-            self.line_layouts.insert(pos.row + 1, None);
-            self.invalidate_layout(pos.row);
-        } else {
-            self.lines[pos.row].insert(pos.column, ch);
-            self.invalidate_layout(pos.row);
-        }
-    }
-
-    pub fn insert_str(&mut self, pos: BufferPosition, text: &str) {
-        if pos.row >= self.lines.len() {
-            return;
-        }
-
-        if !text.contains('\n') {
-            self.lines[pos.row].insert_str(pos.column, text);
-            self.invalidate_layout(pos.row);
-        } else {
-            let new_lines: Vec<&str> = text.split('\n').collect();
-            let line = &self.lines[pos.row];
-            let before = line[..pos.column].to_string();
-            let after = line[pos.column..].to_string();
-
-            self.lines[pos.row] = before + new_lines[0];
-            self.invalidate_layout(pos.row);
-
-            for (i, new_line) in new_lines.iter().enumerate().skip(1) {
-                if i == new_lines.len() - 1 {
-                    self.lines
-                        .insert(pos.row + i, new_line.to_string() + &after);
-                } else {
-                    self.lines.insert(pos.row + i, new_line.to_string());
-                }
-                self.line_layouts.insert(pos.row + i, None);
-            }
-        }
-    }
-
-    pub fn delete_char(&mut self, pos: BufferPosition) -> bool {
-        if pos.row >= self.lines.len() {
-            return false;
-        }
-
-        let line = &self.lines[pos.row];
-
-        if pos.column >= line.len() {
-            if pos.row + 1 < self.lines.len() {
-                let next_line = self.lines.remove(pos.row + 1);
-                self.lines[pos.row].push_str(&next_line);
-                self.line_layouts.remove(pos.row + 1);
-                self.invalidate_layout(pos.row);
-                return true;
-            }
-            return false;
-        }
-
-        let mut new_line = line.clone();
-        new_line.remove(pos.column);
-        self.lines[pos.row] = new_line;
-        self.invalidate_layout(pos.row);
-        true
-    }
-
-    pub fn backspace(&mut self, pos: BufferPosition) -> bool {
-        if pos.column > 0 {
-            let line = &self.lines[pos.row];
-            let mut new_pos = pos.column - 1;
-            while new_pos > 0 && !line.is_char_boundary(new_pos) {
-                new_pos -= 1;
-            }
-
-            let mut new_line = line.clone();
-            new_line.remove(new_pos);
-            self.lines[pos.row] = new_line;
-            self.invalidate_layout(pos.row);
-            true
-        } else if pos.row > 0 {
-            let line = self.lines.remove(pos.row);
-            self.lines[pos.row - 1].push_str(&line);
-            self.line_layouts.remove(pos.row);
-            self.invalidate_layout(pos.row - 1);
-            true
-        } else {
-            false
-        }
-    }
-
-    pub fn delete_range(&mut self, start: BufferPosition, end: BufferPosition) {
-        if start == end {
-            return;
-        }
-
-        let (start, end) =
-            if start.row > end.row || (start.row == end.row && start.column > end.column) {
-                (end, start)
-            } else {
-                (start, end)
-            };
-
-        if start.row == end.row {
-            let line = &self.lines[start.row];
-            let before = &line[..start.column];
-            let after = &line[end.column..];
-            self.lines[start.row] = before.to_string() + after;
-            self.invalidate_layout(start.row);
-        } else {
-            let start_line = &self.lines[start.row][..start.column];
-            let end_line = &self.lines[end.row][end.column..];
-            self.lines[start.row] = start_line.to_string() + end_line;
-
-            for _ in start.row + 1..=end.row {
-                if start.row + 1 < self.lines.len() {
-                    self.lines.remove(start.row + 1);
-                    self.line_layouts.remove(start.row + 1);
-                }
-            }
-            self.invalidate_layout(start.row);
-        }
-    }
-
-    pub fn position_to_byte_offset(&self, pos: BufferPosition) -> usize {
-        let mut offset = 0;
-        for row in 0..pos.row.min(self.lines.len()) {
-            offset += self.lines[row].len() + 1;
-        }
-        if pos.row < self.lines.len() {
-            offset += pos.column.min(self.lines[pos.row].len());
-        }
-        offset
+        self.text.line_len(row)
     }
 
-    fn invalidate_layout(&mut self, row: usize) {
-        if row < self.line_layouts.len() {
-            self.line_layouts[row] = None;
+    /// Take the engine's text after `edits`, dropping the layouts of only
+    /// the lines they touched
+    pub fn apply_edits<'a>(
+        &mut self,
+        text: &zrd_core::Buffer,
+        edits: impl IntoIterator<Item = &'a zrd_core::TextEdit>,
+    ) {
+        for edit in edits {
+            let old_rows = edit.old_rows();
+            let end = (*old_rows.end() + 1).min(self.line_layouts.len());
+            let start = (*old_rows.start()).min(end);
+            let new_rows = edit.new_rows().count();
+            self.line_layouts.splice(start..end, (0..new_rows).map(|_| None));
         }
+        self.text = text.clone();
+        // Stay in step with the text even if an edit was missed
+        self.line_layouts.resize_with(self.text.line_count(), || None);
     }
 
     pub fn invalidate_all_layouts(&mut self) {
@@ -251,16 +115,16 @@ impl TextBuffer {
         wrap_width: Pixels,
         text_system: &WindowTextSystem,
     ) -> Option<&ShapedLine> {
-        if row >= self.lines.len() {
+        if row >= self.line_count() {
             return None;
         }
 
-        let needs_reshaping = self.line_layouts[row].as_ref().map_or(true, |cached| {
+        let needs_reshaping = self.line_layouts[row].as_ref().is_none_or(|cached| {
             cached.font_size != font_size || cached.wrap_width != wrap_width
         });
 
         if needs_reshaping {
-            let line = &self.lines[row];
+            let line = self.text.line(row).unwrap_or_default().into_owned();
             let text = SharedString::from(line.clone());
 
             let run = TextRun {
@@ -279,7 +143,7 @@ impl TextBuffer {
             };
 
             let shaped = text_system.shape_line(text, font_size, &[run], None);
-            let visual_lines = self.compute_visual_lines(line, &shaped, wrap_width);
+            let visual_lines = self.compute_visual_lines(&line, &shaped, wrap_width);
 
             self.line_layouts[row] = Some(CachedLineLayout {
                 shaped_line: shaped,
@@ -403,17 +267,31 @@ impl TextBuffer {
     }
 
     pub fn visual_line_count(&self) -> usize {
+        // Lines not shaped yet count as a single row
         self.line_layouts
             .iter()
-            .filter_map(|layout| layout.as_ref())
-            .map(|layout| layout.visual_lines.len())
+            .map(|layout| layout.as_ref().map_or(1, |layout| layout.visual_lines.len()))
             .sum()
     }
 
+    /// Display cells between the start of a wrapped segment and a byte column
+    fn segment_cell(&self, row: usize, segment: &Range<usize>, column: usize) -> usize {
+        let line = self.text.line(row).unwrap_or_default();
+        let segment_text = line.get(segment.clone()).unwrap_or_default();
+        display_width::column_to_cell(segment_text, column - segment.start)
+    }
+
+    /// Byte column drawn at a display cell of a wrapped segment
+    fn segment_column(&self, row: usize, segment: &Range<usize>, cell: usize) -> usize {
+        let line = self.text.line(row).unwrap_or_default();
+        let segment_text = line.get(segment.clone()).unwrap_or_default();
+        segment.start + display_width::cell_to_column(segment_text, cell)
+    }
+
     pub fn buffer_to_visual(&self, buffer_pos: BufferPosition) -> VisualPosition {
         let mut visual_row = 0;
 
-        for row in 0..buffer_pos.row.min(self.lines.len()) {
+        for row in 0..buffer_pos.row.min(self.line_count()) {
             if let Some(visual_lines) = self.get_visual_lines(row) {
                 visual_row += visual_lines.len();
             } else {
@@ -423,23 +301,24 @@ impl TextBuffer {
 
         if let Some(visual_lines) = self.get_visual_lines(buffer_pos.row) {
             for (visual_line_idx, visual_line) in visual_lines.iter().enumerate() {
-                if buffer_pos.column >= visual_line.byte_range.start
-                    && buffer_pos.column < visual_line.byte_range.end
-                {
-                    return VisualPosition::new(
-                        visual_row + visual_line_idx,
-                        buffer_pos.column - visual_line.byte_range.start,
-                    );
-                }
-                if buffer_pos.column == visual_line.byte_range.end
-                    && visual_line_idx == visual_lines.len() - 1
+                let range = &visual_line.byte_range;
+                let is_last = visual_line_idx == visual_lines.len() - 1;
+                if (buffer_pos.column >= range.start && buffer_pos.column < range.end)
+                    || (buffer_pos.column == range.end && is_last)
                 {
                     return VisualPosition::new(
                         visual_row + visual_line_idx,
-                        buffer_pos.column - visual_line.byte_range.start,
+                        self.segment_cell(buffer_pos.row, range, buffer_pos.column),
                     );
                 }
             }
+        } else {
+            let range = 0..self.line_len(buffer_pos.row);
+            let column = buffer_pos.column.min(range.end);
+            return VisualPosition::new(
+                visual_row,
+                self.segment_cell(buffer_pos.row, &range, column),
+            );
         }
 
         VisualPosition::new(visual_row, 0)
@@ -448,28 +327,27 @@ impl TextBuffer {
     pub fn visual_to_buffer(&self, visual_pos: VisualPosition) -> BufferPosition {
         let mut visual_row_counter = 0;
 
-        for (buffer_row, _line) in self.lines.iter().enumerate() {
+        for buffer_row in 0..self.line_count() {
             if let Some(visual_lines) = self.get_visual_lines(buffer_row) {
-                for (_visual_line_idx, visual_line) in visual_lines.iter().enumerate() {
+                for visual_line in visual_lines {
                     if visual_row_counter == visual_pos.visual_row {
-                        let buffer_column = visual_line.byte_range.start
-                            + visual_pos.column.min(visual_line.byte_range.len());
-                        return BufferPosition::new(buffer_row, buffer_column);
+                        let range = &visual_line.byte_range;
+                        let column = self.segment_column(buffer_row, range, visual_pos.column);
+                        return BufferPosition::new(buffer_row, column);
                     }
                     visual_row_counter += 1;
                 }
             } else {
                 if visual_row_counter == visual_pos.visual_row {
-                    return BufferPosition::new(
-                        buffer_row,
-                        visual_pos.column.min(self.line_len(buffer_row)),
-                    );
+                    let range = 0..self.line_len(buffer_row);
+                    let column = self.segment_column(buffer_row, &range, visual_pos.column);
+                    return BufferPosition::new(buffer_row, column);
                 }
                 visual_row_counter += 1;
             }
         }
 
-        let last_row = self.lines.len().saturating_sub(1);
+        let last_row = self.line_count().saturating_sub(1);
         let last_col = self.line_len(last_row);
         BufferPosition::new(last_row, last_col)
     }
@@ -490,17 +368,24 @@ impl TextBuffer {
         None
     }
 
-    pub fn move_visual_up(&self, buffer_pos: BufferPosition) -> BufferPosition {
+    /// Display column of a position within its wrapped row
+    pub fn visual_column(&self, buffer_pos: BufferPosition) -> usize {
+        self.buffer_to_visual(buffer_pos).column
+    }
+
+    /// Position one wrapped row up, at the goal display column
+    pub fn move_visual_up(&self, buffer_pos: BufferPosition, goal_column: usize) -> BufferPosition {
         let visual_pos = self.buffer_to_visual(buffer_pos);
         if visual_pos.visual_row == 0 {
             return buffer_pos;
         }
 
-        let target_visual_pos = VisualPosition::new(visual_pos.visual_row - 1, visual_pos.column);
+        let target_visual_pos = VisualPosition::new(visual_pos.visual_row - 1, goal_column);
         self.visual_to_buffer(target_visual_pos)
     }
 
-    pub fn move_visual_down(&self, buffer_pos: BufferPosition) -> BufferPosition {
+    /// Position one wrapped row down, at the goal display column
+    pub fn move_visual_down(&self, buffer_pos: BufferPosition, goal_column: usize) -> BufferPosition {
         let visual_pos = self.buffer_to_visual(buffer_pos);
         let max_visual_row = self.visual_line_count().saturating_sub(1);
 
@@ -508,7 +393,7 @@ impl TextBuffer {
             return buffer_pos;
         }
 
-        let target_visual_pos = VisualPosition::new(visual_pos.visual_row + 1, visual_pos.column);
+        let target_visual_pos = VisualPosition::new(visual_pos.visual_row + 1, goal_column);
         self.visual_to_buffer(target_visual_pos)
     }
 
@@ -7,6 +7,8 @@ pub struct Theme {
     pub text_muted: Hsla,
     pub selection: Hsla,
     pub cursor: Hsla,
+    pub bookmark: Hsla,
+    pub find_match: Hsla,
 }
 
 impl Default for Theme {
@@ -17,6 +19,8 @@ impl Default for Theme {
             text_muted: hsla(0.61, 0.11, 0.44, 0.6),
             selection: hsla(0.61, 0.13, 0.28, 0.7),
             cursor: rgb(0x528bff).into(),
+            bookmark: rgb(0x56b6c2).into(),
+            find_match: hsla(0.11, 0.7, 0.5, 0.35),
         }
     }
 }
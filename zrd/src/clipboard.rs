@@ -0,0 +1,163 @@
+//! The clipboard Ctrl+C, Ctrl+X and Ctrl+V use. Copied text goes to the
+//! terminal with OSC 52, which works over SSH; to an external command such
+//! as `wl-copy` too, in case the terminal ignores OSC 52; and in any case to
+//! a register of our own. Pasting reads the system clipboard with the
+//! matching command if there is one, and otherwise the register.
+
+use std::io::{self, Read, Stdout, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::time::{Duration, Instant};
+use zrd_core::{Clipboard, MemoryClipboard, Osc52Clipboard};
+
+/// How long pasting waits for the paste command before giving up on it
+const PASTE_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Copy and paste commands tried when none are configured, with the
+/// environment variable that shows their display server is running
+const KNOWN_COMMANDS: &[(Option<&str>, &str, &str)] = &[
+    (Some("WAYLAND_DISPLAY"), "wl-copy", "wl-paste --no-newline"),
+    (
+        Some("DISPLAY"),
+        "xclip -selection clipboard",
+        "xclip -selection clipboard -o",
+    ),
+    (None, "pbcopy", "pbpaste"),
+];
+
+pub struct TerminalClipboard {
+    osc52: Option<Osc52Clipboard<Stdout>>,
+    copy_command: Option<String>,
+    paste_command: Option<String>,
+    register: MemoryClipboard,
+}
+
+impl TerminalClipboard {
+    /// Set up from the environment. `ZRD_OSC52=0` turns OSC 52 off, and
+    /// `ZRD_COPY_COMMAND` and `ZRD_PASTE_COMMAND` replace the commands found
+    /// on the `PATH`; set to an empty string, they turn them off.
+    pub fn from_env() -> Self {
+        let osc52 = std::env::var("ZRD_OSC52").map_or(true, |value| value != "0")
+            && terminal_supports_osc52();
+        let known = KNOWN_COMMANDS.iter().find(|(display, copy, _)| {
+            display.map_or(cfg!(target_os = "macos"), |var| {
+                std::env::var_os(var).is_some()
+            }) && copy.split_whitespace().next().is_some_and(is_on_path)
+        });
+        let command = |var: &str, default: Option<&str>| match std::env::var(var) {
+            Ok(command) => Some(command).filter(|command| !command.trim().is_empty()),
+            Err(_) => default.map(str::to_string),
+        };
+        Self {
+            osc52: osc52.then(|| Osc52Clipboard::new(io::stdout())),
+            copy_command: command("ZRD_COPY_COMMAND", known.map(|(_, copy, _)| *copy)),
+            paste_command: command("ZRD_PASTE_COMMAND", known.map(|(_, _, paste)| *paste)),
+            register: MemoryClipboard::new(),
+        }
+    }
+}
+
+impl Clipboard for TerminalClipboard {
+    fn read(&mut self) -> Option<String> {
+        self.paste_command
+            .as_deref()
+            .and_then(run_paste)
+            .filter(|text| !text.is_empty())
+            .or_else(|| self.register.read())
+    }
+
+    fn write(&mut self, text: &str) -> io::Result<()> {
+        self.register.write(text)?;
+        // Whether the terminal acted on OSC 52 can't be told, so the command
+        // runs as well; the register still has the text if both fail
+        if let Some(osc52) = &mut self.osc52 {
+            let _ = osc52.write(text);
+        }
+        if let Some(command) = &self.copy_command {
+            let _ = run_copy(command, text);
+        }
+        Ok(())
+    }
+}
+
+/// Whether the terminal may take OSC 52. Terminals known to ignore it are
+/// ruled out, except over SSH, where the one on the other end is unknown.
+fn terminal_supports_osc52() -> bool {
+    if std::env::var_os("SSH_TTY").is_some() {
+        return true;
+    }
+    let term_program = std::env::var("TERM_PROGRAM").unwrap_or_default();
+    let term = std::env::var("TERM").unwrap_or_default();
+    term_program != "Apple_Terminal" && term != "linux"
+}
+
+fn is_on_path(program: &str) -> bool {
+    std::env::var_os("PATH")
+        .is_some_and(|path| std::env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+        || Path::new(program).is_file()
+}
+
+fn command_from(line: &str) -> Option<Command> {
+    let mut words = line.split_whitespace();
+    let mut command = Command::new(words.next()?);
+    command.args(words).stderr(Stdio::null());
+    Some(command)
+}
+
+fn run_copy(line: &str, text: &str) -> io::Result<()> {
+    let mut command = command_from(line)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "empty copy command"))?;
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .spawn()?;
+    let written = match child.stdin.take() {
+        Some(mut stdin) => stdin.write_all(text.as_bytes()),
+        None => Ok(()),
+    };
+    // Some copy commands stay running to serve the clipboard, so the editor
+    // doesn't wait for them; a thread reaps the process once it exits
+    std::thread::spawn(move || child.wait());
+    written
+}
+
+/// Run a paste command for its output, killing it if it takes longer than
+/// `PASTE_TIMEOUT`, so that one that hangs can't freeze the editor
+fn run_paste(line: &str) -> Option<String> {
+    let mut child = command_from(line)?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let deadline = Instant::now() + PASTE_TIMEOUT;
+
+    let mut stdout = child.stdout.take()?;
+    let (sender, receiver) = mpsc::channel();
+    std::thread::spawn(move || {
+        let mut output = Vec::new();
+        let _ = sender.send(stdout.read_to_end(&mut output).map(|_| output));
+    });
+    let output = receiver
+        .recv_timeout(PASTE_TIMEOUT)
+        .ok()
+        .and_then(Result::ok);
+
+    let status = loop {
+        match child.try_wait() {
+            Ok(Some(status)) => break Some(status),
+            Ok(None) if Instant::now() < deadline => {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                break None;
+            }
+        }
+    };
+    if !status?.success() {
+        return None;
+    }
+    String::from_utf8(output?).ok()
+}
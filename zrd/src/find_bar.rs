@@ -0,0 +1,217 @@
+//! Find bar: type a query in the bottom row to highlight its matches and
+//! select them one at a time. When replacing, a second row holds the
+//! replacement.
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+    widgets::{Clear, Paragraph},
+};
+use zrd_core::{EditorAction, EditorEngine, SearchQuery};
+
+pub enum FindCommand {
+    Stay,
+    Close,
+}
+
+pub struct FindBar {
+    query: SearchQuery,
+    /// Text to replace matches with, when replacing
+    replacement: Option<String>,
+    /// Whether typing goes to the replacement rather than the query
+    editing_replacement: bool,
+    /// Result of the last replace all, until the next key
+    message: Option<String>,
+}
+
+impl FindBar {
+    /// Open with the selected text as the query, if it is on one line. When
+    /// replacing, a selection over several lines limits the replacements.
+    pub fn open(engine: &mut EditorEngine, replace: bool) -> Self {
+        let selected = engine.selected_text();
+        let mut query = SearchQuery::default();
+        if !selected.is_empty() && !selected.contains('\n') {
+            query.text = selected;
+            engine.set_search(query.clone());
+        } else if replace && selected.contains('\n') {
+            engine.set_search_in_selection(true);
+        }
+        Self {
+            query,
+            replacement: replace.then(String::new),
+            editing_replacement: false,
+            message: None,
+        }
+    }
+
+    pub fn handle_key(&mut self, engine: &mut EditorEngine, key: KeyEvent) -> FindCommand {
+        self.message = None;
+        match (key.code, key.modifiers) {
+            (KeyCode::Esc, _) => {
+                // The match found stays selected
+                engine.clear_search();
+                return FindCommand::Close;
+            }
+            (KeyCode::Enter, KeyModifiers::NONE) if self.editing_replacement => {
+                if let Some(replacement) = &self.replacement {
+                    engine.replace_match(replacement);
+                }
+                return FindCommand::Stay;
+            }
+            (KeyCode::Enter | KeyCode::Down | KeyCode::F(3), KeyModifiers::NONE) => {
+                engine.handle_action(EditorAction::FindNext);
+                return FindCommand::Stay;
+            }
+            (KeyCode::Up, KeyModifiers::NONE) | (KeyCode::F(3), KeyModifiers::SHIFT) => {
+                engine.handle_action(EditorAction::FindPrevious);
+                return FindCommand::Stay;
+            }
+            (KeyCode::Tab, KeyModifiers::NONE) if self.replacement.is_some() => {
+                self.editing_replacement = !self.editing_replacement;
+                return FindCommand::Stay;
+            }
+            (KeyCode::Char('r'), KeyModifiers::CONTROL) => {
+                self.replacement.get_or_insert_with(String::new);
+                self.editing_replacement = true;
+                return FindCommand::Stay;
+            }
+            (KeyCode::Char('a'), KeyModifiers::ALT) => {
+                if let Some(replacement) = &self.replacement {
+                    let count = engine.replace_all(replacement);
+                    self.message = Some(format!("replaced {count}"));
+                }
+                return FindCommand::Stay;
+            }
+            (KeyCode::Char('s'), KeyModifiers::ALT) => {
+                engine.set_search_in_selection(!engine.is_search_in_selection());
+                return FindCommand::Stay;
+            }
+            (KeyCode::Char('c'), KeyModifiers::ALT) => {
+                self.query.case_sensitive = !self.query.case_sensitive;
+            }
+            (KeyCode::Char('w'), KeyModifiers::ALT) => {
+                self.query.whole_word = !self.query.whole_word;
+            }
+            (KeyCode::Char('r'), KeyModifiers::ALT) => {
+                self.query.regex = !self.query.regex;
+            }
+            // Only a change to the query needs finding again
+            (KeyCode::Backspace, _) => {
+                if self.field().pop().is_none() || self.editing_replacement {
+                    return FindCommand::Stay;
+                }
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.field().push(c);
+                if self.editing_replacement {
+                    return FindCommand::Stay;
+                }
+            }
+            _ => return FindCommand::Stay,
+        }
+        engine.set_search(self.query.clone());
+        FindCommand::Stay
+    }
+
+    /// Add pasted text to the field being typed in, up to the first line break
+    pub fn paste(&mut self, engine: &mut EditorEngine, text: &str) {
+        self.message = None;
+        self.field()
+            .push_str(text.lines().next().unwrap_or_default());
+        if !self.editing_replacement {
+            engine.set_search(self.query.clone());
+        }
+    }
+
+    /// The text being typed in
+    fn field(&mut self) -> &mut String {
+        match &mut self.replacement {
+            Some(replacement) if self.editing_replacement => replacement,
+            _ => &mut self.query.text,
+        }
+    }
+
+    /// Draw the query, match count and options in `area`, one row high.
+    /// When replacing, the query goes in the row above and the replacement
+    /// in `area`.
+    pub fn render(&self, engine: &EditorEngine, frame: &mut ratatui::Frame, area: Rect) {
+        let label = Style::default().fg(Color::Yellow);
+        let muted = Style::default().fg(Color::DarkGray);
+        let cursor = Style::default().add_modifier(Modifier::REVERSED);
+        let option = |name: &'static str, enabled: bool| {
+            if enabled {
+                Span::styled(name, Style::default().fg(Color::Black).bg(Color::Yellow))
+            } else {
+                Span::styled(name, muted)
+            }
+        };
+
+        let count = engine.search_matches().len();
+        let position = match engine.current_match() {
+            _ if !self.query.is_valid() => "invalid regex".to_string(),
+            Some(index) => format!("{} of {count}", index + 1),
+            None if self.query.text.is_empty() => String::new(),
+            None if count == 0 => "no matches".to_string(),
+            None => format!("{count} matches"),
+        };
+        let mut find = vec![
+            Span::styled("Find: ", label),
+            Span::raw(self.query.text.as_str()),
+            Span::styled(
+                " ",
+                if self.editing_replacement {
+                    muted
+                } else {
+                    cursor
+                },
+            ),
+            Span::styled(format!("  {position}  "), muted),
+            option("Aa", self.query.case_sensitive),
+            Span::styled(" Alt+C  ", muted),
+            option("word", self.query.whole_word),
+            Span::styled(" Alt+W  ", muted),
+            option(".*", self.query.regex),
+            Span::styled(" Alt+R  ", muted),
+        ];
+
+        let Some(replacement) = &self.replacement else {
+            find.push(Span::styled(
+                "Enter/↓ next  ↑ previous  Ctrl+R replace  Esc close",
+                muted,
+            ));
+            frame.render_widget(Paragraph::new(Line::from(find)), area);
+            return;
+        };
+
+        find.push(option("in selection", engine.is_search_in_selection()));
+        find.push(Span::styled(" Alt+S", muted));
+        let result = match &self.message {
+            Some(message) => format!("  {message}  "),
+            None => format!("  Enter replace  Alt+A replace all ({count})  "),
+        };
+        let replace = vec![
+            Span::styled("Replace: ", label),
+            Span::raw(replacement.as_str()),
+            Span::styled(
+                " ",
+                if self.editing_replacement {
+                    cursor
+                } else {
+                    muted
+                },
+            ),
+            Span::styled(result, muted),
+            Span::styled("Tab switch  Esc close", muted),
+        ];
+
+        let find_area = Rect {
+            y: area.y.saturating_sub(1),
+            ..area
+        };
+        frame.render_widget(Clear, find_area);
+        frame.render_widget(Paragraph::new(Line::from(find)), find_area);
+        frame.render_widget(Paragraph::new(Line::from(replace)), area);
+    }
+}
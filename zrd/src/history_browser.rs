@@ -0,0 +1,159 @@
+//! Undo history browser: pick any state in the undo tree, preview it in
+//! place of the document, and restore it
+
+use crossterm::event::{KeyCode, KeyEvent};
+use ratatui::{
+    layout::Rect,
+    style::{Color, Modifier, Style},
+    text::Line,
+    widgets::{Block, Borders, Clear, Paragraph},
+};
+use std::time::{Duration, SystemTime};
+use zrd_core::undo::HistoryEntry;
+use zrd_core::{EditorAction, EditorEngine, EditorState};
+
+/// How far PageUp/PageDown jump through history
+const TIME_JUMP: Duration = Duration::from_secs(5 * 60);
+
+/// Width of the history panel, borders included
+pub const PANEL_WIDTH: u16 = 36;
+
+pub const HELP: &str = "↑↓ step  PgUp/PgDn ±5 min  Enter restore  Esc cancel";
+
+pub enum BrowserCommand {
+    Stay,
+    Cancel,
+    Restore(EditorAction),
+}
+
+pub struct HistoryBrowser {
+    /// States oldest first
+    entries: Vec<HistoryEntry>,
+    selected: usize,
+    preview: EditorState,
+}
+
+impl HistoryBrowser {
+    /// Open on the current state
+    pub fn open(engine: &EditorEngine) -> Self {
+        let entries = engine.undo_history().entries();
+        let selected = entries
+            .iter()
+            .position(|entry| entry.is_current)
+            .unwrap_or(0);
+        Self {
+            entries,
+            selected,
+            preview: engine.state().clone(),
+        }
+    }
+
+    /// The document as it was at the selected state
+    pub fn preview(&self) -> &EditorState {
+        &self.preview
+    }
+
+    pub fn handle_key(&mut self, engine: &EditorEngine, key: KeyEvent) -> BrowserCommand {
+        let selected = match key.code {
+            KeyCode::Esc => return BrowserCommand::Cancel,
+            KeyCode::Enter => {
+                let id = self.entries[self.selected].id;
+                return BrowserCommand::Restore(EditorAction::GoToUndoState(id));
+            }
+            KeyCode::Up => self.selected.saturating_sub(1),
+            KeyCode::Down => (self.selected + 1).min(self.entries.len() - 1),
+            KeyCode::PageUp => {
+                let time = self.entries[self.selected].time;
+                self.newest_at(
+                    time.checked_sub(TIME_JUMP)
+                        .unwrap_or(SystemTime::UNIX_EPOCH),
+                )
+            }
+            KeyCode::PageDown => {
+                let time = self.entries[self.selected].time;
+                self.newest_at(time + TIME_JUMP).max(self.selected)
+            }
+            KeyCode::Home => 0,
+            KeyCode::End => self.entries.len() - 1,
+            _ => return BrowserCommand::Stay,
+        };
+
+        if selected != self.selected {
+            self.selected = selected;
+            self.preview = engine
+                .preview_undo_state(self.entries[selected].id)
+                .unwrap_or_else(|| engine.state().clone());
+        }
+        BrowserCommand::Stay
+    }
+
+    /// Index of the newest state last changed no later than `time`
+    fn newest_at(&self, time: SystemTime) -> usize {
+        self.entries
+            .iter()
+            .rposition(|entry| entry.time <= time)
+            .unwrap_or(0)
+    }
+
+    /// Draw the list of states at the right edge of `area`
+    pub fn render(&self, frame: &mut ratatui::Frame, area: Rect) {
+        let width = PANEL_WIDTH.min(area.width);
+        let panel = Rect {
+            x: area.x + area.width - width,
+            width,
+            ..area
+        };
+        let rows = panel.height.saturating_sub(2) as usize;
+        // Keep the selection in view, preferring to show the newest states
+        let first = self.entries.len().saturating_sub(rows).min(self.selected);
+
+        let now = SystemTime::now();
+        let lines: Vec<Line> = self
+            .entries
+            .iter()
+            .enumerate()
+            .skip(first)
+            .take(rows)
+            .map(|(index, entry)| {
+                let marker = if entry.is_current { '●' } else { ' ' };
+                let ago = format_ago(now.duration_since(entry.time).unwrap_or_default());
+                let mut text = match entry.parent {
+                    None => format!("{marker} #{:<4} {ago:>4}  original", entry.id),
+                    Some(_) => format!(
+                        "{marker} #{:<4} {ago:>4}  +{} -{}",
+                        entry.id, entry.inserted, entry.deleted
+                    ),
+                };
+                // Mark where a branch starts
+                let previous = index.checked_sub(1).map(|index| self.entries[index].id);
+                if let Some(parent) = entry.parent.filter(|&parent| Some(parent) != previous) {
+                    text.push_str(&format!("  ⑂#{parent}"));
+                }
+                let style = if index == self.selected {
+                    Style::default().add_modifier(Modifier::REVERSED)
+                } else {
+                    Style::default()
+                };
+                Line::styled(text, style)
+            })
+            .collect();
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(" History ")
+            .border_style(Style::default().fg(Color::DarkGray));
+        frame.render_widget(Clear, panel);
+        frame.render_widget(Paragraph::new(lines).block(block), panel);
+    }
+}
+
+/// Compact age such as "45s", "3m", "2h" or "4d"
+fn format_ago(age: Duration) -> String {
+    let seconds = age.as_secs();
+    match seconds {
+        0..=59 => format!("{seconds}s"),
+        60..=3599 => format!("{}m", seconds / 60),
+        3600..=86399 => format!("{}h", seconds / 3600),
+        _ => format!("{}d", seconds / 86400),
+    }
+}
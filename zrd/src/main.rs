@@ -1,12 +1,20 @@
+mod clipboard;
+mod find_bar;
+mod history_browser;
+
 use anyhow::Result;
+use clipboard::TerminalClipboard;
 use crossterm::{
     event::{
-        self, poll, DisableMouseCapture, EnableMouseCapture, Event, KeyCode, KeyEvent,
-        KeyModifiers, MouseButton, MouseEvent, MouseEventKind,
+        self, poll, DisableBracketedPaste, DisableMouseCapture, EnableBracketedPaste,
+        EnableMouseCapture, Event, KeyCode, KeyEvent, KeyModifiers, MouseButton, MouseEvent,
+        MouseEventKind,
     },
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
+use find_bar::{FindBar, FindCommand};
+use history_browser::{BrowserCommand, HistoryBrowser};
 use ratatui::{
     backend::CrosstermBackend,
     layout::Rect,
@@ -15,29 +23,81 @@ use ratatui::{
     widgets::Paragraph,
     Terminal,
 };
-use std::time::Duration;
-use zrd_core::{EditorAction, EditorEngine};
+#[cfg(unix)]
+use signal_hook::consts::SIGHUP;
+use signal_hook::consts::TERM_SIGNALS;
+use std::borrow::Cow;
+use std::ops::Range;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use unicode_segmentation::UnicodeSegmentation;
+use zrd_core::binary::BYTES_PER_ROW;
+use zrd_core::display_width;
+use zrd_core::{
+    Autosave, BufferPosition, EditorAction, EditorEngine, EditorState, FileEncoding, HexDocument,
+};
+
+/// How often to check the file for changes made elsewhere
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+// Hex view columns: "00000010  4d 5a 90 ...  MZ."
+const HEX_BYTES_START: usize = 10;
+const HEX_ASCII_START: usize = HEX_BYTES_START + BYTES_PER_ROW * 3 + 1;
 
 struct TuiEditor {
     engine: EditorEngine,
     file_path: std::path::PathBuf,
     last_modified: Option<std::time::SystemTime>,
-    scroll_offset: u16,
+    scroll_offset: usize,
     terminal_size: Rect,
+    status_message: Option<String>,
+    history_browser: Option<HistoryBrowser>,
+    find_bar: Option<FindBar>,
+    autosave: Autosave,
+    /// Set by SIGTERM and the like, to save and exit
+    quit_requested: Arc<AtomicBool>,
 }
 
 impl TuiEditor {
     fn new(file_path: std::path::PathBuf) -> Self {
         let mut engine = EditorEngine::new();
 
+        // Legacy encoding for files that are neither UTF-8 nor BOM-marked
+        if let Some(encoding) = std::env::var("ZRD_FALLBACK_ENCODING")
+            .ok()
+            .and_then(|label| FileEncoding::for_label(&label))
+        {
+            engine.set_fallback_encoding(encoding);
+        }
+
+        // Memory cap for undo history, in megabytes
+        if let Some(megabytes) = std::env::var("ZRD_UNDO_MEMORY_MB")
+            .ok()
+            .and_then(|value| value.parse::<usize>().ok())
+        {
+            engine.set_undo_memory_limit(megabytes * 1024 * 1024);
+        }
+
+        // Keep undo history, bookmarks and the kill ring across sessions
+        engine.set_undo_history_dir(Some(EditorEngine::config_dir().join("undo")));
+        engine.set_bookmark_dir(Some(EditorEngine::config_dir().join("bookmarks")));
+        engine.set_kill_ring_file(Some(EditorEngine::config_dir().join("kill-ring")));
+
+        // Copy with OSC 52 or a clipboard command, keeping a register as well
+        engine.set_clipboard(Box::new(TerminalClipboard::from_env()));
+
         // Ensure parent directory exists
         if let Some(parent) = file_path.parent() {
             let _ = std::fs::create_dir_all(parent);
         }
 
         // Load existing file if it exists
+        let mut status_message = None;
         let last_modified = if file_path.exists() {
-            let _ = engine.load_from_file(&file_path);
+            if let Err(err) = engine.load_from_file(&file_path) {
+                status_message = Some(Self::load_error_message(&err));
+            }
             std::fs::metadata(&file_path)
                 .ok()
                 .and_then(|m| m.modified().ok())
@@ -45,18 +105,55 @@ impl TuiEditor {
             None
         };
 
+        // Saves follow the edits these report
+        engine.record_changes(true);
+
         Self {
             engine,
+            autosave: Autosave::new(&file_path),
+            quit_requested: Arc::new(AtomicBool::new(false)),
             file_path,
             last_modified,
             scroll_offset: 0,
             terminal_size: Rect::default(),
+            status_message,
+            history_browser: None,
+            find_bar: None,
+        }
+    }
+
+    fn load_error_message(err: &std::io::Error) -> String {
+        format!("Could not open file: {} (autosave disabled)", err)
+    }
+
+    /// The document being shown: the engine's, or a state being previewed
+    /// in the history browser
+    fn shown_state(&self) -> &EditorState {
+        match &self.history_browser {
+            Some(browser) => browser.preview(),
+            None => self.engine.state(),
+        }
+    }
+
+    /// Display row of the cursor; binary files show one row per 16 bytes
+    fn cursor_row(&self) -> usize {
+        match self.engine.hex_document() {
+            Some(hex) => hex.cursor() / BYTES_PER_ROW,
+            None => self.shown_state().cursor.row,
+        }
+    }
+
+    fn row_count(&self) -> usize {
+        match self.engine.hex_document() {
+            Some(hex) => hex.row_count(),
+            None => self.engine.state().line_count(),
         }
     }
 
     fn ensure_cursor_visible(&mut self, visible_height: u16) {
-        let cursor_row = self.engine.state().cursor.row as u16;
-        let padding = 2u16;
+        let cursor_row = self.cursor_row();
+        let visible_height = visible_height as usize;
+        let padding = 2usize;
 
         // Scroll up if cursor is above visible area
         if cursor_row < self.scroll_offset + padding {
@@ -70,7 +167,7 @@ impl TuiEditor {
         }
     }
 
-    /// Convert screen coordinates to document position
+    /// Convert screen coordinates to a document row and display cell column
     /// Returns None if click is outside the text area
     fn screen_to_document(&self, screen_col: u16, screen_row: u16) -> Option<(usize, usize)> {
         let area = self.terminal_size;
@@ -90,27 +187,54 @@ impl TuiEditor {
         }
 
         // Convert to document coordinates
-        let doc_col = (screen_col - text_x_start) as usize;
-        let doc_row = (screen_row - text_y_start) as usize + self.scroll_offset as usize;
+        let cell = (screen_col - text_x_start) as usize;
+        let doc_row = (screen_row - text_y_start) as usize + self.scroll_offset;
 
-        Some((doc_row, doc_col))
+        Some((doc_row, cell))
     }
 
-    /// Clamp document position to valid bounds
+    /// Clamp document position to valid bounds, mapping the screen cell to
+    /// the byte column of the character drawn there.
+    /// In the hex view the screen column is mapped to a byte within the row.
     fn clamp_to_document(&self, row: usize, column: usize) -> (usize, usize) {
+        if let Some(hex) = self.engine.hex_document() {
+            let row = row.min(hex.row_count() - 1);
+            let column = if column >= HEX_ASCII_START {
+                column - HEX_ASCII_START
+            } else {
+                column.saturating_sub(HEX_BYTES_START) / 3
+            };
+            return (row, column.min(BYTES_PER_ROW - 1));
+        }
         let state = self.engine.state();
-        let row = row.min(state.lines.len().saturating_sub(1));
-        let column = column.min(state.lines[row].len());
-        (row, column)
+        let row = row.min(state.line_count().saturating_sub(1));
+        let line = state.line(row).unwrap_or_default();
+        (row, display_width::cell_to_column(&line, column))
+    }
+
+    /// Whether the text was edited since the last call
+    fn take_edited(&mut self) -> bool {
+        let changes = self.engine.take_changes();
+        changes.iter().any(|change| !change.edits.is_empty())
     }
 
     fn check_and_reload(&mut self) -> bool {
+        // Unsaved edits win over changes made elsewhere
+        if self.autosave.is_pending() {
+            return false;
+        }
         if let Ok(metadata) = std::fs::metadata(&self.file_path) {
             if let Ok(modified) = metadata.modified() {
-                if self.last_modified.map_or(true, |last| modified > last) {
-                    if self.engine.load_from_file(&self.file_path).is_ok() {
-                        self.last_modified = Some(modified);
-                        return true;
+                if self.last_modified.is_none_or(|last| modified > last) {
+                    match self.engine.load_from_file(&self.file_path) {
+                        Ok(()) => {
+                            // The text on disk needs no saving
+                            self.engine.take_changes();
+                            self.last_modified = Some(modified);
+                            self.status_message = None;
+                            return true;
+                        }
+                        Err(err) => self.status_message = Some(Self::load_error_message(&err)),
                     }
                 }
             }
@@ -118,53 +242,88 @@ impl TuiEditor {
         false
     }
 
-    fn render_cursor_line<'a>(
-        &self,
+    /// Style a line given its selected byte ranges, search matches and
+    /// cursor columns, with the styles for each in that order. The grapheme
+    /// under each cursor is reversed; a cursor past the end of the line is
+    /// drawn as a reversed space.
+    fn styled_line<'a>(
         line: &'a str,
-        cursor_col: usize,
-        spans: &mut Vec<Span<'a>>,
-        cursor_style: Style,
-    ) {
-        if cursor_col == 0 {
-            // Cursor at start
-            if line.is_empty() {
-                spans.push(Span::styled(" ", cursor_style));
+        selected: &[Range<usize>],
+        found: &[Range<usize>],
+        cursors: &[usize],
+        (selection_style, match_style, cursor_style): (Style, Style, Style),
+    ) -> Line<'a> {
+        let style_at = |column: usize| {
+            if cursors.contains(&column) {
+                cursor_style
+            } else if selected.iter().any(|range| range.contains(&column)) {
+                selection_style
+            } else if found.iter().any(|range| range.contains(&column)) {
+                match_style
             } else {
-                let cursor_char = line.chars().next().unwrap();
-                spans.push(Span::styled(cursor_char.to_string(), cursor_style));
-                spans.push(Span::raw(&line[cursor_char.len_utf8()..]));
+                Style::default()
+            }
+        };
+
+        // Consecutive graphemes with the same style share a span
+        let mut spans = Vec::new();
+        let mut run: Option<(usize, Style)> = None;
+        for (column, _) in line.grapheme_indices(true) {
+            let style = style_at(column);
+            match run {
+                Some((_, run_style)) if run_style == style => {}
+                Some((run_start, run_style)) => {
+                    spans.push(Span::styled(
+                        display_text(line, run_start, column),
+                        run_style,
+                    ));
+                    run = Some((column, style));
+                }
+                None => run = Some((column, style)),
             }
-        } else if cursor_col >= line.len() {
-            // Cursor at end
-            spans.push(Span::raw(line));
+        }
+        if let Some((run_start, run_style)) = run {
+            spans.push(Span::styled(
+                display_text(line, run_start, line.len()),
+                run_style,
+            ));
+        }
+
+        if cursors.iter().any(|&column| column >= line.len()) {
             spans.push(Span::styled(" ", cursor_style));
-        } else {
-            // Cursor in middle
-            let (before, rest) = line.split_at(cursor_col);
-            let cursor_char = rest.chars().next().unwrap();
-            let after = &rest[cursor_char.len_utf8()..];
-
-            spans.push(Span::raw(before));
-            spans.push(Span::styled(cursor_char.to_string(), cursor_style));
-            spans.push(Span::raw(after));
         }
+        Line::from(spans)
     }
 
     fn run(&mut self) -> Result<()> {
+        #[cfg(unix)]
+        signal_hook::flag::register(SIGHUP, Arc::clone(&self.quit_requested))?;
+        for signal in TERM_SIGNALS {
+            signal_hook::flag::register(*signal, Arc::clone(&self.quit_requested))?;
+        }
+
         enable_raw_mode()?;
         let mut stdout = std::io::stdout();
-        execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
+        execute!(
+            stdout,
+            EnterAlternateScreen,
+            EnableMouseCapture,
+            EnableBracketedPaste
+        )?;
 
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
 
         let result = self.run_loop(&mut terminal);
+        // Whatever ended the loop, nothing typed is lost
+        let _ = self.autosave.flush(&self.engine, true);
 
         disable_raw_mode()?;
         execute!(
             terminal.backend_mut(),
             LeaveAlternateScreen,
-            DisableMouseCapture
+            DisableMouseCapture,
+            DisableBracketedPaste
         )?;
 
         result
@@ -175,6 +334,10 @@ impl TuiEditor {
         terminal: &mut Terminal<CrosstermBackend<std::io::Stdout>>,
     ) -> Result<()> {
         loop {
+            if self.quit_requested.load(Ordering::Relaxed) {
+                break;
+            }
+
             // Check for file changes before rendering
             if self.check_and_reload() {
                 // File was reloaded
@@ -189,29 +352,74 @@ impl TuiEditor {
 
             terminal.draw(|frame| self.render(frame))?;
 
-            // Poll for events with timeout to check file changes periodically
-            if poll(Duration::from_millis(100))? {
+            // Poll for events with timeout to check file changes periodically,
+            // waking up sooner for a save that is due
+            let timeout = self.autosave.due_at().map_or(POLL_INTERVAL, |due| {
+                due.saturating_duration_since(Instant::now())
+                    .min(POLL_INTERVAL)
+            });
+            if poll(timeout)? {
                 match event::read()? {
                     Event::Key(key) => {
-                        if let Some(action) = self.translate_key_event(key) {
+                        let action = if let Some(browser) = self.history_browser.as_mut() {
+                            match browser.handle_key(&self.engine, key) {
+                                BrowserCommand::Stay => None,
+                                BrowserCommand::Cancel => {
+                                    self.history_browser = None;
+                                    None
+                                }
+                                BrowserCommand::Restore(action) => {
+                                    self.history_browser = None;
+                                    Some(action)
+                                }
+                            }
+                        } else if let Some(find_bar) = self.find_bar.as_mut() {
+                            if let FindCommand::Close = find_bar.handle_key(&mut self.engine, key) {
+                                self.find_bar = None;
+                            }
+                            None
+                        } else if matches!(key.code, KeyCode::Char('f' | 'r'))
+                            && key.modifiers == KeyModifiers::CONTROL
+                            && !self.engine.is_binary()
+                        {
+                            // Ctrl+F finds, Ctrl+R finds and replaces
+                            let replace = key.code == KeyCode::Char('r');
+                            self.find_bar = Some(FindBar::open(&mut self.engine, replace));
+                            None
+                        } else if key.code == KeyCode::Char('u')
+                            && key.modifiers == KeyModifiers::ALT
+                            && !self.engine.is_binary()
+                        {
+                            // Alt+U browses undo history; Ctrl+U is taken by the
+                            // Cmd+Backspace fallback
+                            self.history_browser = Some(HistoryBrowser::open(&self.engine));
+                            None
+                        } else {
+                            self.translate_key_event(key)
+                        };
+                        if let Some(action) = action {
                             if matches!(action, EditorAction::Quit) {
-                                // Save before quitting
-                                let _ = self.engine.save_to_file(&self.file_path);
+                                // Pending edits are saved once the loop ends
                                 break;
                             }
+                            let toggles_bookmark = matches!(action, EditorAction::ToggleBookmark);
                             self.engine.handle_action(action);
-
-                            // Auto-save after each action
-                            if self.engine.save_to_file(&self.file_path).is_ok() {
-                                // Update last modified time after we save
-                                if let Ok(metadata) = std::fs::metadata(&self.file_path) {
-                                    if let Ok(modified) = metadata.modified() {
-                                        self.last_modified = Some(modified);
-                                    }
-                                }
+                            if toggles_bookmark {
+                                // The text is unchanged, so only the bookmarks are saved
+                                let _ = self.engine.save_bookmarks(&self.file_path);
                             }
                         }
                     }
+                    Event::Paste(text) => {
+                        // Terminals send line breaks in pastes as carriage returns
+                        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+                        if let Some(find_bar) = self.find_bar.as_mut() {
+                            find_bar.paste(&mut self.engine, &text);
+                        } else if self.history_browser.is_none() {
+                            // Inserted as is, without list continuation
+                            self.engine.handle_action(EditorAction::Paste(text));
+                        }
+                    }
                     Event::Mouse(mouse) => {
                         match mouse.kind {
                             MouseEventKind::ScrollUp => {
@@ -228,15 +436,6 @@ impl TuiEditor {
                                     let visible_height =
                                         self.terminal_size.height.saturating_sub(2);
                                     self.ensure_cursor_visible(visible_height);
-
-                                    // Auto-save after mouse actions
-                                    if self.engine.save_to_file(&self.file_path).is_ok() {
-                                        if let Ok(metadata) = std::fs::metadata(&self.file_path) {
-                                            if let Ok(modified) = metadata.modified() {
-                                                self.last_modified = Some(modified);
-                                            }
-                                        }
-                                    }
                                 }
                             }
                         }
@@ -244,6 +443,20 @@ impl TuiEditor {
                     _ => {}
                 }
             }
+
+            // Edits are saved once they pause. Only edits schedule a save, so
+            // that changes made elsewhere are reloaded rather than overwritten.
+            if self.take_edited() {
+                self.autosave.schedule(&self.engine);
+            }
+            if let Ok(true) = self.autosave.save_if_due(&self.engine) {
+                // Update last modified time after we save
+                if let Ok(metadata) = std::fs::metadata(&self.file_path) {
+                    if let Ok(modified) = metadata.modified() {
+                        self.last_modified = Some(modified);
+                    }
+                }
+            }
         }
         Ok(())
     }
@@ -265,6 +478,12 @@ impl TuiEditor {
             }
             (KeyCode::Char('z'), KeyModifiers::CONTROL) => Some(EditorAction::Undo),
 
+            // Clipboard
+            (KeyCode::Char('c'), KeyModifiers::CONTROL) => Some(EditorAction::Copy),
+            (KeyCode::Char('x'), KeyModifiers::CONTROL) => Some(EditorAction::Cut),
+            (KeyCode::Char('v'), KeyModifiers::CONTROL) => Some(EditorAction::PasteFromClipboard),
+            (KeyCode::Char('y'), KeyModifiers::ALT) => Some(EditorAction::PasteFromHistory),
+
             // Line operations
             (KeyCode::Char('k'), mods)
                 if mods.contains(KeyModifiers::CONTROL | KeyModifiers::SHIFT) =>
@@ -287,6 +506,15 @@ impl TuiEditor {
                 Some(EditorAction::DeleteToBeginningOfLine)
             }
 
+            // Select the next occurrence of the selection or word
+            (KeyCode::Char('d'), KeyModifiers::CONTROL) => Some(EditorAction::SelectNextOccurrence),
+
+            // Bookmarks; Alt+M for terminals that don't send Ctrl+F2
+            (KeyCode::F(2), KeyModifiers::CONTROL) => Some(EditorAction::ToggleBookmark),
+            (KeyCode::Char('m'), KeyModifiers::ALT) => Some(EditorAction::ToggleBookmark),
+            (KeyCode::F(2), KeyModifiers::NONE) => Some(EditorAction::NextBookmark),
+            (KeyCode::F(2), KeyModifiers::SHIFT) => Some(EditorAction::PreviousBookmark),
+
             // Font size (will be ignored in TUI but kept for consistency)
             (KeyCode::Char('='), KeyModifiers::CONTROL) => Some(EditorAction::IncreaseFontSize),
             (KeyCode::Char('-'), KeyModifiers::CONTROL) => Some(EditorAction::DecreaseFontSize),
@@ -311,16 +539,40 @@ impl TuiEditor {
                 Some(EditorAction::MoveWordRight)
             }
 
-            // Shift+Alt for word selection
+            // Shift+Alt for word selection, or to resize a block selection
             (KeyCode::Left, mods)
                 if mods.contains(KeyModifiers::SHIFT) && mods.contains(KeyModifiers::ALT) =>
             {
-                Some(EditorAction::SelectWordLeft)
+                if self.engine.block_selection().is_some() {
+                    Some(EditorAction::BlockSelectLeft)
+                } else {
+                    Some(EditorAction::SelectWordLeft)
+                }
             }
             (KeyCode::Right, mods)
                 if mods.contains(KeyModifiers::SHIFT) && mods.contains(KeyModifiers::ALT) =>
             {
-                Some(EditorAction::SelectWordRight)
+                if self.engine.block_selection().is_some() {
+                    Some(EditorAction::BlockSelectRight)
+                } else {
+                    Some(EditorAction::SelectWordRight)
+                }
+            }
+
+            // Shift+Alt+Up/Down to start or grow a block selection
+            (KeyCode::Up, mods) if mods == KeyModifiers::SHIFT | KeyModifiers::ALT => {
+                Some(EditorAction::BlockSelectUp)
+            }
+            (KeyCode::Down, mods) if mods == KeyModifiers::SHIFT | KeyModifiers::ALT => {
+                Some(EditorAction::BlockSelectDown)
+            }
+
+            // Ctrl+Alt+Up/Down to add a cursor on the line above/below
+            (KeyCode::Up, mods) if mods == KeyModifiers::CONTROL | KeyModifiers::ALT => {
+                Some(EditorAction::AddCursorAbove)
+            }
+            (KeyCode::Down, mods) if mods == KeyModifiers::CONTROL | KeyModifiers::ALT => {
+                Some(EditorAction::AddCursorBelow)
             }
 
             // Alt+Up/Down for moving lines
@@ -377,6 +629,14 @@ impl TuiEditor {
                     None
                 }
             }
+            // Alt+drag selects a block, which may extend past the end of lines
+            MouseEventKind::Drag(MouseButton::Left)
+                if event.modifiers.contains(KeyModifiers::ALT)
+                    && self.engine.hex_document().is_none() =>
+            {
+                self.screen_to_document(event.column, event.row)
+                    .map(|(row, cell)| EditorAction::ExtendBlockSelection { row, cell })
+            }
             MouseEventKind::Drag(MouseButton::Left) => {
                 if let Some((row, col)) = self.screen_to_document(event.column, event.row) {
                     let (row, col) = self.clamp_to_document(row, col);
@@ -390,142 +650,256 @@ impl TuiEditor {
     }
 
     fn handle_scroll(&mut self, direction: i16) {
-        const SCROLL_LINES: u16 = 3;
+        const SCROLL_LINES: usize = 3;
         if direction < 0 {
             // Scroll up
             self.scroll_offset = self.scroll_offset.saturating_sub(SCROLL_LINES);
         } else {
             // Scroll down
-            let max_scroll = self.engine.state().lines.len().saturating_sub(1) as u16;
+            let max_scroll = self.row_count().saturating_sub(1);
             self.scroll_offset = (self.scroll_offset + SCROLL_LINES).min(max_scroll);
         }
     }
 
     fn render(&self, frame: &mut ratatui::Frame) {
-        let state = self.engine.state();
+        let state = self.shown_state();
 
-        // Selection highlighting style
-        let selection_style = Style::default().bg(Color::DarkGray);
+        // Selection highlighting style; while finding, the selection is the
+        // current match
+        let selection_style = if self.find_bar.is_some() {
+            Style::default().add_modifier(Modifier::REVERSED | Modifier::BOLD)
+        } else {
+            Style::default().bg(Color::DarkGray)
+        };
+        let match_style = Style::default().fg(Color::Black).bg(Color::Yellow);
         let cursor_style = Style::default().add_modifier(Modifier::REVERSED);
 
+        // Create a rect with padding on all sides
+        let area = frame.size();
+        let padded_area = Rect {
+            x: area.x + 2,
+            y: area.y + 1,
+            width: area.width.saturating_sub(4),
+            height: area.height.saturating_sub(2),
+        };
+
+        if let Some(hex) = self.engine.hex_document() {
+            self.render_hex(frame, hex, padded_area, cursor_style);
+            return;
+        }
+
+        // Only the visible rows are fetched from the buffer
+        let first_row = self.scroll_offset.min(state.line_count());
+        let last_row = (first_row + padded_area.height as usize).min(state.line_count());
+        let visible_lines: Vec<_> = (first_row..last_row)
+            .map(|row| state.line(row).unwrap_or_default())
+            .collect();
+
+        let selections = state.selections();
+        // Matches that end before the first visible row are skipped
+        let matches = self.engine.search_matches();
+        let matches = &matches[matches.partition_point(|found| found.1.row < first_row)..];
+        let block = self
+            .engine
+            .block_selection()
+            .filter(|_| self.history_browser.is_none());
+
         // Build styled lines with cursor and selection highlighting
         let mut display_lines = Vec::new();
 
-        for (row_idx, line) in state.lines.iter().enumerate() {
-            let mut spans = Vec::new();
+        for (offset, line) in visible_lines.iter().enumerate() {
+            let row_idx = first_row + offset;
+            let line: &str = line;
+            let mut selected = Vec::new();
+            let mut cursors = Vec::new();
 
-            if let Some(anchor) = state.selection_anchor {
-                // Calculate selection range
-                let (sel_start_row, sel_start_col, sel_end_row, sel_end_col) = if anchor.row
-                    < state.cursor.row
-                    || (anchor.row == state.cursor.row && anchor.column < state.cursor.column)
+            for selection in &selections {
+                let (start, end) = selection.range();
+                if start != end {
+                    selected.extend(columns_on_row(start, end, row_idx, line.len()));
+                }
+                // Cursors are drawn when nothing is selected, or past the end of the line
+                if selection.cursor.row == row_idx
+                    && (start == end || selection.cursor.column >= line.len())
                 {
-                    (
-                        anchor.row,
-                        anchor.column,
-                        state.cursor.row,
-                        state.cursor.column,
-                    )
-                } else {
-                    (
-                        state.cursor.row,
-                        state.cursor.column,
-                        anchor.row,
-                        anchor.column,
-                    )
-                };
+                    cursors.push(selection.cursor.column);
+                }
+            }
 
-                if row_idx == state.cursor.row && row_idx >= sel_start_row && row_idx <= sel_end_row
-                {
-                    // Line with cursor and possibly selection
-                    let (sel_from, sel_to) = if row_idx == sel_start_row && row_idx == sel_end_row {
-                        (sel_start_col, sel_end_col)
-                    } else if row_idx == sel_start_row {
-                        (sel_start_col, line.len())
-                    } else if row_idx == sel_end_row {
-                        (0, sel_end_col)
-                    } else {
-                        (0, line.len())
-                    };
+            let found: Vec<_> = matches
+                .iter()
+                .take_while(|found| found.0.row <= row_idx)
+                .filter_map(|&(start, end)| columns_on_row(start, end, row_idx, line.len()))
+                .collect();
+
+            let mut styled = Self::styled_line(
+                line,
+                &selected,
+                &found,
+                &cursors,
+                (selection_style, match_style, cursor_style),
+            );
+            // A block selection extends past the end of short lines
+            if let Some(block) = block.filter(|block| block.rows().contains(&row_idx)) {
+                let cells = block.cells();
+                let mut width = display_width::line_width(line);
+                if cursors.iter().any(|&column| column >= line.len()) {
+                    width += 1;
+                }
+                if width < cells.end {
+                    let start = cells.start.max(width);
+                    styled.spans.push(Span::raw(" ".repeat(start - width)));
+                    styled
+                        .spans
+                        .push(Span::styled(" ".repeat(cells.end - start), selection_style));
+                }
+            }
+            display_lines.push(styled);
+        }
 
-                    // Before selection
-                    if sel_from > 0 {
-                        spans.push(Span::raw(&line[..sel_from]));
-                    }
+        let paragraph = Paragraph::new(display_lines).style(Style::default().fg(Color::White));
 
-                    // Selected text
-                    if sel_to > sel_from {
-                        let sel_text = &line[sel_from..sel_to.min(line.len())];
-                        spans.push(Span::styled(sel_text, selection_style));
-                    }
+        frame.render_widget(paragraph, padded_area);
 
-                    // After selection
-                    if sel_to < line.len() {
-                        spans.push(Span::raw(&line[sel_to..]));
-                    }
+        // Bookmarked lines are marked in the left padding
+        let bookmarks = self.engine.bookmarks();
+        let gutter: Vec<Line> = (first_row..last_row)
+            .map(|row| {
+                if bookmarks.binary_search(&row).is_ok() {
+                    Line::styled("▸", Style::default().fg(Color::Cyan))
+                } else {
+                    Line::raw("")
+                }
+            })
+            .collect();
+        let gutter_area = Rect {
+            x: area.x,
+            width: 1.min(area.width),
+            ..padded_area
+        };
+        frame.render_widget(Paragraph::new(gutter), gutter_area);
+
+        if let Some(browser) = &self.history_browser {
+            browser.render(frame, padded_area);
+            self.render_status(frame, history_browser::HELP);
+        } else if let Some(find_bar) = &self.find_bar {
+            find_bar.render(&self.engine, frame, status_area(area));
+        } else if let Some(message) = &self.status_message {
+            self.render_status(frame, message);
+        }
+    }
 
-                    // Cursor
-                    let cursor_col = state.cursor.column;
-                    if cursor_col >= line.len() {
-                        spans.push(Span::styled(" ", cursor_style));
-                    }
-                } else if row_idx >= sel_start_row && row_idx <= sel_end_row {
-                    // Line within selection range but not cursor line
-                    let (sel_from, sel_to) = if row_idx == sel_start_row {
-                        (sel_start_col, line.len())
-                    } else if row_idx == sel_end_row {
-                        (0, sel_end_col)
-                    } else {
-                        (0, line.len())
-                    };
+    /// Status message in the bottom padding row
+    fn render_status(&self, frame: &mut ratatui::Frame, message: &str) {
+        let status = Paragraph::new(message).style(Style::default().fg(Color::Yellow));
+        frame.render_widget(status, status_area(frame.size()));
+    }
 
-                    if sel_from > 0 {
-                        spans.push(Span::raw(&line[..sel_from]));
-                    }
-                    if sel_to > sel_from {
-                        spans.push(Span::styled(
-                            &line[sel_from..sel_to.min(line.len())],
-                            selection_style,
-                        ));
-                    }
-                    if sel_to < line.len() {
-                        spans.push(Span::raw(&line[sel_to..]));
+    /// Render a binary file as offset / hex bytes / ASCII columns
+    fn render_hex(
+        &self,
+        frame: &mut ratatui::Frame,
+        hex: &HexDocument,
+        area: Rect,
+        cursor_style: Style,
+    ) {
+        let offset_style = Style::default().fg(Color::DarkGray);
+        let cursor = hex.cursor();
+
+        let first_row = self.scroll_offset.min(hex.row_count());
+        let last_row = (first_row + area.height as usize).min(hex.row_count());
+
+        let mut display_lines = Vec::new();
+        for row in first_row..last_row {
+            let row_start = row * BYTES_PER_ROW;
+            let mut spans = vec![Span::styled(format!("{:08x}  ", row_start), offset_style)];
+
+            let bytes = hex.row(row);
+            for column in 0..BYTES_PER_ROW {
+                let Some(&byte) = bytes.get(column) else {
+                    spans.push(Span::raw("   "));
+                    continue;
+                };
+                let digits = format!("{:02x}", byte);
+                if row_start + column == cursor {
+                    // While typing, only the nibble being edited is highlighted
+                    if hex.is_editing_low_nibble() {
+                        spans.push(Span::raw(digits[..1].to_string()));
+                        spans.push(Span::styled(digits[1..].to_string(), cursor_style));
+                    } else {
+                        spans.push(Span::styled(digits, cursor_style));
                     }
-                } else if row_idx == state.cursor.row {
-                    // Cursor line without selection
-                    self.render_cursor_line(line, state.cursor.column, &mut spans, cursor_style);
                 } else {
-                    // Regular line
-                    spans.push(Span::raw(line.as_str()));
+                    spans.push(Span::raw(digits));
+                }
+                spans.push(Span::raw(" "));
+            }
+
+            spans.push(Span::raw(" "));
+            for (column, &byte) in bytes.iter().enumerate() {
+                let c = if byte.is_ascii_graphic() || byte == b' ' {
+                    byte as char
+                } else {
+                    '.'
+                };
+                if row_start + column == cursor {
+                    spans.push(Span::styled(c.to_string(), cursor_style));
+                } else {
+                    spans.push(Span::raw(c.to_string()));
                 }
-            } else if row_idx == state.cursor.row {
-                // No selection, just cursor
-                self.render_cursor_line(line, state.cursor.column, &mut spans, cursor_style);
-            } else {
-                // Regular line
-                spans.push(Span::raw(line.as_str()));
             }
 
             display_lines.push(Line::from(spans));
         }
 
-        let paragraph = Paragraph::new(display_lines)
-            .style(Style::default().fg(Color::White))
-            .scroll((self.scroll_offset, 0));
+        let paragraph = Paragraph::new(display_lines).style(Style::default().fg(Color::White));
+        frame.render_widget(paragraph, area);
 
-        // Create a rect with padding on all sides
-        let area = frame.size();
-        let padded_area = Rect {
-            x: area.x + 2,
-            y: area.y + 1,
-            width: area.width.saturating_sub(4),
-            height: area.height.saturating_sub(2),
+        let message = match &self.status_message {
+            Some(message) => message.clone(),
+            None => format!(
+                "Binary file, {} bytes (hex edit only) | offset {:08x}",
+                hex.bytes().len(),
+                cursor
+            ),
         };
+        self.render_status(frame, &message);
+    }
+}
 
-        frame.render_widget(paragraph, padded_area);
+/// The bottom padding row of the screen
+fn status_area(area: Rect) -> Rect {
+    Rect {
+        x: area.x + 2,
+        y: area.y + area.height.saturating_sub(1),
+        width: area.width.saturating_sub(4),
+        height: 1.min(area.height),
     }
 }
 
+/// Columns of `row` covered by the range from `start` to `end`
+fn columns_on_row(
+    start: BufferPosition,
+    end: BufferPosition,
+    row: usize,
+    line_len: usize,
+) -> Option<Range<usize>> {
+    if row < start.row || end.row < row {
+        return None;
+    }
+    let from = if start.row == row { start.column } else { 0 };
+    let to = if end.row == row { end.column } else { line_len };
+    Some(from..to)
+}
+
+/// Text of `line[from..to]` as drawn on screen, with tabs expanded
+/// relative to where the slice starts on the line
+fn display_text(line: &str, from: usize, to: usize) -> Cow<'_, str> {
+    let to = to.min(line.len());
+    let from = from.min(to);
+    display_width::expand_tabs(&line[from..to], display_width::column_to_cell(line, from))
+}
+
 fn resolve_file_path() -> std::path::PathBuf {
     let args: Vec<String> = std::env::args().collect();
 